@@ -29,4 +29,7 @@ pub trait AccountDB {
     async fn enable_webtoken(&self, account_id: AccountId) -> DBResult<()>;
     async fn disable_webtoken(&self, account_id: AccountId) -> DBResult<()>;
     async fn remove_webtokens(&self) -> DBResult<()>;
+
+    /// Persists pending writes, before the server exits
+    async fn flush(&self) -> DBResult<()>;
 }
@@ -1,10 +1,14 @@
+use std::convert::TryFrom;
 use std::{net::Ipv4Addr, time::SystemTime};
 
 use chrono::{Date, Utc};
+use serde::Deserialize;
 
 use super::db::{AccountId, UserId};
+use crate::codec::{DecodeFixed, EncodeFixed};
+use crate::error::PacketError;
+use crate::pincode::PincodeHash;
 
-pub const MAX_CHARS: u8 = 10;
 pub const PINCODE_LENGTH: usize = 4;
 pub const WEB_AUTH_TOKEN_LENGTH: usize = 16;
 
@@ -19,8 +23,9 @@ pub struct MmoAccount {
     pub email: String,
     /// Player group ID
     pub group_id: Option<usize>,
-    /// this accounts maximum character slots (maximum is limited to MAX_CHARS define in char server)
-    pub char_slots: u8,
+    /// this accounts character slots, premium ones included (`None` for the char server's normal
+    /// slots, limited to `MAX_CHARACTERS_PER_ACCOUNT`)
+    pub char_slots: Option<u8>,
     /// packet 0x006a value + 1 (0: compte OK)
     pub state: AccountState,
     /// number of successful auth attempts
@@ -31,22 +36,40 @@ pub struct MmoAccount {
     pub last_ip: Ipv4Addr,
     /// assigned birth date (format: YYYY-MM-DD)
     pub birth_date: Date<Utc>,
-    /// pincode system
-    pub pincode: [u8; PINCODE_LENGTH],
+    /// pincode system (hashed, `None` if no pincode has been set yet)
+    pub pincode: Option<PincodeHash>,
     /// last time of pincode change
     pub pincode_change: SystemTime,
+    /// incorrect pincodes entered in a row, across sessions
+    pub pincode_failures: u8,
     /// web authentication token (randomized on each login)
     pub web_auth_token: [u8; WEB_AUTH_TOKEN_LENGTH],
 }
 
 #[repr(u8)]
-#[derive(Debug, Clone, Copy, int_enum::IntEnum)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, int_enum::IntEnum)]
+#[serde(rename_all = "snake_case")]
 pub enum Sex {
     Male = 1,
     Female = 0,
     Server = 2,
 }
 
+impl EncodeFixed for Sex {
+    const SIZE: usize = 1;
+    fn encode(&self, buf: &mut [u8]) {
+        buf[0] = *self as u8;
+    }
+}
+
+impl DecodeFixed for Sex {
+    const SIZE: usize = 1;
+    fn decode(buf: &[u8]) -> Result<Self, PacketError> {
+        Sex::try_from(buf[0])
+            .map_err(|_| PacketError::InvalidRequest(format!("Invalid sex: {}", buf[0])))
+    }
+}
+
 // TODO: Remove derived Debug for passwords
 #[derive(Clone, Debug)]
 pub enum Password {
@@ -65,14 +88,15 @@ impl Default for MmoAccount {
             sex: Sex::Male,
             email: String::from("a@a.com"),
             group_id: None,
-            char_slots: MAX_CHARS,
+            char_slots: None,
             state: AccountState::Normal,
             login_count: 0,
             lastlogin: SystemTime::now(),
             last_ip: Ipv4Addr::LOCALHOST,
             birth_date: Utc::today(),
-            pincode: [0u8; PINCODE_LENGTH],
+            pincode: None,
             pincode_change: SystemTime::now(),
+            pincode_failures: 0,
             web_auth_token: [0u8; WEB_AUTH_TOKEN_LENGTH],
         }
     }
@@ -0,0 +1,141 @@
+use super::{Captcha, CaptchaProvider, IMAGE_HEIGHT, IMAGE_WIDTH};
+
+const FILE_HEADER_SIZE: usize = 14;
+const INFO_HEADER_SIZE: usize = 40;
+const PALETTE_SIZE: usize = 256 * 4;
+const PIXEL_OFFSET: usize = FILE_HEADER_SIZE + INFO_HEADER_SIZE + PALETTE_SIZE;
+
+const GLYPH_WIDTH: usize = 5;
+const GLYPH_HEIGHT: usize = 7;
+const GLYPH_SCALE: usize = 6;
+/// 5x7 glyphs for the digits 0-9, one byte per row with the leftmost pixel in bit 4
+const DIGITS: [[u8; GLYPH_HEIGHT]; 10] = [
+    [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E],
+    [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E],
+    [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F],
+    [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E],
+    [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02],
+    [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E],
+    [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E],
+    [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+    [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E],
+    [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C],
+];
+
+const BACKGROUND: u8 = 0;
+const TEXT: u8 = 1;
+const NOISE: u8 = 2;
+
+/// Built-in captcha provider, rendering a random number as an 8-bit BMP
+pub struct BitmapCaptcha {
+    length: usize,
+    noise: usize,
+}
+
+impl BitmapCaptcha {
+    pub fn new(length: usize, noise: usize) -> Self {
+        let max_length = IMAGE_WIDTH / ((GLYPH_WIDTH + 1) * GLYPH_SCALE);
+        Self {
+            length: length.clamp(1, max_length),
+            noise,
+        }
+    }
+
+    fn render(&self, answer: &str) -> Vec<u8> {
+        let mut pixels = vec![BACKGROUND; IMAGE_WIDTH * IMAGE_HEIGHT];
+
+        let glyph_width = (GLYPH_WIDTH + 1) * GLYPH_SCALE;
+        let margin_x = (IMAGE_WIDTH - answer.len() * glyph_width) / 2;
+        let max_offset_y = IMAGE_HEIGHT - GLYPH_HEIGHT * GLYPH_SCALE;
+        for (i, digit) in answer.bytes().enumerate() {
+            let glyph = &DIGITS[(digit - b'0') as usize];
+            // Jitter each digit vertically to make segmentation harder
+            let offset_x = margin_x + i * glyph_width + fastrand::usize(..GLYPH_SCALE);
+            let offset_y = fastrand::usize(..=max_offset_y);
+            for (row, bits) in glyph.iter().enumerate() {
+                for col in 0..GLYPH_WIDTH {
+                    if bits & (0x10 >> col) == 0 {
+                        continue;
+                    }
+                    for dy in 0..GLYPH_SCALE {
+                        for dx in 0..GLYPH_SCALE {
+                            let x = offset_x + col * GLYPH_SCALE + dx;
+                            let y = offset_y + row * GLYPH_SCALE + dy;
+                            pixels[y * IMAGE_WIDTH + x] = TEXT;
+                        }
+                    }
+                }
+            }
+        }
+
+        for _ in 0..self.noise {
+            let x = fastrand::usize(..IMAGE_WIDTH);
+            let y = fastrand::usize(..IMAGE_HEIGHT);
+            pixels[y * IMAGE_WIDTH + x] = NOISE;
+        }
+
+        encode_bmp(&pixels)
+    }
+}
+
+impl Default for BitmapCaptcha {
+    fn default() -> Self {
+        Self::new(5, 1500)
+    }
+}
+
+impl CaptchaProvider for BitmapCaptcha {
+    fn generate(&self) -> Captcha {
+        let answer: String = (0..self.length)
+            .map(|_| char::from(b'0' + fastrand::u8(..10)))
+            .collect();
+        Captcha {
+            image: self.render(&answer),
+            answer,
+        }
+    }
+}
+
+/// Encodes top-down rows of palette indices as a bottom-up 8-bit BMP
+fn encode_bmp(pixels: &[u8]) -> Vec<u8> {
+    // Rows are already 4-byte aligned, as the width is a multiple of 4
+    let image_size = IMAGE_WIDTH * IMAGE_HEIGHT;
+    let file_size = PIXEL_OFFSET + image_size;
+    let mut bmp = Vec::with_capacity(file_size);
+
+    // File header
+    bmp.extend_from_slice(b"BM");
+    bmp.extend_from_slice(&(file_size as u32).to_le_bytes());
+    bmp.extend_from_slice(&0u32.to_le_bytes());
+    bmp.extend_from_slice(&(PIXEL_OFFSET as u32).to_le_bytes());
+
+    // Info header
+    bmp.extend_from_slice(&(INFO_HEADER_SIZE as u32).to_le_bytes());
+    bmp.extend_from_slice(&(IMAGE_WIDTH as i32).to_le_bytes());
+    bmp.extend_from_slice(&(IMAGE_HEIGHT as i32).to_le_bytes());
+    bmp.extend_from_slice(&1u16.to_le_bytes());
+    bmp.extend_from_slice(&8u16.to_le_bytes());
+    bmp.extend_from_slice(&0u32.to_le_bytes());
+    bmp.extend_from_slice(&(image_size as u32).to_le_bytes());
+    bmp.extend_from_slice(&2835i32.to_le_bytes());
+    bmp.extend_from_slice(&2835i32.to_le_bytes());
+    bmp.extend_from_slice(&256u32.to_le_bytes());
+    bmp.extend_from_slice(&0u32.to_le_bytes());
+
+    // Palette, stored as BGRA
+    for index in 0..=255u8 {
+        let color = match index {
+            BACKGROUND => [0xFF, 0xFF, 0xFF],
+            TEXT => [0x20, 0x20, 0x20],
+            NOISE => [0x80, 0x80, 0x80],
+            _ => [0x00, 0x00, 0x00],
+        };
+        bmp.extend_from_slice(&color);
+        bmp.push(0);
+    }
+
+    for row in pixels.chunks(IMAGE_WIDTH).rev() {
+        bmp.extend_from_slice(row);
+    }
+    bmp
+}
@@ -0,0 +1,27 @@
+mod bitmap;
+
+pub use bitmap::BitmapCaptcha;
+
+/// Width of the captcha image displayed by the client
+pub const IMAGE_WIDTH: usize = 220;
+/// Height of the captcha image displayed by the client
+pub const IMAGE_HEIGHT: usize = 90;
+/// Maximum length of a captcha answer sent by the client
+pub const ANSWER_LENGTH: usize = 24;
+
+#[derive(Clone, Debug)]
+pub struct Captcha {
+    /// Image shown to the client, as a BMP file
+    pub image: Vec<u8>,
+    pub answer: String,
+}
+
+impl Captcha {
+    pub fn is_solved_by(&self, answer: &str) -> bool {
+        self.answer.eq_ignore_ascii_case(answer.trim())
+    }
+}
+
+pub trait CaptchaProvider {
+    fn generate(&self) -> Captcha;
+}
@@ -0,0 +1,266 @@
+//! Recording of the raw frames a server exchanges with a client, to reproduce issues offline.
+//!
+//! A capture starts with a header identifying the server & its packet version, followed by a
+//! record per frame: the timestamp in microseconds since the UNIX epoch, the direction, the
+//! length & the raw bytes. Integers are little-endian, like the packets themselves.
+
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read, Write};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use async_codec::{Decode, DecodeResult, Encode, EncodeResult};
+use tracing::{debug, warn};
+
+use crate::config::CaptureConfig;
+use crate::packet_version::PacketVersion;
+
+const MAGIC: &[u8; 4] = b"RCAP";
+const FORMAT_VERSION: u8 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ServerKind {
+    Login,
+    Character,
+}
+
+impl ServerKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Login => "login",
+            Self::Character => "char",
+        }
+    }
+}
+
+/// Direction of a frame, as seen by the server
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Inbound,
+    Outbound,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct CaptureHeader {
+    pub server: ServerKind,
+    pub packet_version: PacketVersion,
+}
+
+#[derive(Debug, Clone)]
+pub struct CapturedFrame {
+    pub timestamp: SystemTime,
+    pub direction: Direction,
+    pub data: Vec<u8>,
+}
+
+fn invalid_data(reason: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason.to_string())
+}
+
+pub struct CaptureWriter {
+    file: File,
+}
+
+impl CaptureWriter {
+    pub fn create(path: impl AsRef<Path>, header: CaptureHeader) -> io::Result<Self> {
+        let mut file = File::create(path)?;
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(FORMAT_VERSION);
+        bytes.push(match header.server {
+            ServerKind::Login => 0,
+            ServerKind::Character => 1,
+        });
+        bytes.extend_from_slice(&header.packet_version.date().to_le_bytes());
+        file.write_all(&bytes)?;
+        Ok(Self { file })
+    }
+
+    /// Creates a capture for a newly connected client in the configured directory
+    pub fn for_session(
+        config: &CaptureConfig,
+        header: CaptureHeader,
+        peer: SocketAddr,
+    ) -> io::Result<Self> {
+        fs::create_dir_all(&config.directory)?;
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        let path = PathBuf::from(&config.directory).join(format!(
+            "{}-{}-{}_{}.cap",
+            header.server.name(),
+            millis,
+            peer.ip(),
+            peer.port()
+        ));
+        debug!(path = %path.display(), "Recording session");
+        Self::create(path, header)
+    }
+
+    /// Appends a frame, unbuffered so a capture is complete even if the server crashes
+    pub fn write(&mut self, direction: Direction, data: &[u8]) -> io::Result<()> {
+        let micros = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_micros() as u64;
+        let mut bytes = Vec::with_capacity(13 + data.len());
+        bytes.extend_from_slice(&micros.to_le_bytes());
+        bytes.push(match direction {
+            Direction::Inbound => 0,
+            Direction::Outbound => 1,
+        });
+        bytes.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(data);
+        self.file.write_all(&bytes)
+    }
+}
+
+/// Iterates over the frames of a capture
+pub struct CaptureReader<R> {
+    reader: R,
+    header: CaptureHeader,
+}
+
+impl CaptureReader<BufReader<File>> {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::new(BufReader::new(File::open(path)?))
+    }
+}
+
+impl<R: Read> CaptureReader<R> {
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let mut bytes = [0u8; 10];
+        reader.read_exact(&mut bytes)?;
+        if &bytes[..4] != MAGIC {
+            return Err(invalid_data("Not a capture"));
+        }
+        if bytes[4] != FORMAT_VERSION {
+            return Err(invalid_data("Unsupported capture format"));
+        }
+        let server = match bytes[5] {
+            0 => ServerKind::Login,
+            1 => ServerKind::Character,
+            _ => return Err(invalid_data("Unknown server kind")),
+        };
+        let mut date = [0u8; 4];
+        date.copy_from_slice(&bytes[6..10]);
+        let header = CaptureHeader {
+            server,
+            packet_version: PacketVersion::new(u32::from_le_bytes(date)),
+        };
+        Ok(Self { reader, header })
+    }
+
+    pub fn header(&self) -> CaptureHeader {
+        self.header
+    }
+
+    fn read_frame(&mut self) -> io::Result<Option<CapturedFrame>> {
+        let mut bytes = [0u8; 13];
+        // A capture may end anywhere, e.g. when the server was killed
+        match self.reader.read_exact(&mut bytes) {
+            Ok(()) => {}
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(err) => return Err(err),
+        }
+        let mut micros = [0u8; 8];
+        micros.copy_from_slice(&bytes[..8]);
+        let direction = match bytes[8] {
+            0 => Direction::Inbound,
+            1 => Direction::Outbound,
+            _ => return Err(invalid_data("Unknown frame direction")),
+        };
+        let mut length = [0u8; 4];
+        length.copy_from_slice(&bytes[9..13]);
+        let mut data = vec![0u8; u32::from_le_bytes(length) as usize];
+        match self.reader.read_exact(&mut data) {
+            Ok(()) => {}
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(err) => return Err(err),
+        }
+        Ok(Some(CapturedFrame {
+            timestamp: UNIX_EPOCH + Duration::from_micros(u64::from_le_bytes(micros)),
+            direction,
+            data,
+        }))
+    }
+}
+
+impl<R: Read> Iterator for CaptureReader<R> {
+    type Item = io::Result<CapturedFrame>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_frame().transpose()
+    }
+}
+
+/// Wraps a server's codec, recording the bytes of every frame it decodes or encodes
+pub struct RecordingCodec<C> {
+    inner: C,
+    writer: Option<CaptureWriter>,
+}
+
+impl<C> RecordingCodec<C> {
+    /// Records nothing without a writer
+    pub fn new(inner: C, writer: Option<CaptureWriter>) -> Self {
+        Self { inner, writer }
+    }
+
+    /// Records the session if enabled, or nothing if the capture can't be created
+    pub fn for_session(
+        inner: C,
+        config: &CaptureConfig,
+        header: CaptureHeader,
+        peer: SocketAddr,
+    ) -> Self {
+        let writer = if config.enabled {
+            CaptureWriter::for_session(config, header, peer)
+                .map_err(|err| warn!(%err, "Could not create capture"))
+                .ok()
+        } else {
+            None
+        };
+        Self::new(inner, writer)
+    }
+
+    fn record(&mut self, direction: Direction, data: &[u8]) {
+        if let Some(writer) = &mut self.writer {
+            if let Err(err) = writer.write(direction, data) {
+                warn!(%err, "Could not write capture, recording stopped");
+                self.writer = None;
+            }
+        }
+    }
+}
+
+impl<C: Decode> Decode for RecordingCodec<C> {
+    type Item = C::Item;
+    type Error = C::Error;
+
+    fn decode(&mut self, buffer: &mut [u8]) -> (usize, DecodeResult<Self::Item, Self::Error>) {
+        let (consumed, result) = self.inner.decode(buffer);
+        // Includes packets the codec skipped, so a replay sends exactly what the client did
+        if consumed > 0 {
+            self.record(Direction::Inbound, &buffer[..consumed]);
+        }
+        (consumed, result)
+    }
+}
+
+impl<C: Encode> Encode for RecordingCodec<C> {
+    type Item = C::Item;
+    type Error = C::Error;
+
+    fn encode(&mut self, item: &Self::Item, buf: &mut [u8]) -> EncodeResult<Self::Error> {
+        let result = self.inner.encode(item, buf);
+        if let EncodeResult::Ok(size) = &result {
+            self.record(Direction::Outbound, &buf[..*size]);
+        }
+        result
+    }
+
+    fn reset(&mut self) {
+        self.inner.reset()
+    }
+}
@@ -1,10 +1,12 @@
 use crate::account::db::AccountId;
 use crate::character::db::CharacterId;
+use crate::inventory::{EquipSlot, Item};
 use serde::Deserialize;
 use std::time::SystemTime;
 
 #[repr(u16)]
-#[derive(Debug, Clone, Copy, int_enum::IntEnum)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, int_enum::IntEnum)]
+#[serde(rename_all = "snake_case")]
 pub enum Class {
     Novice = 0,
     Swordman = 1,
@@ -164,7 +166,7 @@ pub enum Class {
     BabyStarEmperor2 = 4244,
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub struct Stats {
     pub str: u8,
     pub agi: u8,
@@ -178,17 +180,18 @@ pub struct Stats {
     pub max_sp: u16,
 }
 
-impl Default for Stats {
-    fn default() -> Self {
-        let hp = (40 * 101) / 100;
-        let sp = (11 * 101) / 100;
+impl Stats {
+    /// Creates level 1 stats, deriving HP from VIT & SP from INT
+    pub fn new(str: u8, agi: u8, vit: u8, int: u8, dex: u8, luk: u8) -> Self {
+        let hp = (40 * (100 + vit as u32)) / 100;
+        let sp = (11 * (100 + int as u16)) / 100;
         Self {
-            str: 1,
-            agi: 1,
-            vit: 1,
-            int: 1,
-            dex: 1,
-            luk: 1,
+            str,
+            agi,
+            vit,
+            int,
+            dex,
+            luk,
             hp,
             max_hp: hp,
             sp,
@@ -197,7 +200,13 @@ impl Default for Stats {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+impl Default for Stats {
+    fn default() -> Self {
+        Self::new(1, 1, 1, 1, 1, 1)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub struct Experience {
     pub base_level: u16,
     pub job_level: u16,
@@ -220,23 +229,36 @@ impl Default for Experience {
     }
 }
 
-#[derive(Debug, Clone, Copy, Default)]
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
 pub struct Currency {
     pub zeny: u32,
     pub fame: i32,
 }
 
-#[derive(Debug, Clone, Copy, Default)]
+#[derive(Debug, PartialEq, Clone, Default)]
 pub struct Status {
     pub option: u32,
     /// Minutes the character will be muted
     pub manner: Option<u32>,
     pub karma: Option<u32>,
     pub delete_date: Option<SystemTime>,
-    pub unban_on: Option<SystemTime>,
+    pub ban: Option<Ban>,
+}
+
+/// Ban on a single character, the rest of the account can still be played
+#[derive(Debug, PartialEq, Clone)]
+pub struct Ban {
+    pub unban_on: SystemTime,
+    pub reason: String,
+}
+
+impl Ban {
+    pub fn is_active(&self) -> bool {
+        self.unban_on > SystemTime::now()
+    }
 }
 
-#[derive(Debug, Clone, Copy, Default)]
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
 pub struct Appearance {
     pub hair: u16,
     pub hair_color: u16,
@@ -245,7 +267,7 @@ pub struct Appearance {
     pub body: u16,
 }
 
-#[derive(Debug, Clone, Copy, Default)]
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
 pub struct Grouping {
     pub party_id: i32,
     pub guild_id: i32,
@@ -257,7 +279,7 @@ pub struct Grouping {
     pub clan_id: i32,
 }
 
-#[derive(Debug, Clone, Copy, Default)]
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
 pub struct MercenaryGuildRank {
     pub arch_faith: i32,
     pub arch_calls: i32,
@@ -267,7 +289,7 @@ pub struct MercenaryGuildRank {
     pub sword_calls: i32,
 }
 
-#[derive(Debug, Clone, Copy, Default)]
+#[derive(Debug, PartialEq, Clone, Copy, Default)]
 pub struct Equipment {
     pub weapon: u16,
     pub shield: u16,
@@ -277,8 +299,31 @@ pub struct Equipment {
     pub robe: u32,
 }
 
+impl Equipment {
+    /// Updates the view of the slot the item is equipped on, if it's visible on the character
+    pub fn equip(&mut self, item: &Item) {
+        let view = match item.view {
+            Some(view) => view,
+            None => return,
+        };
+        match item.equipped_slot {
+            Some(EquipSlot::Weapon) => self.weapon = view,
+            Some(EquipSlot::Shield) => self.shield = view,
+            Some(EquipSlot::HeadTop) | Some(EquipSlot::CostumeHeadTop) => self.head_top = view,
+            Some(EquipSlot::HeadMid) | Some(EquipSlot::CostumeHeadMid) => self.head_mid = view,
+            Some(EquipSlot::HeadBottom) | Some(EquipSlot::CostumeHeadBottom) => {
+                self.head_bottom = view
+            }
+            Some(EquipSlot::Garment) | Some(EquipSlot::CostumeGarment) => {
+                self.robe = u32::from(view)
+            }
+            _ => {}
+        }
+    }
+}
+
 #[repr(u16)]
-#[derive(Debug, Clone, Copy, int_enum::IntEnum)]
+#[derive(Debug, PartialEq, Clone, Copy, int_enum::IntEnum)]
 pub enum Weapon {
     Fist = 0,
     Dagger = 1,
@@ -313,7 +358,7 @@ pub enum Weapon {
     SwordAndAxe = 30,
 }
 
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, PartialEq, Default)]
 pub struct Relationship {
     pub partner_id: u32,
     pub father: u32,
@@ -322,14 +367,14 @@ pub struct Relationship {
     pub friends: Vec<Friend>,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct Friend {
     pub account_id: AccountId,
     pub char_id: CharacterId,
     pub name: String,
 }
 
-#[derive(Clone, Copy, Debug, Deserialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
 pub struct Location {
     pub last_location: Point,
     pub save: Option<Point>,
@@ -351,22 +396,25 @@ impl Default for Location {
     }
 }
 
-#[derive(Clone, Copy, Debug, Deserialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
 pub struct Point {
     pub map_id: u16,
     pub x: u16,
     pub y: u16,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
 pub struct Skill {
     pub id: u16,
     pub level: u8,
+    #[serde(default)]
     pub flag: SkillFlag,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum SkillFlag {
+    #[default]
     Permanent,
     Temporary,
     Plagiarized,
@@ -375,7 +423,7 @@ pub enum SkillFlag {
     ReplacedLevel0,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Hotkey {
     pub id: u32,
     pub level: u16,
@@ -383,17 +431,19 @@ pub struct Hotkey {
 }
 
 #[repr(u8)]
-#[derive(Clone, Copy, Debug, int_enum::IntEnum)]
+#[derive(Clone, Copy, Debug, PartialEq, int_enum::IntEnum)]
 pub enum HotkeyType {
     Item = 0,
     Skill = 1,
 }
 
-#[derive(Clone, Copy, Debug, Default)]
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
 pub struct Settings {
     pub show_equip: bool,
     pub allow_party: bool,
     pub rename: u16,
+    /// Number of times the character can still be moved to another slot
+    pub slot_moves: u16,
     pub font_id: u8,
     pub cash_shop_sent: bool,
     pub unique_item_counter: u32,
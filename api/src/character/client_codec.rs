@@ -0,0 +1,79 @@
+use std::sync::Arc;
+
+use async_codec::{Decode, DecodeResult, Encode, EncodeResult};
+
+use super::{request::Request, response::Response};
+use crate::error::PacketError;
+use crate::map::Maps;
+use crate::packet_table::PacketTable;
+use crate::packet_version::PacketVersion;
+use crate::utils::parse_word;
+
+/// Client side of the character protocol: encodes requests & decodes the server's responses
+pub struct CharacterClientCodec {
+    maps: Arc<Maps>,
+    version: PacketVersion,
+    packets: PacketTable,
+    /// The server answers `ConnectClient` with the bare account ID, without an opcode
+    awaiting_account_id: bool,
+}
+
+impl CharacterClientCodec {
+    pub fn new(maps: Arc<Maps>, version: PacketVersion) -> Self {
+        Self {
+            maps,
+            version,
+            packets: PacketTable::character_responses(version),
+            awaiting_account_id: false,
+        }
+    }
+}
+
+impl Decode for CharacterClientCodec {
+    type Item = Response;
+    type Error = PacketError;
+
+    fn decode(&mut self, buffer: &mut [u8]) -> (usize, DecodeResult<Self::Item, Self::Error>) {
+        if self.awaiting_account_id {
+            if buffer.len() < 4 {
+                return (0, DecodeResult::UnexpectedEnd);
+            }
+            self.awaiting_account_id = false;
+            let mut account_id = [0u8; 4];
+            account_id.copy_from_slice(&buffer[..4]);
+            let account_id = u32::from_le_bytes(account_id);
+            return (4, DecodeResult::Ok(Response::AccountConnected(account_id)));
+        }
+
+        let frame_length = match self.packets.frame_length(buffer) {
+            Ok(length) => length,
+            Err(PacketError::PacketIncomplete(_count)) => return (0, DecodeResult::UnexpectedEnd),
+            Err(err) => return (0, DecodeResult::Err(err)),
+        };
+        let command_code = parse_word(&buffer[..2]);
+        let result = Response::parse(
+            command_code,
+            &self.maps,
+            self.version,
+            &buffer[2..frame_length],
+        );
+        (frame_length, result.into())
+    }
+}
+
+impl Encode for CharacterClientCodec {
+    type Item = Request;
+    type Error = PacketError;
+
+    fn encode(&mut self, item: &Self::Item, buf: &mut [u8]) -> EncodeResult<Self::Error> {
+        match item.serialize(&self.maps, self.version, buf) {
+            Ok(size) => {
+                if let Request::ConnectClient(_) = item {
+                    self.awaiting_account_id = true;
+                }
+                EncodeResult::Ok(size)
+            }
+            Err(buffer_size) => EncodeResult::Overflow(buffer_size),
+        }
+    }
+}
@@ -1,7 +1,7 @@
 use std::convert::TryFrom;
 
 use async_codec::{Decode, DecodeResult, Encode, EncodeResult};
-use tracing::error;
+use tracing::{error, warn};
 
 use crate::{error::PacketError, utils::parse_word};
 
@@ -10,15 +10,23 @@ use super::{
     response::Response,
 };
 use crate::map::Maps;
+use crate::packet_table::PacketTable;
+use crate::packet_version::PacketVersion;
 use std::sync::Arc;
 
 pub struct CharacterCodec {
     maps: Arc<Maps>,
+    version: PacketVersion,
+    packets: PacketTable,
 }
 
 impl CharacterCodec {
-    pub fn new(maps: Arc<Maps>) -> Self {
-        Self { maps }
+    pub fn new(maps: Arc<Maps>, version: PacketVersion) -> Self {
+        Self {
+            maps,
+            version,
+            packets: PacketTable::character(version),
+        }
     }
 }
 
@@ -27,26 +35,46 @@ impl Decode for CharacterCodec {
     type Error = PacketError;
 
     fn decode(&mut self, buffer: &mut [u8]) -> (usize, DecodeResult<Self::Item, Self::Error>) {
-        if buffer.len() < 2 {
-            return (0, DecodeResult::UnexpectedEnd);
-        }
+        let mut skipped = 0;
+        loop {
+            let buffer = &buffer[skipped..];
+            let frame_length = match self.packets.frame_length(buffer) {
+                Ok(length) => length,
+                Err(PacketError::PacketIncomplete(_count)) => {
+                    return (skipped, DecodeResult::UnexpectedEnd)
+                }
+                Err(PacketError::InvalidCommand(command)) => {
+                    // Without its length, there's no telling where the next packet starts
+                    warn!(
+                        command,
+                        dropped = buffer.len(),
+                        "Dropping the received data after a packet of unknown length"
+                    );
+                    return (skipped + buffer.len(), DecodeResult::UnexpectedEnd);
+                }
+                Err(err) => {
+                    error!(%err, "Could not determine packet length");
+                    return (skipped, DecodeResult::Err(err));
+                }
+            };
 
-        // Parse command type
-        let command = match CharacterCommand::try_from(parse_word(&buffer[..2])) {
-            Ok(command) => command,
-            Err(err) => {
-                error!(%err);
-                return (0, DecodeResult::Err(err));
-            }
-        };
+            // Parse command type
+            let command = match CharacterCommand::try_from(parse_word(&buffer[..2])) {
+                Ok(command) => command,
+                Err(err) => {
+                    warn!(%err, frame_length, "Skipping unhandled packet");
+                    skipped += frame_length;
+                    continue;
+                }
+            };
 
-        match command.parse(&buffer[2..]) {
-            Ok((size, request)) => (size + 2, DecodeResult::Ok(request)),
-            Err(PacketError::PacketIncomplete(_count)) => (0, DecodeResult::UnexpectedEnd),
-            Err(err) => {
-                error!("Could not decode packet {:?}", command);
-                (0, DecodeResult::Err(err))
-            }
+            return match command.parse(&buffer[2..frame_length]) {
+                Ok(request) => (skipped + frame_length, DecodeResult::Ok(request)),
+                Err(err) => {
+                    error!("Could not decode packet {:?}", command);
+                    (skipped + frame_length, DecodeResult::Err(err))
+                }
+            };
         }
     }
 }
@@ -56,18 +84,9 @@ impl Encode for CharacterCodec {
     type Error = PacketError;
 
     fn encode(&mut self, item: &Self::Item, buf: &mut [u8]) -> EncodeResult<Self::Error> {
-        if buf.len() < 2 {
-            return EncodeResult::Overflow(2);
-        }
-        let offset = if let Some(command_code) = item.command_code() {
-            buf[..2].copy_from_slice(&command_code.to_le_bytes());
-            2
-        } else {
-            0
-        };
-        match item.serialize(&self.maps, &mut buf[offset..]) {
-            Ok(size) => EncodeResult::Ok(size + offset),
-            Err(buffer_size) => EncodeResult::Overflow(buffer_size + offset),
+        match item.serialize(&self.maps, self.version, buf) {
+            Ok(size) => EncodeResult::Ok(size),
+            Err(buffer_size) => EncodeResult::Overflow(buffer_size),
         }
     }
 }
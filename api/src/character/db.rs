@@ -1,6 +1,6 @@
 use crate::account::db::AccountId;
 
-use super::Character;
+use super::{Character, CharacterName};
 
 #[derive(Debug, thiserror::Error)]
 pub enum DBError {
@@ -8,6 +8,14 @@ pub enum DBError {
     NoSuchCharacter(CharacterId),
     #[error("No such slot {0}")]
     NoSuchSlot(u8),
+    #[error("No character named {0}")]
+    NoSuchName(CharacterName),
+    #[error("Name {0} is already taken")]
+    NameTaken(CharacterName),
+    #[error("Slot {0} is already in use")]
+    SlotOccupied(u8),
+    #[error("Character on slot {0} has no moves left")]
+    NoMovesLeft(u8),
 }
 
 pub type DBResult<T> = Result<T, DBError>;
@@ -16,10 +24,19 @@ pub type CharacterId = u32;
 #[async_trait::async_trait]
 pub trait CharacterDB {
     async fn init(&mut self) -> DBResult<()>;
-    async fn create(&self, account_id: AccountId) -> DBResult<CharacterId>;
+    /// Stores a new character under a fresh ID, claiming its name & slot in one go. Names are unique
+    /// regardless of case, slots are unique per account.
+    async fn create(&self, character: Character) -> DBResult<CharacterId>;
     async fn update(&self, character: &Character) -> DBResult<()>;
     async fn delete(&self, id: CharacterId) -> DBResult<()>;
     async fn get_by_account_id(&self, id: AccountId) -> DBResult<Vec<Character>>;
     async fn get_by_id(&self, id: CharacterId) -> DBResult<Character>;
     async fn get_by_slot(&self, account_id: AccountId, slot: u8) -> DBResult<Character>;
+    async fn get_by_name(&self, name: &CharacterName) -> DBResult<Character>;
+    /// Moves the character in slot `from` to slot `to`, swapping it with the character
+    /// occupying `to` if there is one, & spends one of the moved character's slot moves.
+    /// Both slots & the moves are updated as a single operation. Returns the moves left.
+    async fn move_slot(&self, account_id: AccountId, from: u8, to: u8) -> DBResult<u16>;
+    /// Persists pending writes, before the server exits
+    async fn flush(&self) -> DBResult<()>;
 }
@@ -1,26 +1,28 @@
-use std::cmp::min;
-use std::net::Ipv4Addr;
+use std::convert::TryFrom;
+use std::net::{Ipv4Addr, SocketAddrV4};
 
 use crate::account::mmo_account::Sex;
 use crate::character::attributes::{
     Appearance, Class, Currency, Equipment, Experience, Grouping, Location, MercenaryGuildRank,
     Relationship, Settings, Skill, Stats, Status,
 };
-use crate::codec::EncodeFixed;
 use crate::codec::RagnarokCodec;
+use crate::codec::{encode_fixed_string, ClientTcpReader, DecodeFixed, EncodeFixed, Packet};
 use crate::error::PacketError;
+use crate::map::{MapName, Maps};
 use crate::{account::db::AccountId, codec::EncodeStruct};
 pub use client::TcpClient;
+pub use client_codec::CharacterClientCodec;
 pub use codec::*;
 use db::CharacterId;
 pub use request::*;
 pub use response::*;
 pub use server::TcpServer;
-use std::convert::TryFrom;
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
 
 pub mod attributes;
 mod client;
+mod client_codec;
 mod codec;
 pub mod db;
 mod request;
@@ -29,6 +31,8 @@ mod server;
 
 /// The maximum number of characters per account
 pub const MAX_CHARACTERS_PER_ACCOUNT: usize = 12;
+/// The number of characters sent in each page of the character list
+pub const CHARACTERS_PER_PAGE: usize = 3;
 pub const DEFAULT_WALK_SPEED: u16 = 150;
 const OPTIONS_INCOMPATIBLE_WITH_WEAPON: u32 = 0x20
     | 0x80000
@@ -50,7 +54,7 @@ pub trait CharacterServer {
     fn info(&self) -> ServerInfo;
 }
 
-#[derive(Clone)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct ServerInfo {
     pub(crate) ip_addr: Ipv4Addr,
     pub(crate) port: u16,
@@ -60,7 +64,17 @@ pub struct ServerInfo {
     pub(crate) server_activity: ServerActivity,
 }
 
-#[derive(Clone, Copy, Debug)]
+impl ServerInfo {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn address(&self) -> SocketAddrV4 {
+        SocketAddrV4::new(self.ip_addr, self.port)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum ServerActivity {
     /// No status color
     Hidden,
@@ -86,7 +100,25 @@ impl Into<u16> for ServerActivity {
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+impl TryFrom<u16> for ServerActivity {
+    type Error = PacketError;
+
+    fn try_from(value: u16) -> Result<Self, Self::Error> {
+        match value {
+            4 => Ok(Self::Hidden),
+            0 => Ok(Self::Smooth),
+            1 => Ok(Self::Normal),
+            2 => Ok(Self::Busy),
+            3 => Ok(Self::Crowded),
+            unknown => Err(PacketError::InvalidRequest(format!(
+                "Invalid server activity {}",
+                unknown
+            ))),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum ServerType {
     Normal,
     Maintenance,
@@ -107,40 +139,101 @@ impl Into<u16> for ServerType {
     }
 }
 
-#[derive(Clone, Debug, Default)]
+impl TryFrom<u16> for ServerType {
+    type Error = PacketError;
+
+    fn try_from(value: u16) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Self::Normal),
+            1 => Ok(Self::Maintenance),
+            2 => Ok(Self::AdultOnly),
+            3 => Ok(Self::Paying),
+            4 => Ok(Self::F2P),
+            unknown => Err(PacketError::InvalidRequest(format!(
+                "Invalid server type {}",
+                unknown
+            ))),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
 pub struct CharacterName(String);
 
+impl CharacterName {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<String> for CharacterName {
+    fn from(name: String) -> Self {
+        Self(name)
+    }
+}
+
+impl std::fmt::Display for CharacterName {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
 impl EncodeFixed for CharacterName {
     const SIZE: usize = 24;
 
     fn encode(&self, buf: &mut [u8]) {
-        let name = self.0.as_bytes();
-        let len = min(Self::SIZE, name.len());
-        buf[..len].copy_from_slice(&name[..len]);
-        buf[len] = b'\0';
+        encode_fixed_string(&self.0, buf);
     }
 }
 
-impl TryFrom<&[u8]> for CharacterName {
-    type Error = PacketError;
+impl DecodeFixed for CharacterName {
+    const SIZE: usize = 24;
+
+    fn decode(buf: &[u8]) -> Result<Self, PacketError> {
+        ClientTcpReader::new(buf)
+            .string(<Self as DecodeFixed>::SIZE)
+            .map(CharacterName)
+    }
+}
+
+/// Character slots an account is allowed to use
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlotAllowance {
+    /// Slots every account can use
+    pub normal: u8,
+    /// Additional slots, e.g. for VIP accounts
+    pub premium: u8,
+}
 
-    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
-        if value.len() < Self::SIZE {
-            return Err(PacketError::PacketIncomplete(Self::SIZE));
+impl SlotAllowance {
+    /// Splits an account's total number of slots into normal & premium ones
+    pub fn new(normal: u8, total: u8) -> Self {
+        let total = total.min(MAX_CHARACTERS_PER_ACCOUNT as u8);
+        let normal = normal.min(total);
+        Self {
+            normal,
+            premium: total - normal,
         }
-        Ok(CharacterName(
-            String::from_utf8_lossy(
-                &value[..Self::SIZE]
-                    .split(|char| *char == b'\0')
-                    .next()
-                    .unwrap_or_default(),
-            )
-            .to_string(),
-        ))
+    }
+
+    /// Number of slots characters can be created in
+    pub fn total(&self) -> u8 {
+        self.normal + self.premium
+    }
+
+    pub fn contains(&self, slot: u8) -> bool {
+        slot < self.total()
+    }
+
+    /// Number of character list pages needed to show every slot
+    pub fn pages(&self) -> u32 {
+        (self.total() as u32)
+            .div_ceil(CHARACTERS_PER_PAGE as u32)
+            .max(1)
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct Character {
     pub id: CharacterId,
     pub account_id: AccountId,
@@ -163,8 +256,6 @@ pub struct Character {
 }
 
 impl Character {
-    pub const FRAME_SIZE: usize = 155;
-
     pub fn new(id: CharacterId, account_id: AccountId) -> Self {
         Self {
             id,
@@ -189,60 +280,201 @@ impl Character {
     }
 }
 
-impl EncodeStruct for Character {
-    fn encode<C: RagnarokCodec>(&self, codec: &mut C) {
-        codec.encode(&self.id);
-        codec.encode(&self.experience.base_exp);
-        codec.encode(&self.currency.zeny);
-        codec.encode(&self.experience.job_exp);
-        codec.encode(&(self.experience.job_level as u32));
-        codec.padding(8);
-        codec.encode(&(self.status.option & !0x40));
-        codec.encode(&self.status.karma.unwrap_or_default());
-        codec.encode(&self.status.manner.unwrap_or_default());
-        codec.encode(&self.experience.status_points);
-        codec.encode(&self.stats.hp);
-        codec.encode(&self.stats.max_hp);
-        codec.encode(&self.stats.sp);
-        codec.encode(&self.stats.max_sp);
-        codec.encode(&DEFAULT_WALK_SPEED);
-        codec.encode(&(self.class as u16));
-        codec.encode(&self.appearance.hair);
-        codec.encode(&self.appearance.body);
-        codec.encode(
-            &if self.status.option & OPTIONS_INCOMPATIBLE_WITH_WEAPON == 0 {
-                self.equipment.weapon
+/// A character as laid out in the character list, see `PacketVersion` for the field dates
+#[derive(Debug, Clone, Packet)]
+pub struct CharacterFrame {
+    pub id: CharacterId,
+    #[packet(since = 20170830)]
+    pub base_exp: u64,
+    #[packet(before = 20170830)]
+    pub legacy_base_exp: u32,
+    pub zeny: u32,
+    #[packet(since = 20170830)]
+    pub job_exp: u64,
+    #[packet(before = 20170830)]
+    pub legacy_job_exp: u32,
+    #[packet(width = 4)]
+    pub job_level: u16,
+    #[packet(padding = 8)]
+    pub option: u32,
+    pub karma: u32,
+    pub manner: u32,
+    pub status_points: u16,
+    pub hp: u32,
+    pub max_hp: u32,
+    pub sp: u16,
+    pub max_sp: u16,
+    pub walk_speed: u16,
+    pub class: u16,
+    pub hair: u16,
+    #[packet(since = 20141022)]
+    pub body: u16,
+    pub weapon: u16,
+    pub base_level: u16,
+    pub skill_points: u16,
+    pub head_bottom: u16,
+    pub shield: u16,
+    pub head_top: u16,
+    pub head_mid: u16,
+    pub hair_color: u16,
+    pub clothes_color: u16,
+    pub name: CharacterName,
+    pub str: u8,
+    pub agi: u8,
+    pub vit: u8,
+    pub int: u8,
+    pub dex: u8,
+    pub luk: u8,
+    pub slot: u16,
+    #[packet(since = 20061023)]
+    pub rename: u16,
+    #[packet(since = 20100720, string = 16)]
+    pub last_map: String,
+    #[packet(since = 20100803)]
+    pub delete_date: u32,
+    #[packet(since = 20110111)]
+    pub robe: u32,
+    #[packet(since = 20110928)]
+    pub slot_change: u32,
+    #[packet(since = 20111025)]
+    pub rename_count: u32,
+    #[packet(since = 20141016)]
+    pub sex: u8,
+}
+
+impl CharacterFrame {
+    pub fn new(character: &Character, maps: &Maps) -> Self {
+        let legacy_exp = |exp: u64| exp.min(i32::MAX as u64) as u32;
+        let delete_date = character
+            .status
+            .delete_date
+            .unwrap_or(SystemTime::UNIX_EPOCH)
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs() as u32;
+        Self {
+            id: character.id,
+            base_exp: character.experience.base_exp,
+            legacy_base_exp: legacy_exp(character.experience.base_exp),
+            zeny: character.currency.zeny,
+            job_exp: character.experience.job_exp,
+            legacy_job_exp: legacy_exp(character.experience.job_exp),
+            job_level: character.experience.job_level,
+            option: character.status.option & !0x40,
+            karma: character.status.karma.unwrap_or_default(),
+            manner: character.status.manner.unwrap_or_default(),
+            status_points: character.experience.status_points,
+            hp: character.stats.hp,
+            max_hp: character.stats.max_hp,
+            sp: character.stats.sp,
+            max_sp: character.stats.max_sp,
+            walk_speed: DEFAULT_WALK_SPEED,
+            class: character.class as u16,
+            hair: character.appearance.hair,
+            body: character.appearance.body,
+            weapon: if character.status.option & OPTIONS_INCOMPATIBLE_WITH_WEAPON == 0 {
+                character.equipment.weapon
             } else {
                 0
             },
-        );
-        codec.encode(&self.experience.base_level);
-        codec.encode(&self.experience.skill_points);
-        codec.encode(&self.equipment.head_bottom);
-        codec.encode(&self.equipment.shield);
-        codec.encode(&self.equipment.head_top);
-        codec.encode(&self.equipment.head_mid);
-        codec.encode(&self.appearance.hair_color);
-        codec.encode(&self.appearance.clothes_color);
-        codec.encode(&self.name);
-        codec.encode(&self.stats.str);
-        codec.encode(&self.stats.agi);
-        codec.encode(&self.stats.vit);
-        codec.encode(&self.stats.int);
-        codec.encode(&self.stats.dex);
-        codec.encode(&self.stats.luk);
-        codec.encode(&self.slot);
-        codec.encode(&if self.settings.rename > 0 { 0u16 } else { 1u16 });
-        codec.encode(
-            &codec
-                .maps()
-                .name(self.location.last_location.map_id)
-                .expect("invalid map name"),
-        );
-        codec.encode(&self.status.delete_date.unwrap_or(SystemTime::UNIX_EPOCH));
-        codec.encode(&(self.equipment.robe as u32));
-        codec.encode(&(CHARACTER_SLOT_MOVE_ENABLED as u32));
-        codec.encode(&(CHARACTER_RENAME_ENABLED as u32));
-        codec.encode(&(self.sex as u8));
+            base_level: character.experience.base_level,
+            skill_points: character.experience.skill_points,
+            head_bottom: character.equipment.head_bottom,
+            shield: character.equipment.shield,
+            head_top: character.equipment.head_top,
+            head_mid: character.equipment.head_mid,
+            hair_color: character.appearance.hair_color,
+            clothes_color: character.appearance.clothes_color,
+            name: character.name.clone(),
+            str: character.stats.str,
+            agi: character.stats.agi,
+            vit: character.stats.vit,
+            int: character.stats.int,
+            dex: character.stats.dex,
+            luk: character.stats.luk,
+            slot: character.slot,
+            rename: if character.settings.rename > 0 { 0 } else { 1 },
+            // The client expects the map's file name, see rAthena's `mapindex_getmapname_ext`
+            last_map: format!(
+                "{}.gat",
+                maps.name(character.location.last_location.map_id)
+                    .expect("invalid map name")
+                    .as_str()
+            ),
+            delete_date,
+            robe: character.equipment.robe,
+            slot_change: if CHARACTER_SLOT_MOVE_ENABLED {
+                character.settings.slot_moves as u32
+            } else {
+                0
+            },
+            rename_count: CHARACTER_RENAME_ENABLED as u32,
+            sex: character.sex as u8,
+        }
+    }
+
+    /// Rebuilds the character as seen by a client.
+    /// Fields the frame doesn't carry, e.g. the account ID, are left at their defaults.
+    pub fn to_character(&self, maps: &Maps) -> Result<Character, PacketError> {
+        let class = Class::try_from(self.class)
+            .map_err(|_| PacketError::InvalidRequest(format!("Invalid class {}", self.class)))?;
+        let sex = Sex::try_from(self.sex)
+            .map_err(|_| PacketError::InvalidRequest(format!("Invalid sex {}", self.sex)))?;
+        let mut character = Character::new(self.id, 0);
+        character.slot = self.slot;
+        character.sex = sex;
+        character.name = self.name.clone();
+        character.class = class;
+        // Only one of the experience layouts is present
+        character.experience.base_exp = self.base_exp.max(self.legacy_base_exp as u64);
+        character.experience.job_exp = self.job_exp.max(self.legacy_job_exp as u64);
+        character.experience.base_level = self.base_level;
+        character.experience.job_level = self.job_level;
+        character.experience.status_points = self.status_points;
+        character.experience.skill_points = self.skill_points;
+        character.currency.zeny = self.zeny;
+        character.stats.str = self.str;
+        character.stats.agi = self.agi;
+        character.stats.vit = self.vit;
+        character.stats.int = self.int;
+        character.stats.dex = self.dex;
+        character.stats.luk = self.luk;
+        character.stats.hp = self.hp;
+        character.stats.max_hp = self.max_hp;
+        character.stats.sp = self.sp;
+        character.stats.max_sp = self.max_sp;
+        character.status.option = self.option;
+        character.status.karma = Some(self.karma);
+        character.status.manner = Some(self.manner);
+        character.status.delete_date = if self.delete_date == 0 {
+            None
+        } else {
+            Some(SystemTime::UNIX_EPOCH + Duration::from_secs(self.delete_date as u64))
+        };
+        character.appearance.hair = self.hair;
+        character.appearance.hair_color = self.hair_color;
+        character.appearance.clothes_color = self.clothes_color;
+        character.appearance.body = self.body;
+        character.equipment.weapon = self.weapon;
+        character.equipment.shield = self.shield;
+        character.equipment.head_top = self.head_top;
+        character.equipment.head_mid = self.head_mid;
+        character.equipment.head_bottom = self.head_bottom;
+        character.equipment.robe = self.robe;
+        character.location.last_location.map_id = maps
+            .id(&MapName::from(
+                self.last_map.trim_end_matches(".gat").to_string(),
+            ))
+            .unwrap_or_default();
+        character.settings.rename = if self.rename == 0 { 1 } else { 0 };
+        character.settings.slot_moves = self.slot_change as u16;
+        Ok(character)
+    }
+}
+
+impl EncodeStruct for Character {
+    fn encode<C: RagnarokCodec>(&self, codec: &mut C) {
+        let frame = CharacterFrame::new(self, codec.maps());
+        codec.encode_struct(&frame);
     }
 }
@@ -1,9 +1,17 @@
 use std::convert::TryFrom;
 
+use crate::captcha;
 use crate::character::attributes::{Appearance, Class, Stats};
 use crate::character::CharacterName;
-use crate::utils::parse_word;
-use crate::{account::mmo_account::Sex, error::PacketError, utils::parse_long};
+use crate::codec::{encode_string, ClientTcpCodec, ClientTcpReader, Packet, RagnarokCodec};
+use crate::map::Maps;
+use crate::packet_version::PacketVersion;
+use crate::pincode::Pincode;
+use crate::{
+    account::{db::AccountId, mmo_account::Sex},
+    error::PacketError,
+};
+use serde::Deserialize;
 use tracing::error;
 
 #[derive(Debug, Copy, Clone)]
@@ -11,7 +19,12 @@ pub enum CharacterCommand {
     ConnectClient,
     ListCharacters,
     SelectCharacter,
-    CreateCharacter,
+    /// Creation with allocated stats, used by clients before 2012-03-07
+    CreateCharacterV1,
+    /// Creation without stats
+    CreateCharacterV2,
+    /// Creation with starting job & sex, used by clients from 2015-10-01
+    CreateCharacterV3,
     DeleteCharacter,
     RequestCharacterDeletion,
     AcceptCharacterDeletion,
@@ -33,7 +46,9 @@ impl TryFrom<u16> for CharacterCommand {
     fn try_from(value: u16) -> Result<Self, Self::Error> {
         match value {
             0x65 => Ok(CharacterCommand::ConnectClient),
-            0xa39 | 0x970 | 0x67 => Ok(CharacterCommand::CreateCharacter),
+            0x67 => Ok(CharacterCommand::CreateCharacterV1),
+            0x970 => Ok(CharacterCommand::CreateCharacterV2),
+            0xa39 => Ok(CharacterCommand::CreateCharacterV3),
             0x66 => Ok(CharacterCommand::SelectCharacter),
             0x9a1 => Ok(CharacterCommand::ListCharacters),
             0x68 | 0x1fb => Ok(CharacterCommand::DeleteCharacter),
@@ -55,115 +70,346 @@ impl TryFrom<u16> for CharacterCommand {
 }
 
 impl CharacterCommand {
-    pub fn parse(&self, buf: &[u8]) -> Result<(usize, Request), PacketError> {
+    /// Parses the packet's payload, i.e. everything after the opcode
+    pub fn parse(&self, buf: &[u8]) -> Result<Request, PacketError> {
+        let mut reader = ClientTcpReader::new(buf);
         match self {
-            Self::ConnectClient => {
-                if buf.len() >= 15 {
-                    let account_info = AccountInfo {
-                        account_id: parse_long(&buf[..4]),
-                        authentication_code: parse_long(&buf[4..8]),
-                        user_level: parse_long(&buf[8..12]),
-                        sex: Sex::try_from(buf[14]).map_err(|_| {
-                            error!("Invalid sex: {}", buf[14]);
-                            PacketError::InvalidRequest("Invalid sex".to_string())
-                        })?,
-                    };
-                    Ok((15, Request::ConnectClient(account_info)))
-                } else {
-                    Err(PacketError::PacketIncomplete(15 - buf.len()))
-                }
+            Self::ConnectClient => Ok(Request::ConnectClient(reader.decode_struct()?)),
+            Self::ListCharacters => Ok(Request::ListCharacters),
+            Self::SelectCharacter => Ok(Request::SelectCharacter {
+                slot: reader.decode()?,
+            }),
+            Self::CreateCharacterV1 => {
+                let name = reader.decode()?;
+                let stats = StatAllocation {
+                    str: reader.decode()?,
+                    agi: reader.decode()?,
+                    vit: reader.decode()?,
+                    int: reader.decode()?,
+                    dex: reader.decode()?,
+                    luk: reader.decode()?,
+                };
+                let slot = reader.decode()?;
+                let hair_color = reader.decode()?;
+                let hair = reader.decode()?;
+                Ok(Request::CreateCharacter(NewCharacter {
+                    name,
+                    slot,
+                    stats: Some(stats),
+                    appearance: Appearance {
+                        hair,
+                        hair_color,
+                        ..Default::default()
+                    },
+                    class: Class::Novice,
+                    sex: None,
+                }))
             }
-            Self::ListCharacters => Ok((2, Request::ListCharacters)),
-            Self::SelectCharacter => {
-                if buf.len() >= 1 {
-                    Ok((1, Request::SelectCharacter { slot: buf[0] }))
-                } else {
-                    Err(PacketError::PacketIncomplete(1 - buf.len()))
-                }
+            Self::CreateCharacterV2 => {
+                let name = reader.decode()?;
+                let slot = reader.decode()?;
+                let hair_color = reader.decode()?;
+                let hair = reader.decode()?;
+                Ok(Request::CreateCharacter(NewCharacter {
+                    name,
+                    slot,
+                    stats: None,
+                    appearance: Appearance {
+                        hair,
+                        hair_color,
+                        ..Default::default()
+                    },
+                    class: Class::Novice,
+                    sex: None,
+                }))
             }
-            Self::CreateCharacter => {
-                if buf.len() >= 34 {
-                    let new_character = NewCharacter {
-                        name: CharacterName::try_from(buf)?,
-                        slot: buf[24],
-                        stats: Default::default(),
-                        appearance: Appearance {
-                            hair: parse_word(&buf[27..29]),
-                            hair_color: parse_word(&buf[25..27]),
-                            ..Default::default()
-                        },
-                        class: Class::try_from(parse_word(&buf[29..31])).map_err(|err| {
-                            error!(%err, "Could not parse class");
-                            PacketError::InvalidRequest("Invalid class".to_string())
-                        })?,
-                        sex: Sex::try_from(buf[33]).map_err(|_| {
-                            error!("Invalid sex: {}", buf[33]);
-                            PacketError::InvalidRequest("Invalid sex".to_string())
-                        })?,
-                    };
-                    Ok((34, Request::CreateCharacter(new_character)))
-                } else {
-                    Err(PacketError::PacketIncomplete(34 - buf.len()))
-                }
+            Self::CreateCharacterV3 => {
+                let name = reader.decode()?;
+                let slot = reader.decode()?;
+                let hair_color = reader.decode()?;
+                let hair = reader.decode()?;
+                let class = decode_class(&mut reader)?;
+                reader.skip(2)?;
+                let sex = decode_sex(&mut reader)?;
+                Ok(Request::CreateCharacter(NewCharacter {
+                    name,
+                    slot,
+                    stats: None,
+                    appearance: Appearance {
+                        hair,
+                        hair_color,
+                        ..Default::default()
+                    },
+                    class,
+                    sex: Some(sex),
+                }))
             }
-            Self::DeleteCharacter => todo!("parse DeleteCharacter"),
-            Self::RequestCharacterDeletion => todo!("parse RequestCharacterDeletion"),
-            Self::AcceptCharacterDeletion => todo!("parse AcceptCharacterDeletion"),
-            Self::CancelCharacterDeletion2 => todo!("parse CancelCharacterDeletion2"),
-            Self::RenameCharacter => todo!("parse RenameCharacter"),
-            Self::RequestCaptcha => todo!("parse RequestCaptcha"),
-            Self::CheckCaptcha => todo!("parse CheckCaptcha"),
-            Self::MoveCharacterSlot => todo!("parse MoveCharacterSlot"),
-            Self::KeepAlive => {
-                if buf.len() >= 4 {
-                    Ok((4, Request::KeepAlive))
-                } else {
-                    Err(PacketError::PacketIncomplete(4 - buf.len()))
-                }
+            // TODO: Parse character deletion & renaming
+            Self::DeleteCharacter
+            | Self::RequestCharacterDeletion
+            | Self::AcceptCharacterDeletion
+            | Self::CancelCharacterDeletion2
+            | Self::RenameCharacter => Err(PacketError::UnsupportedRequest(format!("{:?}", self))),
+            Self::RequestCaptcha => {
+                reader.skip(2)?;
+                Ok(Request::RequestCaptcha {
+                    account_id: reader.decode()?,
+                })
+            }
+            Self::CheckCaptcha => {
+                reader.skip(2)?;
+                Ok(Request::CheckCaptcha {
+                    account_id: reader.decode()?,
+                    answer: reader.string(captcha::ANSWER_LENGTH)?,
+                })
+            }
+            Self::MoveCharacterSlot => {
+                // The trailing move count reported by the client is not trusted
+                let from = decode_slot(&mut reader)?;
+                let to = decode_slot(&mut reader)?;
+                Ok(Request::MoveCharacterSlot { from, to })
             }
-            Self::CheckPincode => todo!("parse CheckPincode"),
-            Self::RequestPincode => todo!("parse RequestPincode"),
-            Self::ChangePincode => todo!("parse ChangePincode"),
-            Self::NewPincode => todo!("parse NewPincode"),
+            Self::KeepAlive => Ok(Request::KeepAlive),
+            Self::CheckPincode => Ok(Request::CheckPincode {
+                account_id: reader.decode()?,
+                pincode: reader.decode()?,
+            }),
+            Self::RequestPincode => Ok(Request::RequestPincode {
+                account_id: reader.decode()?,
+            }),
+            Self::ChangePincode => Ok(Request::ChangePincode {
+                account_id: reader.decode()?,
+                old_pincode: reader.decode()?,
+                new_pincode: reader.decode()?,
+            }),
+            Self::NewPincode => Ok(Request::NewPincode {
+                account_id: reader.decode()?,
+                pincode: reader.decode()?,
+            }),
         }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub enum Request {
     ConnectClient(AccountInfo),
     ListCharacters,
-    SelectCharacter { slot: u8 },
+    SelectCharacter {
+        slot: u8,
+    },
     CreateCharacter(NewCharacter),
     DeleteCharacter,
     RequestCharacterDeletion,
     AcceptCharacterDeletion,
     CancelCharacterDeletion2,
     RenameCharacter,
-    RequestCaptcha,
-    CheckCaptcha,
-    MoveCharacterSlot,
+    RequestCaptcha {
+        account_id: AccountId,
+    },
+    CheckCaptcha {
+        account_id: AccountId,
+        answer: String,
+    },
+    MoveCharacterSlot {
+        from: u8,
+        to: u8,
+    },
     KeepAlive,
-    CheckPincode,
-    RequestPincode,
-    ChangePincode,
-    NewPincode,
+    CheckPincode {
+        account_id: AccountId,
+        pincode: Pincode,
+    },
+    RequestPincode {
+        account_id: AccountId,
+    },
+    ChangePincode {
+        account_id: AccountId,
+        old_pincode: Pincode,
+        new_pincode: Pincode,
+    },
+    NewPincode {
+        account_id: AccountId,
+        pincode: Pincode,
+    },
+}
+
+impl Request {
+    pub fn command_code(&self, version: PacketVersion) -> u16 {
+        match self {
+            Self::ConnectClient(_) => AccountInfo::OPCODE,
+            Self::ListCharacters => 0x9a1,
+            Self::SelectCharacter { .. } => 0x66,
+            Self::CreateCharacter(_) if version.has_creation_with_class() => 0xa39,
+            Self::CreateCharacter(_) if version.has_creation_without_stats() => 0x970,
+            Self::CreateCharacter(_) => 0x67,
+            Self::DeleteCharacter => 0x68,
+            Self::RequestCharacterDeletion => 0x827,
+            Self::AcceptCharacterDeletion => 0x829,
+            Self::CancelCharacterDeletion2 => 0x82b,
+            Self::RenameCharacter => 0x28d,
+            Self::RequestCaptcha { .. } => 0x7e5,
+            Self::CheckCaptcha { .. } => 0x7e7,
+            Self::MoveCharacterSlot { .. } => 0x8d4,
+            Self::KeepAlive => 0x187,
+            Self::CheckPincode { .. } => 0x8b8,
+            Self::RequestPincode { .. } => 0x8c5,
+            Self::ChangePincode { .. } => 0x8be,
+            Self::NewPincode { .. } => 0x8ba,
+        }
+    }
+
+    /// Encodes the packet as a client would, command code included.
+    /// Variants without data are sent zero-filled.
+    /// Fails with the required buffer size if `buf` is too small.
+    pub fn serialize(
+        &self,
+        maps: &Maps,
+        version: PacketVersion,
+        buf: &mut [u8],
+    ) -> Result<usize, usize> {
+        let mut codec = ClientTcpCodec::new(maps, version, buf);
+        codec.encode(&self.command_code(version));
+        match self {
+            Self::ConnectClient(info) => codec.encode_struct(info),
+            Self::ListCharacters => {}
+            Self::SelectCharacter { slot } => codec.encode(slot),
+            Self::CreateCharacter(character) => {
+                codec.encode(&character.name);
+                if !version.has_creation_without_stats() {
+                    let stats = character.stats.unwrap_or_default();
+                    stats.iter().for_each(|stat| codec.encode(&stat));
+                }
+                codec.encode(&character.slot);
+                codec.encode(&character.appearance.hair_color);
+                codec.encode(&character.appearance.hair);
+                if version.has_creation_with_class() {
+                    codec.encode(&(character.class as u16));
+                    codec.padding(2);
+                    codec.encode(&character.sex.unwrap_or(Sex::Female));
+                }
+            }
+            // Character ID & email
+            Self::DeleteCharacter => codec.padding(44),
+            Self::RequestCharacterDeletion => codec.padding(4),
+            Self::AcceptCharacterDeletion => codec.padding(10),
+            Self::CancelCharacterDeletion2 => codec.padding(4),
+            Self::RenameCharacter => codec.padding(32),
+            Self::RequestCaptcha { account_id } => {
+                codec.padding(2);
+                codec.encode(account_id);
+            }
+            Self::CheckCaptcha { account_id, answer } => {
+                codec.padding(2);
+                codec.encode(account_id);
+                encode_string(&mut codec, answer, captcha::ANSWER_LENGTH);
+            }
+            Self::MoveCharacterSlot { from, to } => {
+                codec.encode(&(*from as u16));
+                codec.encode(&(*to as u16));
+                // Moves left, as known to the client
+                codec.padding(2);
+            }
+            // Account ID
+            Self::KeepAlive => codec.padding(4),
+            Self::CheckPincode {
+                account_id,
+                pincode,
+            }
+            | Self::NewPincode {
+                account_id,
+                pincode,
+            } => {
+                codec.encode(account_id);
+                codec.encode(pincode);
+            }
+            Self::RequestPincode { account_id } => codec.encode(account_id),
+            Self::ChangePincode {
+                account_id,
+                old_pincode,
+                new_pincode,
+            } => {
+                codec.encode(account_id);
+                codec.encode(old_pincode);
+                codec.encode(new_pincode);
+            }
+        }
+        codec.finish()
+    }
+}
+
+fn decode_slot(reader: &mut ClientTcpReader) -> Result<u8, PacketError> {
+    let slot: u16 = reader.decode()?;
+    u8::try_from(slot).map_err(|_| {
+        error!("Invalid slot: {}", slot);
+        PacketError::InvalidRequest("Invalid slot".to_string())
+    })
+}
+
+fn decode_sex(reader: &mut ClientTcpReader) -> Result<Sex, PacketError> {
+    let sex: u8 = reader.decode()?;
+    Sex::try_from(sex).map_err(|_| {
+        error!("Invalid sex: {}", sex);
+        PacketError::InvalidRequest("Invalid sex".to_string())
+    })
 }
 
-#[derive(Debug, Clone, Copy)]
+fn decode_class(reader: &mut ClientTcpReader) -> Result<Class, PacketError> {
+    let class: u16 = reader.decode()?;
+    Class::try_from(class).map_err(|err| {
+        error!(%err, "Could not parse class");
+        PacketError::InvalidRequest("Invalid class".to_string())
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Packet)]
+#[packet(opcode = 0x0065)]
 pub struct AccountInfo {
     pub account_id: u32,
     pub authentication_code: u32,
     pub user_level: u32,
+    /// Preceded by the client type
+    #[packet(padding = 2)]
     pub sex: Sex,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct NewCharacter {
     pub name: CharacterName,
     pub slot: u8,
-    pub stats: Stats,
+    /// Only allocated by older clients, newer ones start with the default stats
+    pub stats: Option<StatAllocation>,
     pub appearance: Appearance,
     pub class: Class,
-    pub sex: Sex,
+    /// Only chosen by newer clients, older ones use the account's sex
+    pub sex: Option<Sex>,
+}
+
+/// Stat points distributed by the player when creating a character
+#[derive(Debug, PartialEq, Clone, Copy, Default, Deserialize)]
+pub struct StatAllocation {
+    pub str: u8,
+    pub agi: u8,
+    pub vit: u8,
+    pub int: u8,
+    pub dex: u8,
+    pub luk: u8,
+}
+
+impl StatAllocation {
+    pub fn iter(&self) -> impl Iterator<Item = u8> {
+        vec![self.str, self.agi, self.vit, self.int, self.dex, self.luk].into_iter()
+    }
+}
+
+impl From<StatAllocation> for Stats {
+    fn from(allocation: StatAllocation) -> Self {
+        Stats::new(
+            allocation.str,
+            allocation.agi,
+            allocation.vit,
+            allocation.int,
+            allocation.dex,
+            allocation.luk,
+        )
+    }
 }
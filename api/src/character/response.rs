@@ -1,21 +1,129 @@
 use crate::account::db::AccountId;
-use crate::codec::{ClientTcpCodec, RagnarokCodec};
+use crate::codec::{
+    encode_fixed_string, ClientTcpCodec, ClientTcpReader, DecodeFixed, EncodeFixed, RagnarokCodec,
+};
+use crate::error::PacketError;
+use crate::login::LoginAborted;
 use crate::map::Maps;
+use crate::packet_version::PacketVersion;
 use crate::{
-    character::{Character, MAX_CHARACTERS_PER_ACCOUNT},
-    pincode::PincodeInfo,
+    character::{
+        db::CharacterId, Character, CharacterFrame, SlotAllowance, MAX_CHARACTERS_PER_ACCOUNT,
+    },
+    pincode::{PincodeInfo, PincodeStatus},
 };
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
+use std::convert::TryFrom;
+use std::time::SystemTime;
+
+/// Reasons for refusing a character creation, as displayed by the client
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CreationRefused {
+    /// The name is already taken or reserved
+    NameExists,
+    /// Generic refusal
+    Denied,
+    Underaged,
+    /// The name contains disallowed characters
+    SymbolsForbidden,
+    /// The account may not use this slot
+    SlotNotEligible,
+    PremiumOnly,
+    InvalidName,
+}
 
+impl CreationRefused {
+    pub fn error_code(&self) -> u8 {
+        match self {
+            Self::NameExists => 0x00,
+            Self::Underaged => 0x01,
+            Self::SymbolsForbidden => 0x02,
+            Self::SlotNotEligible => 0x03,
+            Self::PremiumOnly => 0x0b,
+            Self::InvalidName => 0x0c,
+            Self::Denied => 0xff,
+        }
+    }
+
+    pub fn from_error_code(code: u8) -> Option<Self> {
+        match code {
+            0x00 => Some(Self::NameExists),
+            0x01 => Some(Self::Underaged),
+            0x02 => Some(Self::SymbolsForbidden),
+            0x03 => Some(Self::SlotNotEligible),
+            0x0b => Some(Self::PremiumOnly),
+            0x0c => Some(Self::InvalidName),
+            0xff => Some(Self::Denied),
+            _ => None,
+        }
+    }
+}
+
+const BAN_DATE_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// Entry of the banned character list
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BannedCharacter {
+    pub id: CharacterId,
+    pub unban_on: SystemTime,
+}
+
+impl EncodeFixed for BannedCharacter {
+    const SIZE: usize = 24;
+
+    fn encode(&self, buf: &mut [u8]) {
+        buf[..4].copy_from_slice(&self.id.to_le_bytes());
+        // The client displays the date as is
+        let date = DateTime::<Local>::from(self.unban_on)
+            .format(BAN_DATE_FORMAT)
+            .to_string();
+        encode_fixed_string(&date, &mut buf[4..]);
+    }
+}
+
+impl DecodeFixed for BannedCharacter {
+    const SIZE: usize = 24;
+
+    fn decode(buf: &[u8]) -> Result<Self, PacketError> {
+        let mut reader = ClientTcpReader::new(buf);
+        let id = reader.decode()?;
+        let date = reader.string(20)?;
+        let unban_on = NaiveDateTime::parse_from_str(&date, BAN_DATE_FORMAT)
+            .ok()
+            .and_then(|date| Local.from_local_datetime(&date).single())
+            .ok_or_else(|| PacketError::InvalidRequest(format!("Invalid ban date {}", date)))?;
+        Ok(Self {
+            id,
+            unban_on: unban_on.into(),
+        })
+    }
+}
+
+#[derive(Debug, PartialEq)]
 pub enum Response {
     AccountConnected(AccountId),
     Rejected,
-    CharacterSlotCount,
-    CharacterInfo(Vec<Character>),
+    CharacterSlotCount(SlotAllowance),
+    CharacterInfo {
+        characters: Vec<Character>,
+        slots: SlotAllowance,
+    },
     NewCharacterInfo(Character),
+    CreationRefused(CreationRefused),
     Characters(Vec<Character>),
     CharacterPagesAvailable(u32),
-    BannedCharacters,
+    BannedCharacters(Vec<BannedCharacter>),
     PincodeInfo(PincodeInfo),
+    SlotMoveResult {
+        moved: bool,
+        remaining_moves: u16,
+    },
+    CaptchaImage(Vec<u8>),
+    CaptchaResult {
+        solved: bool,
+    },
+    /// Disconnects the client, e.g. when the server shuts down
+    Aborted(LoginAborted),
 }
 
 impl Response {
@@ -23,103 +131,233 @@ impl Response {
         match self {
             Self::AccountConnected(_) => None,
             Self::Rejected => Some(0x6c),
-            Self::CharacterSlotCount => Some(0x82d),
-            Self::CharacterInfo(_) => Some(0x6b),
+            Self::CharacterSlotCount(_) => Some(0x82d),
+            Self::CharacterInfo { .. } => Some(0x6b),
             Self::NewCharacterInfo(_) => Some(0x6d),
+            Self::CreationRefused(_) => Some(0x6e),
             Self::Characters(_) => Some(0x99d),
             Self::CharacterPagesAvailable(_) => Some(0x9a0),
-            Self::BannedCharacters => Some(0x20d),
+            Self::BannedCharacters(_) => Some(0x20d),
             Self::PincodeInfo { .. } => Some(0x8b9),
+            Self::SlotMoveResult { .. } => Some(0x8d5),
+            Self::CaptchaImage(_) => Some(0x7e8),
+            Self::CaptchaResult { .. } => Some(0x7e9),
+            Self::Aborted(_) => Some(0x81),
         }
     }
 
-    pub fn serialize(&self, maps: &Maps, buf: &mut [u8]) -> Result<usize, usize> {
-        let mut codec = ClientTcpCodec::new(maps, buf);
+    /// Encodes the packet, command code included.
+    /// Fails with the required buffer size if `buf` is too small.
+    pub fn serialize(
+        &self,
+        maps: &Maps,
+        version: PacketVersion,
+        buf: &mut [u8],
+    ) -> Result<usize, usize> {
+        let mut codec = ClientTcpCodec::new(maps, version, buf);
+        if let Some(command_code) = self.command_code() {
+            codec.encode(&command_code);
+        }
         match self {
             Self::AccountConnected(account_id) => {
-                if codec.capacity() < 4 {
-                    return Err(4);
-                }
                 codec.encode(account_id);
             }
             Self::Rejected => {
-                if codec.capacity() < 1 {
-                    return Err(1);
-                }
                 codec.encode(&0u8);
             }
-            Self::CharacterSlotCount => {
-                if codec.capacity() < 27 {
-                    return Err(27);
-                }
-                codec.encode(&29u16);
-                codec.encode(&(MAX_CHARACTERS_PER_ACCOUNT as u8));
-                codec.encode(&(MAX_CHARACTERS_PER_ACCOUNT as u8));
+            Self::CharacterSlotCount(slots) => {
+                codec.variable_length();
+                codec.encode(&slots.normal);
+                codec.encode(&slots.premium);
+                // Billing slots
                 codec.encode(&0u8);
-                codec.encode(&(MAX_CHARACTERS_PER_ACCOUNT as u8));
+                // Producible slots
+                codec.encode(&slots.total());
+                // Valid slots
                 codec.encode(&(MAX_CHARACTERS_PER_ACCOUNT as u8));
                 codec.padding(20);
             }
-            Self::CharacterInfo(characters) => {
-                let frame_size = 25 + (characters.len() * Character::FRAME_SIZE);
-                if codec.capacity() < frame_size {
-                    return Err(frame_size);
+            Self::CharacterInfo { characters, slots } => {
+                codec.variable_length();
+                if version.has_slot_count_in_character_list() {
+                    codec.encode(&(MAX_CHARACTERS_PER_ACCOUNT as u8));
+                    // Premium slots start after the normal ones
+                    codec.encode(&slots.normal);
+                    codec.encode(&slots.total());
                 }
-                codec.encode(&(frame_size as u16 + 2));
-                codec.encode(&(MAX_CHARACTERS_PER_ACCOUNT as u8));
-                codec.encode(&(MAX_CHARACTERS_PER_ACCOUNT as u8));
-                codec.encode(&(MAX_CHARACTERS_PER_ACCOUNT as u8));
-                codec.padding(20);
                 // Unknown bytes
+                codec.padding(20);
                 characters
                     .iter()
                     .for_each(|character| codec.encode_struct(character));
             }
             Self::NewCharacterInfo(character) => {
-                let frame_size = 2 + Character::FRAME_SIZE;
-                if codec.capacity() < frame_size {
-                    return Err(frame_size);
-                }
                 codec.encode_struct(character);
             }
+            Self::CreationRefused(reason) => {
+                codec.encode(&reason.error_code());
+            }
             Self::Characters(characters) => {
-                let frame_size = 2
-                    + (characters.len() * Character::FRAME_SIZE)
-                    + if characters.len() == 3 { 4 } else { 0 };
-                if codec.capacity() < frame_size {
-                    return Err(frame_size);
-                }
-                codec.encode(&(frame_size as u16 + 2));
+                codec.variable_length();
                 characters
                     .iter()
                     .for_each(|character| codec.encode_struct(character));
-                if characters.len() == 3 {
-                    codec.encode(&self.command_code().unwrap());
-                    codec.encode(&4u16);
-                }
             }
             Self::CharacterPagesAvailable(count) => {
-                if codec.capacity() < 4 {
-                    return Err(4);
-                }
                 codec.encode(count);
             }
-            Self::BannedCharacters => {
-                if codec.capacity() < 2 {
-                    return Err(2);
-                }
-                // TODO: implement banned list
-                codec.encode(&4u16);
+            Self::BannedCharacters(characters) => {
+                codec.variable_length();
+                characters
+                    .iter()
+                    .for_each(|character| codec.encode(character));
             }
-            Self::PincodeInfo(PincodeInfo { status, account_id }) => {
-                if codec.capacity() < 10 {
-                    return Err(10);
-                }
-                codec.encode(&(fastrand::u16(..) as u32));
+            Self::PincodeInfo(PincodeInfo {
+                seed,
+                status,
+                account_id,
+            }) => {
+                codec.encode(seed);
                 codec.encode(account_id);
                 codec.encode(&(*status as u16));
             }
+            Self::SlotMoveResult {
+                moved,
+                remaining_moves,
+            } => {
+                codec.variable_length();
+                codec.encode(&if *moved { 0u16 } else { 1u16 });
+                codec.encode(remaining_moves);
+            }
+            Self::CaptchaImage(image) => {
+                codec.variable_length();
+                image.iter().for_each(|byte| codec.encode(byte));
+            }
+            Self::CaptchaResult { solved } => {
+                codec.variable_length();
+                codec.encode(&(*solved as u8));
+            }
+            Self::Aborted(reason) => {
+                codec.encode(&reason.error_code());
+            }
+        }
+        codec.finish()
+    }
+
+    /// Parses the packet's payload, i.e. everything after the opcode, as a client would.
+    /// `AccountConnected` has no opcode, so it's decoded by the client codec itself.
+    pub fn parse(
+        command_code: u16,
+        maps: &Maps,
+        version: PacketVersion,
+        buf: &[u8],
+    ) -> Result<Self, PacketError> {
+        let mut reader = ClientTcpReader::with_version(buf, version);
+        match command_code {
+            0x6c => Ok(Self::Rejected),
+            0x82d => {
+                reader.skip(2)?;
+                let normal = reader.decode()?;
+                let premium = reader.decode()?;
+                Ok(Self::CharacterSlotCount(SlotAllowance { normal, premium }))
+            }
+            0x6b => {
+                reader.skip(2)?;
+                let slots = if version.has_slot_count_in_character_list() {
+                    let _valid_slots: u8 = reader.decode()?;
+                    let normal = reader.decode()?;
+                    let total = reader.decode()?;
+                    SlotAllowance::new(normal, total)
+                } else {
+                    let max = MAX_CHARACTERS_PER_ACCOUNT as u8;
+                    SlotAllowance::new(max, max)
+                };
+                reader.skip(20)?;
+                Ok(Self::CharacterInfo {
+                    characters: decode_characters(&mut reader, maps)?,
+                    slots,
+                })
+            }
+            0x6d => {
+                let frame: CharacterFrame = reader.decode_struct()?;
+                Ok(Self::NewCharacterInfo(frame.to_character(maps)?))
+            }
+            0x6e => {
+                let code = reader.decode()?;
+                CreationRefused::from_error_code(code)
+                    .map(Self::CreationRefused)
+                    .ok_or_else(|| {
+                        PacketError::InvalidRequest(format!("Unknown creation refusal {}", code))
+                    })
+            }
+            0x99d => {
+                reader.skip(2)?;
+                Ok(Self::Characters(decode_characters(&mut reader, maps)?))
+            }
+            0x9a0 => Ok(Self::CharacterPagesAvailable(reader.decode()?)),
+            0x20d => {
+                reader.skip(2)?;
+                let mut characters = vec![];
+                while reader.remaining() > 0 {
+                    characters.push(reader.decode()?);
+                }
+                Ok(Self::BannedCharacters(characters))
+            }
+            0x8b9 => {
+                let seed = reader.decode()?;
+                let account_id = reader.decode()?;
+                let status: u16 = reader.decode()?;
+                let status = PincodeStatus::try_from(status).map_err(|_| {
+                    PacketError::InvalidRequest(format!("Invalid pincode status {}", status))
+                })?;
+                Ok(Self::PincodeInfo(PincodeInfo {
+                    seed,
+                    status,
+                    account_id,
+                }))
+            }
+            0x8d5 => {
+                reader.skip(2)?;
+                let failed: u16 = reader.decode()?;
+                Ok(Self::SlotMoveResult {
+                    moved: failed == 0,
+                    remaining_moves: reader.decode()?,
+                })
+            }
+            0x7e8 => {
+                reader.skip(2)?;
+                Ok(Self::CaptchaImage(
+                    reader.bytes(reader.remaining())?.to_vec(),
+                ))
+            }
+            0x7e9 => {
+                reader.skip(2)?;
+                let solved: u8 = reader.decode()?;
+                Ok(Self::CaptchaResult {
+                    solved: solved != 0,
+                })
+            }
+            0x81 => {
+                let code = reader.decode()?;
+                LoginAborted::from_error_code(code)
+                    .map(Self::Aborted)
+                    .ok_or_else(|| {
+                        PacketError::InvalidRequest(format!("Unknown login abort {}", code))
+                    })
+            }
+            unknown => Err(PacketError::InvalidCommand(unknown)),
         }
-        Ok(codec.len())
     }
 }
+
+fn decode_characters(
+    reader: &mut ClientTcpReader,
+    maps: &Maps,
+) -> Result<Vec<Character>, PacketError> {
+    let mut characters = vec![];
+    while reader.remaining() > 0 {
+        let frame: CharacterFrame = reader.decode_struct()?;
+        characters.push(frame.to_character(maps)?);
+    }
+    Ok(characters)
+}
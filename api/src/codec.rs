@@ -1,6 +1,11 @@
+use crate::error::PacketError;
 use crate::map::Maps;
+use crate::packet_version::PacketVersion;
+use std::convert::TryFrom;
 use std::time::SystemTime;
 
+pub use packet_derive::Packet;
+
 pub trait RagnarokCodec {
     fn encode<T: EncodeFixed>(&mut self, val: &T);
     fn encode_struct<T: EncodeStruct>(&mut self, val: &T);
@@ -8,6 +13,7 @@ pub trait RagnarokCodec {
 
     // Resource access
     fn maps(&self) -> &Maps;
+    fn version(&self) -> PacketVersion;
 }
 
 pub trait EncodeFixed {
@@ -19,6 +25,16 @@ pub trait EncodeStruct {
     fn encode<C: RagnarokCodec>(&self, codec: &mut C);
 }
 
+pub trait DecodeFixed: Sized {
+    const SIZE: usize;
+    /// Decodes the value from exactly `SIZE` bytes
+    fn decode(buf: &[u8]) -> Result<Self, PacketError>;
+}
+
+pub trait DecodeStruct: Sized {
+    fn decode(reader: &mut ClientTcpReader) -> Result<Self, PacketError>;
+}
+
 macro_rules! encode_fixed {
     ($ty:ty, $size:literal) => {
         impl EncodeFixed for $ty {
@@ -38,6 +54,27 @@ encode_fixed!(i32, 4);
 encode_fixed!(u64, 8);
 encode_fixed!(i64, 8);
 
+macro_rules! decode_fixed {
+    ($ty:ty, $size:literal) => {
+        impl DecodeFixed for $ty {
+            const SIZE: usize = $size;
+
+            fn decode(buf: &[u8]) -> Result<Self, PacketError> {
+                let mut bytes = [0u8; $size];
+                bytes.copy_from_slice(&buf[..$size]);
+                Ok(Self::from_le_bytes(bytes))
+            }
+        }
+    };
+}
+
+decode_fixed!(u16, 2);
+decode_fixed!(i16, 2);
+decode_fixed!(u32, 4);
+decode_fixed!(i32, 4);
+decode_fixed!(u64, 8);
+decode_fixed!(i64, 8);
+
 impl EncodeFixed for u8 {
     const SIZE: usize = 1;
     fn encode(&self, buf: &mut [u8]) {
@@ -45,6 +82,29 @@ impl EncodeFixed for u8 {
     }
 }
 
+impl DecodeFixed for u8 {
+    const SIZE: usize = 1;
+    fn decode(buf: &[u8]) -> Result<Self, PacketError> {
+        Ok(buf[0])
+    }
+}
+
+impl<const N: usize> EncodeFixed for [u8; N] {
+    const SIZE: usize = N;
+    fn encode(&self, buf: &mut [u8]) {
+        buf.copy_from_slice(self);
+    }
+}
+
+impl<const N: usize> DecodeFixed for [u8; N] {
+    const SIZE: usize = N;
+    fn decode(buf: &[u8]) -> Result<Self, PacketError> {
+        let mut bytes = [0u8; N];
+        bytes.copy_from_slice(&buf[..N]);
+        Ok(bytes)
+    }
+}
+
 impl EncodeFixed for SystemTime {
     const SIZE: usize = 4;
     fn encode(&self, buf: &mut [u8]) {
@@ -56,18 +116,25 @@ impl EncodeFixed for SystemTime {
     }
 }
 
+/// Encodes a server packet into a fixed buffer.
+/// Values that don't fit are skipped, but still counted, so `finish` can report the size required.
 pub struct ClientTcpCodec<'a> {
     buf: &'a mut [u8],
     cursor: usize,
+    /// Offset of the 2-byte length of a variable-length packet, filled in by `finish`
+    length_offset: Option<usize>,
     maps: &'a Maps,
+    version: PacketVersion,
 }
 
 impl<'a> ClientTcpCodec<'a> {
-    pub fn new(maps: &'a Maps, buf: &'a mut [u8]) -> Self {
+    pub fn new(maps: &'a Maps, version: PacketVersion, buf: &'a mut [u8]) -> Self {
         Self {
             buf,
             cursor: 0,
+            length_offset: None,
             maps,
+            version,
         }
     }
     pub fn capacity(&self) -> usize {
@@ -76,13 +143,42 @@ impl<'a> ClientTcpCodec<'a> {
     pub fn len(&self) -> usize {
         self.cursor
     }
+    pub fn is_empty(&self) -> bool {
+        self.cursor == 0
+    }
+
+    /// Reserves the length of a variable-length packet, which is known once the packet is encoded
+    pub fn variable_length(&mut self) {
+        self.length_offset = Some(self.cursor);
+        self.padding(2);
+    }
+
+    /// The size of the encoded packet, or the buffer size it requires if it didn't fit. A
+    /// variable-length packet longer than its 2-byte length can hold never fits.
+    pub fn finish(self) -> Result<usize, usize> {
+        if self.cursor > self.buf.len() {
+            return Err(self.cursor);
+        }
+        if let Some(offset) = self.length_offset {
+            let length = u16::try_from(self.cursor).map_err(|_| self.cursor)?;
+            length.encode(&mut self.buf[offset..offset + 2]);
+        }
+        Ok(self.cursor)
+    }
+
+    /// The part of the buffer for the next `count` bytes, if they fit
+    fn reserve(&mut self, count: usize) -> Option<&mut [u8]> {
+        let offset = self.cursor;
+        self.cursor += count;
+        self.buf.get_mut(offset..offset + count)
+    }
 }
 
 impl<'a> RagnarokCodec for ClientTcpCodec<'a> {
     fn encode<T: EncodeFixed>(&mut self, val: &T) {
-        let offset = self.cursor;
-        val.encode(&mut self.buf[offset..offset + T::SIZE]);
-        self.cursor += T::SIZE;
+        if let Some(buf) = self.reserve(T::SIZE) {
+            val.encode(buf);
+        }
     }
 
     fn encode_struct<T: EncodeStruct>(&mut self, val: &T) {
@@ -90,13 +186,96 @@ impl<'a> RagnarokCodec for ClientTcpCodec<'a> {
     }
 
     fn padding(&mut self, count: usize) {
-        for i in self.cursor..self.cursor + count {
-            self.buf[i] = 0;
+        if let Some(buf) = self.reserve(count) {
+            buf.iter_mut().for_each(|byte| *byte = 0);
         }
-        self.cursor += count;
     }
 
     fn maps(&self) -> &Maps {
         &self.maps
     }
+
+    fn version(&self) -> PacketVersion {
+        self.version
+    }
+}
+
+/// Writes `val` into `buf` as a NUL-terminated string, truncated to fit and padded with NUL bytes
+pub fn encode_fixed_string(val: &str, buf: &mut [u8]) {
+    let bytes = val.as_bytes();
+    let len = bytes.len().min(buf.len().saturating_sub(1));
+    buf[..len].copy_from_slice(&bytes[..len]);
+    buf[len..].iter_mut().for_each(|byte| *byte = 0);
+}
+
+/// Encodes `val` as a NUL-terminated string of `size` bytes, see `encode_fixed_string`
+pub fn encode_string<C: RagnarokCodec>(codec: &mut C, val: &str, size: usize) {
+    let bytes = val.as_bytes();
+    let len = bytes.len().min(size.saturating_sub(1));
+    for byte in &bytes[..len] {
+        codec.encode(byte);
+    }
+    codec.padding(size - len);
+}
+
+/// Reads values from a client packet, without ever reading past its end
+pub struct ClientTcpReader<'a> {
+    buf: &'a [u8],
+    cursor: usize,
+    version: PacketVersion,
+}
+
+impl<'a> ClientTcpReader<'a> {
+    pub fn new(buf: &'a [u8]) -> Self {
+        Self::with_version(buf, PacketVersion::default())
+    }
+
+    pub fn with_version(buf: &'a [u8], version: PacketVersion) -> Self {
+        Self {
+            buf,
+            cursor: 0,
+            version,
+        }
+    }
+
+    pub fn version(&self) -> PacketVersion {
+        self.version
+    }
+
+    /// Number of bytes left to read
+    pub fn remaining(&self) -> usize {
+        self.buf.len() - self.cursor
+    }
+
+    pub fn decode<T: DecodeFixed>(&mut self) -> Result<T, PacketError> {
+        T::decode(self.bytes(T::SIZE)?)
+    }
+
+    pub fn decode_struct<T: DecodeStruct>(&mut self) -> Result<T, PacketError> {
+        T::decode(self)
+    }
+
+    /// Reads the next `count` bytes as is
+    pub fn bytes(&mut self, count: usize) -> Result<&'a [u8], PacketError> {
+        if self.remaining() < count {
+            return Err(PacketError::PacketIncomplete(count - self.remaining()));
+        }
+        let bytes = &self.buf[self.cursor..self.cursor + count];
+        self.cursor += count;
+        Ok(bytes)
+    }
+
+    /// Reads a string padded with NUL bytes to `size` bytes
+    pub fn string(&mut self, size: usize) -> Result<String, PacketError> {
+        let bytes = self.bytes(size)?;
+        let bytes = bytes
+            .split(|byte| *byte == b'\0')
+            .next()
+            .unwrap_or_default();
+        Ok(String::from_utf8_lossy(bytes).to_string())
+    }
+
+    pub fn skip(&mut self, count: usize) -> Result<(), PacketError> {
+        self.bytes(count).map(|_| ())
+    }
 }
@@ -1,12 +1,16 @@
 use serde::Deserialize;
 
-use super::ServerConfig;
+use super::{CaptureConfig, ServerConfig, ShutdownConfig};
 
 #[derive(Deserialize, Debug)]
 pub struct Config {
     pub account_db: AccountDBConfig,
     pub login_server: ServerConfig,
     pub char_servers: Vec<ServerConfig>,
+    #[serde(default)]
+    pub capture: CaptureConfig,
+    #[serde(default)]
+    pub shutdown: ShutdownConfig,
 }
 
 #[derive(Deserialize, Debug)]
@@ -1,5 +1,7 @@
 use serde::Deserialize;
 
+use crate::packet_version::PacketVersion;
+
 pub mod login;
 
 #[derive(Deserialize, Debug, Clone)]
@@ -7,4 +9,41 @@ pub struct ServerConfig {
     pub name: String,
     pub address: String,
     pub port: u16,
+    /// Client version the server speaks to
+    #[serde(default)]
+    pub packet_version: PacketVersion,
+}
+
+/// Recording of every session's raw frames, see `capture`
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct CaptureConfig {
+    pub enabled: bool,
+    /// Directory a file is created in for each session
+    pub directory: String,
+}
+
+impl Default for CaptureConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            directory: "captures".to_string(),
+        }
+    }
+}
+
+/// Draining of the sessions once the server is told to shut down, see `shutdown`
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ShutdownConfig {
+    /// How long sessions get to finish before the server exits regardless
+    pub timeout_seconds: u64,
+}
+
+impl Default for ShutdownConfig {
+    fn default() -> Self {
+        Self {
+            timeout_seconds: 30,
+        }
+    }
 }
@@ -6,4 +6,7 @@ pub enum PacketError {
     PacketIncomplete(usize),
     #[error("Invalid request: {0}")]
     InvalidRequest(String),
+    /// A known packet the server can't parse yet
+    #[error("Unsupported request {0}")]
+    UnsupportedRequest(String),
 }
@@ -11,8 +11,40 @@ pub struct Item {
     pub amount: u16,
     #[serde(default = "default_is_identified")]
     pub identified: bool,
-    pub equipped_slot: Option<u8>,
+    pub equipped_slot: Option<EquipSlot>,
+    /// Sprite shown on the character while equipped, i.e. the weapon type for weapons.
+    /// Items without one, e.g. most armors, don't change the character's look.
+    #[serde(default)]
+    pub view: Option<u16>,
 }
 
 #[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub struct ItemId(u32);
+
+impl From<ItemId> for u32 {
+    fn from(id: ItemId) -> Self {
+        id.0
+    }
+}
+
+/// Equipment position an item is worn on
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EquipSlot {
+    HeadBottom = 0,
+    Weapon = 1,
+    Garment = 2,
+    AccessoryLeft = 3,
+    Armor = 4,
+    Shield = 5,
+    Shoes = 6,
+    AccessoryRight = 7,
+    HeadTop = 8,
+    HeadMid = 9,
+    CostumeHeadTop = 10,
+    CostumeHeadMid = 11,
+    CostumeHeadBottom = 12,
+    CostumeGarment = 13,
+    Ammo = 15,
+}
@@ -3,7 +3,7 @@ use serde::{Deserialize, Serialize};
 
 mod item;
 
-pub use item::{Item, ItemId};
+pub use item::{EquipSlot, Item, ItemId};
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Inventory {
@@ -1,4 +1,9 @@
+// Lets `#[derive(Packet)]` refer to `::api` from within this crate
+extern crate self as api;
+
 pub mod account;
+pub mod captcha;
+pub mod capture;
 pub mod character;
 pub mod character_server;
 pub mod codec;
@@ -8,5 +13,8 @@ pub mod inventory;
 pub mod login;
 pub mod map;
 pub mod map_server;
+pub mod packet_table;
+pub mod packet_version;
 pub mod pincode;
+pub mod shutdown;
 pub mod utils;
@@ -0,0 +1,50 @@
+use async_codec::{Decode, DecodeResult, Encode, EncodeResult};
+
+use super::{request::Request, response::Response};
+use crate::error::PacketError;
+use crate::packet_table::PacketTable;
+use crate::packet_version::PacketVersion;
+use crate::utils::parse_word;
+
+/// Client side of the login protocol: encodes requests & decodes the server's responses
+pub struct LoginClientCodec {
+    version: PacketVersion,
+    packets: PacketTable,
+}
+
+impl LoginClientCodec {
+    pub fn new(version: PacketVersion) -> Self {
+        Self {
+            version,
+            packets: PacketTable::login_responses(version),
+        }
+    }
+}
+
+impl Decode for LoginClientCodec {
+    type Item = Response;
+    type Error = PacketError;
+
+    fn decode(&mut self, buffer: &mut [u8]) -> (usize, DecodeResult<Self::Item, Self::Error>) {
+        let frame_length = match self.packets.frame_length(buffer) {
+            Ok(length) => length,
+            Err(PacketError::PacketIncomplete(_count)) => return (0, DecodeResult::UnexpectedEnd),
+            Err(err) => return (0, DecodeResult::Err(err)),
+        };
+        let command_code = parse_word(&buffer[..2]);
+        let result = Response::parse(command_code, self.version, &buffer[2..frame_length]);
+        (frame_length, result.into())
+    }
+}
+
+impl Encode for LoginClientCodec {
+    type Item = Request;
+    type Error = PacketError;
+
+    fn encode(&mut self, item: &Self::Item, buf: &mut [u8]) -> EncodeResult<Self::Error> {
+        match item.serialize(self.version, buf) {
+            Ok(size) => EncodeResult::Ok(size),
+            Err(buffer_size) => EncodeResult::Overflow(buffer_size),
+        }
+    }
+}
@@ -1,38 +1,72 @@
 use async_codec::{Decode, DecodeResult, Encode, EncodeResult};
 use std::convert::TryFrom;
-use tracing::error;
+use tracing::{error, warn};
 
 use super::request::{LoginCommand, Request};
 use super::{error::Error, response::Response};
+use crate::error::PacketError;
+use crate::packet_table::PacketTable;
+use crate::packet_version::PacketVersion;
+use crate::utils::parse_word;
 
-pub struct LoginCodec;
+pub struct LoginCodec {
+    version: PacketVersion,
+    packets: PacketTable,
+}
+
+impl LoginCodec {
+    pub fn new(version: PacketVersion) -> Self {
+        Self {
+            version,
+            packets: PacketTable::login(version),
+        }
+    }
+}
 
 impl Decode for LoginCodec {
     type Item = Request;
     type Error = Error;
 
     fn decode(&mut self, buffer: &mut [u8]) -> (usize, DecodeResult<Self::Item, Self::Error>) {
-        if buffer.len() < 2 {
-            return (0, DecodeResult::UnexpectedEnd);
-        }
+        let mut skipped = 0;
+        loop {
+            let buffer = &buffer[skipped..];
+            let frame_length = match self.packets.frame_length(buffer) {
+                Ok(length) => length,
+                Err(PacketError::PacketIncomplete(_count)) => {
+                    return (skipped, DecodeResult::UnexpectedEnd)
+                }
+                Err(PacketError::InvalidCommand(command)) => {
+                    // Without its length, there's no telling where the next packet starts
+                    warn!(
+                        command,
+                        dropped = buffer.len(),
+                        "Dropping the received data after a packet of unknown length"
+                    );
+                    return (skipped + buffer.len(), DecodeResult::UnexpectedEnd);
+                }
+                Err(err) => {
+                    error!(%err);
+                    return (
+                        skipped,
+                        DecodeResult::Err(Error::InvalidPacketSize(buffer.len())),
+                    );
+                }
+            };
+
+            // Parse command type
+            let command = match LoginCommand::try_from(parse_word(&buffer[..2])) {
+                Ok(command) => command,
+                Err(err) => {
+                    warn!(%err, frame_length, "Skipping unhandled packet");
+                    skipped += frame_length;
+                    continue;
+                }
+            };
 
-        // Parse command type
-        let mut command_buf = [0u8; 2];
-        command_buf.copy_from_slice(&buffer[..2]);
-        let command = match LoginCommand::try_from(u16::from_le_bytes(command_buf)) {
-            Ok(command) => command,
-            Err(err) => {
-                error!(%err);
-                return (0, DecodeResult::Err(err));
-            }
-        };
-
-        let (request_size, request) = match command.parse(&buffer[2..]) {
-            Ok((size, request)) => (size, request),
-            Err(Error::PacketIncomplete(_count)) => return (0, DecodeResult::UnexpectedEnd),
-            Err(err) => return (0, DecodeResult::Err(err)),
-        };
-        (request_size + 2, DecodeResult::Ok(request))
+            let result = command.parse(&buffer[2..frame_length]);
+            return (skipped + frame_length, result.into());
+        }
     }
 }
 
@@ -41,13 +75,9 @@ impl Encode for LoginCodec {
     type Error = Error;
 
     fn encode(&mut self, item: &Self::Item, buf: &mut [u8]) -> EncodeResult<Self::Error> {
-        if buf.len() < 2 {
-            return EncodeResult::Overflow(2);
-        }
-        buf[..2].copy_from_slice(&item.command_code().to_le_bytes());
-        match item.serialize(&mut buf[2..]) {
-            Ok(size) => EncodeResult::Ok(size + 2),
-            Err(buffer_size) => EncodeResult::Overflow(buffer_size + 2),
+        match item.serialize(self.version, buf) {
+            Ok(size) => EncodeResult::Ok(size),
+            Err(buffer_size) => EncodeResult::Overflow(buffer_size),
         }
     }
 }
@@ -1,3 +1,5 @@
+use crate::error::PacketError;
+
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     #[error("Invalid command {0}[{0:x}]")]
@@ -6,4 +8,19 @@ pub enum Error {
     InvalidPacketSize(usize),
     #[error("Packet incomplete - need {0} bytes")]
     PacketIncomplete(usize),
+    #[error("Invalid request: {0}")]
+    InvalidRequest(String),
+    #[error("Unsupported request {0}")]
+    UnsupportedRequest(String),
+}
+
+impl From<PacketError> for Error {
+    fn from(err: PacketError) -> Self {
+        match err {
+            PacketError::InvalidCommand(command) => Self::InvalidCommand(command),
+            PacketError::PacketIncomplete(count) => Self::PacketIncomplete(count),
+            PacketError::InvalidRequest(reason) => Self::InvalidRequest(reason),
+            PacketError::UnsupportedRequest(command) => Self::UnsupportedRequest(command),
+        }
+    }
 }
@@ -1,8 +1,11 @@
+pub use client_codec::LoginClientCodec;
 pub use codec::LoginCodec;
 pub use credentials::LoginCredentials;
-pub use request::Request;
+pub use error::Error;
+pub use request::{LoginCommand, Request};
 pub use response::*;
 
+mod client_codec;
 mod codec;
 mod credentials;
 mod error;
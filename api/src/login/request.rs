@@ -1,7 +1,11 @@
 use std::convert::TryFrom;
 
 use super::{error::Error, LoginCredentials};
+use crate::codec::{encode_string, ClientTcpCodec, ClientTcpReader, RagnarokCodec};
+use crate::map::Maps;
+use crate::packet_version::PacketVersion;
 
+#[derive(Debug, Copy, Clone)]
 pub enum LoginCommand {
     KeepAlive,
     UpdateClientHash,
@@ -40,85 +44,29 @@ impl TryFrom<u16> for LoginCommand {
 }
 
 impl LoginCommand {
-    pub fn parse(&self, buf: &[u8]) -> Result<(usize, Request), Error> {
+    /// Parses the packet's payload, i.e. everything after the opcode
+    pub fn parse(&self, buf: &[u8]) -> Result<Request, Error> {
+        let mut reader = ClientTcpReader::new(buf);
         match self {
-            Self::KeepAlive => {
-                if buf.len() >= 24 {
-                    Ok((24, Request::KeepAlive))
-                } else {
-                    Err(Error::PacketIncomplete(24 - buf.len()))
-                }
-            }
-            Self::UpdateClientHash => {
-                if buf.len() >= 16 {
-                    let mut hash = [0u8; 16];
-                    hash.copy_from_slice(&buf[..16]);
-                    Ok((16, Request::UpdateClientHash(hash)))
-                } else {
-                    Err(Error::PacketIncomplete(16 - buf.len()))
-                }
-            }
-            Self::ClientLoginRawPassV1 => {
-                if buf.len() >= 53 {
-                    let credentials = parse_cleartext_credentials(&buf);
-                    Ok((53, Request::ClientLogin(credentials)))
-                } else {
-                    Err(Error::PacketIncomplete(53 - buf.len()))
-                }
-            }
-            Self::ClientLoginRawPassV2 => {
-                if buf.len() >= 82 {
-                    let credentials = parse_cleartext_credentials(&buf);
-                    Ok((82, Request::ClientLogin(credentials)))
-                } else {
-                    Err(Error::PacketIncomplete(82 - buf.len()))
-                }
-            }
-            Self::ClientLoginRawPassV3 => {
-                if buf.len() >= 83 {
-                    let credentials = parse_cleartext_credentials(&buf);
-                    Ok((83, Request::ClientLogin(credentials)))
-                } else {
-                    Err(Error::PacketIncomplete(83 - buf.len()))
-                }
-            }
-            Self::ClientLoginHashedPassV1 => {
-                if buf.len() >= 45 {
-                    let credentials = parse_hashed_credentials(&buf);
-                    Ok((45, Request::ClientLogin(credentials)))
-                } else {
-                    Err(Error::PacketIncomplete(45 - buf.len()))
-                }
-            }
-            Self::ClientLoginHashedPassV2 => {
-                if buf.len() >= 46 {
-                    let credentials = parse_hashed_credentials(&buf);
-                    Ok((46, Request::ClientLogin(credentials)))
-                } else {
-                    Err(Error::PacketIncomplete(46 - buf.len()))
-                }
-            }
-            Self::ClientLoginHashedPassV3 => {
-                if buf.len() >= 58 {
-                    let credentials = parse_hashed_credentials(&buf);
-                    Ok((58, Request::ClientLogin(credentials)))
-                } else {
-                    Err(Error::PacketIncomplete(58 - buf.len()))
-                }
-            }
-            Self::ClientLoginHashedPassV4 => {
-                todo!("Parse OTPs");
+            Self::KeepAlive => Ok(Request::KeepAlive),
+            Self::UpdateClientHash => Ok(Request::UpdateClientHash(reader.decode()?)),
+            Self::ClientLoginRawPassV1
+            | Self::ClientLoginRawPassV2
+            | Self::ClientLoginRawPassV3 => {
+                let credentials = decode_cleartext_credentials(&mut reader)?;
+                Ok(Request::ClientLogin(credentials))
             }
-            Self::CreateSessionKey => {
-                // logclif_parse_reqkey
-                todo!("Parse CodeKey");
-            }
-            Self::OneTimePassLogin => {
-                todo!("Parse OneTimePassLogin");
-            }
-            Self::CharConnect => {
-                todo!("Parse CharConnect");
+            Self::ClientLoginHashedPassV1
+            | Self::ClientLoginHashedPassV2
+            | Self::ClientLoginHashedPassV3 => {
+                let credentials = decode_hashed_credentials(&mut reader)?;
+                Ok(Request::ClientLogin(credentials))
             }
+            // TODO: Parse OTPs & CodeKey (logclif_parse_reqkey)
+            Self::ClientLoginHashedPassV4
+            | Self::CreateSessionKey
+            | Self::OneTimePassLogin
+            | Self::CharConnect => Err(Error::UnsupportedRequest(format!("{:?}", self))),
         }
     }
 }
@@ -133,54 +81,96 @@ pub enum Request {
     ConnectChar,
 }
 
-fn parse_cleartext_credentials(data: &[u8]) -> LoginCredentials {
-    let mut username = [0u8; 24];
-    copy_zero_terminated_buffer(&mut username, &data[4..4 + 23 + 1]);
+impl Request {
+    pub fn command_code(&self) -> u16 {
+        match self {
+            Self::KeepAlive => 0x200,
+            Self::UpdateClientHash(_) => 0x204,
+            Self::ClientLogin(LoginCredentials::ClearText { .. }) => 0x64,
+            Self::ClientLogin(LoginCredentials::Hashed { .. }) => 0x1dd,
+            Self::ClientLogin(LoginCredentials::OTP { .. }) => 0x825,
+            Self::CodeKey => 0x1db,
+            Self::OneTimeToken => 0xacf,
+            Self::ConnectChar => 0x2710,
+        }
+    }
 
-    let mut password = [0u8; 24];
-    copy_zero_terminated_buffer(&mut password, &data[28..28 + 24]);
-    LoginCredentials::ClearText {
-        client_type: data[data.len() - 1],
-        username: String::from_utf8_lossy(
-            &username
-                .split(|char| *char == b'\0')
-                .next()
-                .unwrap_or_default(),
-        )
-        .to_string(),
-        password: String::from_utf8_lossy(
-            &password
-                .split(|char| *char == b'\0')
-                .next()
-                .unwrap_or_default(),
-        )
-        .to_string(),
+    /// Encodes the packet as a client would. Variants without data are sent zero-filled.
+    pub fn serialize(&self, version: PacketVersion, buf: &mut [u8]) -> Result<usize, usize> {
+        // Login packets don't refer to any map
+        let maps = Maps::default();
+        let mut codec = ClientTcpCodec::new(&maps, version, buf);
+        codec.encode(&self.command_code());
+        match self {
+            Self::KeepAlive => codec.padding(24),
+            Self::UpdateClientHash(hash) => codec.encode(hash),
+            // The client version isn't checked by the server
+            Self::ClientLogin(LoginCredentials::ClearText {
+                client_type,
+                username,
+                password,
+            }) => {
+                codec.padding(4);
+                encode_string(&mut codec, username, 24);
+                encode_string(&mut codec, password, 24);
+                codec.encode(client_type);
+            }
+            Self::ClientLogin(LoginCredentials::Hashed {
+                client_type,
+                username,
+                password,
+            }) => {
+                codec.padding(4);
+                encode_string(&mut codec, username, 24);
+                codec.encode(password);
+                codec.encode(client_type);
+            }
+            Self::ClientLogin(LoginCredentials::OTP {
+                client_type,
+                account_name,
+                password,
+            }) => {
+                codec.variable_length();
+                codec.padding(4);
+                codec.encode(client_type);
+                encode_string(&mut codec, account_name, 24);
+                // The password is sent as the single sign-on token, after the MAC & IP address
+                codec.padding(OTP_UNUSED_LENGTH);
+                for byte in password {
+                    codec.encode(byte);
+                }
+            }
+            Self::CodeKey => {}
+            Self::OneTimeToken => codec.padding(66),
+            Self::ConnectChar => codec.padding(84),
+        }
+        codec.finish()
     }
 }
 
-fn parse_hashed_credentials(data: &[u8]) -> LoginCredentials {
-    let mut username = [0u8; 23 + 1];
-    copy_zero_terminated_buffer(&mut username, &data[4..4 + 23 + 1]);
-    let mut password = [0u8; 16];
-    copy_zero_terminated_buffer(&mut password, &data[28..28 + 16]);
-    LoginCredentials::Hashed {
-        client_type: data[data.len() - 1],
-        username: String::from_utf8_lossy(
-            &username
-                .split(|char| *char == b'\0')
-                .next()
-                .unwrap_or_default(),
-        )
-        .to_string(),
+/// Unused part of the OTP header: password, MAC & IP address
+const OTP_UNUSED_LENGTH: usize = 27 + 17 + 15;
+
+fn decode_cleartext_credentials(reader: &mut ClientTcpReader) -> Result<LoginCredentials, Error> {
+    // Client version
+    reader.skip(4)?;
+    let username = reader.string(24)?;
+    let password = reader.string(24)?;
+    Ok(LoginCredentials::ClearText {
+        client_type: reader.decode()?,
+        username,
         password,
-    }
+    })
 }
 
-fn copy_zero_terminated_buffer(buffer: &mut [u8], string_bytes: &[u8]) {
-    for (i, value) in string_bytes.iter().enumerate() {
-        if *value == 0 {
-            break;
-        }
-        buffer[i] = *value;
-    }
+fn decode_hashed_credentials(reader: &mut ClientTcpReader) -> Result<LoginCredentials, Error> {
+    // Client version
+    reader.skip(4)?;
+    let username = reader.string(24)?;
+    let password = reader.decode()?;
+    Ok(LoginCredentials::Hashed {
+        client_type: reader.decode()?,
+        username,
+        password,
+    })
 }
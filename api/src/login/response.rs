@@ -3,11 +3,20 @@ use std::time::SystemTime;
 use chrono::{DateTime, Utc};
 use stackvec::StackVec;
 
-use crate::{account::mmo_account::Sex, character::ServerInfo as CharacterServerInfo};
+use std::convert::TryFrom;
+
+use crate::{
+    account::mmo_account::Sex,
+    character::{ServerActivity, ServerInfo as CharacterServerInfo, ServerType},
+    codec::{encode_string, ClientTcpCodec, ClientTcpReader, RagnarokCodec},
+    error::PacketError,
+    map::Maps,
+    packet_version::PacketVersion,
+};
 
 const BAN_TIME_FORMAT: &str = "%Y-%M-%D %H:%M";
 
-#[derive(Debug, thiserror::Error)]
+#[derive(Debug, PartialEq, thiserror::Error)]
 pub enum LoginFailed {
     #[error("UnregisteredId")]
     UnregisteredId(String),
@@ -89,7 +98,38 @@ impl LoginFailed {
     }
 }
 
-#[derive(Debug, thiserror::Error)]
+impl LoginFailed {
+    /// Maps a failure code back to its reason. Details the packet doesn't carry are left empty.
+    pub fn from_error_code(code: u32) -> Option<Self> {
+        Some(match code {
+            0 => Self::UnregisteredId(String::new()),
+            1 => Self::IncorrectPassword,
+            2 => Self::IdIsExpired,
+            3 => Self::RejectedFromServer,
+            4 => Self::AccountPermanentlySuspended,
+            5 => Self::GameExeNotUpToDate,
+            6 => Self::BannedUntil(SystemTime::UNIX_EPOCH),
+            7 => Self::ServerOverpopulated,
+            8 => Self::MaxCompanyCapacityReached,
+            9 => Self::BannedByDBA,
+            10 => Self::EmailNotConfirmed,
+            11 => Self::BannedByGM,
+            12 => Self::TemporaryBanForDBWork,
+            13 => Self::SelfLock,
+            14 => Self::GroupNotPermittedV1(0),
+            15 => Self::GroupNotPermittedV2(0),
+            99 => Self::IdErased,
+            100 => Self::LoginInfoRelocated,
+            101 => Self::LockedForHackingInvestigation,
+            102 => Self::LockedForBugInvestigation,
+            103 => Self::DeleteInProgressV1,
+            104 => Self::DeleteInProgressV2,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Debug, PartialEq, thiserror::Error)]
 pub enum LoginAborted {
     #[error("The server is closed")]
     ServerClosed,
@@ -107,97 +147,168 @@ impl LoginAborted {
             Self::AlreadyOnline => 8,
         }
     }
+
+    pub fn from_error_code(code: u8) -> Option<Self> {
+        match code {
+            1 => Some(Self::ServerClosed),
+            2 => Some(Self::AlreadyLoggedIn),
+            8 => Some(Self::AlreadyOnline),
+            _ => None,
+        }
+    }
 }
 
+#[derive(Debug, PartialEq)]
 pub enum Response {
-    LoginSuccessV1(CharacterSelectionInfo),
-    LoginSuccessV3(CharacterSelectionInfo),
+    LoginSuccess(Box<CharacterSelectionInfo>),
     LoginFailed(LoginFailed),
     LoginAborted(LoginAborted),
 }
 
 impl Response {
-    pub fn command_code(&self) -> u16 {
+    pub fn command_code(&self, version: PacketVersion) -> u16 {
         match self {
-            Response::LoginSuccessV1(_) => 0x69,
-            Response::LoginSuccessV3(_) => 0xac4,
-            Response::LoginFailed(_) => 0x83e,
+            Response::LoginSuccess(_) if version.has_extended_server_list() => 0xac4,
+            Response::LoginSuccess(_) => 0x69,
+            Response::LoginFailed(_) if version.has_extended_login_failure() => 0x83e,
+            Response::LoginFailed(_) => 0x6a,
             Response::LoginAborted(_) => 0x81,
         }
     }
 
-    pub fn serialize(&self, buf: &mut [u8]) -> Result<usize, usize> {
+    pub fn serialize(&self, version: PacketVersion, buf: &mut [u8]) -> Result<usize, usize> {
+        // Login packets don't refer to any map
+        let maps = Maps::default();
+        let mut codec = ClientTcpCodec::new(&maps, version, buf);
+        codec.encode(&self.command_code(version));
         match self {
-            Self::LoginSuccessV1(_info) => {
-                todo!("Handle LoginSuccessV1");
-            }
-            Self::LoginSuccessV3(info) => {
-                let msg_len = 64 + info.char_servers.len() * 160;
-                if buf.len() < msg_len {
-                    return Err(msg_len);
-                }
-                buf[..2].copy_from_slice(&(msg_len as u16).to_le_bytes());
-                buf[2..6].copy_from_slice(&info.authentication_code.to_le_bytes());
-                buf[6..10].copy_from_slice(&info.account_id.to_le_bytes());
-                buf[10..14].copy_from_slice(&info.user_level.to_le_bytes());
+            Self::LoginSuccess(info) => {
+                codec.variable_length();
+                codec.encode(&info.authentication_code);
+                codec.encode(&info.account_id);
+                codec.encode(&info.user_level);
                 // unused (last_login_ip + last_login_time)
-                buf[14..44].copy_from_slice(&[0u8; 30]);
-                buf[44] = info.sex.into();
-                buf[45..61].copy_from_slice(&info.web_auth_token);
-                buf[61] = 0;
-                let header_offset = 62;
-                for (i, server) in info.char_servers.iter().enumerate() {
-                    let offset = header_offset + (i * 160);
+                codec.padding(30);
+                codec.encode(&info.sex);
+                if version.has_extended_server_list() {
+                    codec.encode(&info.web_auth_token);
+                    codec.padding(1);
+                }
+                for server in info.char_servers.iter() {
                     let ip: u32 = server.ip_addr.into();
-                    buf[offset..offset + 4].copy_from_slice(&ip.to_be_bytes());
-                    buf[offset + 4..offset + 6].copy_from_slice(&server.port.to_le_bytes());
-                    buf[offset + 6..offset + 6 + server.name.len()]
-                        .copy_from_slice(&server.name.as_bytes());
-                    buf[offset + 6 + server.name.len()] = b'\0';
+                    codec.encode(&ip.to_be_bytes());
+                    codec.encode(&server.port);
+                    encode_string(&mut codec, &server.name, 20);
                     let server_activity: u16 = server.server_activity.into();
-                    buf[offset + 26..offset + 28].copy_from_slice(&server_activity.to_le_bytes());
+                    codec.encode(&server_activity);
                     let server_type: u16 = server.server_type.into();
-                    buf[offset + 28..offset + 30].copy_from_slice(&server_type.to_be_bytes());
-                    buf[offset + 30..offset + 32].copy_from_slice(&[0u8; 2]);
-                    buf[offset + 32..offset + 160].copy_from_slice(&[0u8; 128]);
+                    codec.encode(&server_type);
+                    codec.padding(2);
+                    if version.has_extended_server_list() {
+                        codec.padding(128);
+                    }
                 }
-
-                Ok(msg_len)
             }
             Self::LoginFailed(failure) => {
-                if buf.len() < 20 {
-                    return Err(20);
-                }
                 let failure_code = failure.error_code();
-                buf[..4].copy_from_slice(&failure_code.to_le_bytes());
+                if version.has_extended_login_failure() {
+                    codec.encode(&failure_code);
+                } else {
+                    codec.encode(&(failure_code as u8));
+                }
                 if let LoginFailed::BannedUntil(time) = failure {
                     let time_str = DateTime::<Utc>::from(*time)
                         .format(BAN_TIME_FORMAT)
                         .to_string();
-                    let time_str_bytes = time_str.as_bytes();
-                    buf[4..4 + time_str_bytes.len()].copy_from_slice(&time_str_bytes);
-                    if time_str_bytes.len() < 20 {
-                        buf[4 + time_str_bytes.len()] = b'\0';
-                    }
-                    Ok(4 + time_str_bytes.len())
+                    encode_string(&mut codec, &time_str, 20);
                 } else {
-                    // Fill with zero bytes
-                    buf[4..24].copy_from_slice(&[0u8; 20]);
-                    Ok(24)
+                    codec.padding(20);
                 }
             }
             Self::LoginAborted(aborted) => {
-                if !buf.is_empty() {
-                    buf[0] = aborted.error_code();
-                    Ok(1)
-                } else {
-                    Err(1)
+                codec.encode(&aborted.error_code());
+            }
+        }
+        codec.finish()
+    }
+
+    /// Parses the packet's payload, i.e. everything after the opcode, as a client would
+    pub fn parse(
+        command_code: u16,
+        version: PacketVersion,
+        buf: &[u8],
+    ) -> Result<Self, PacketError> {
+        let mut reader = ClientTcpReader::with_version(buf, version);
+        match command_code {
+            0x69 | 0xac4 => {
+                let server_len = if command_code == 0xac4 { 160 } else { 32 };
+                reader.skip(2)?;
+                let authentication_code = reader.decode()?;
+                let account_id = reader.decode()?;
+                let user_level = reader.decode()?;
+                // unused (last_login_ip + last_login_time)
+                reader.skip(30)?;
+                let sex = reader.decode()?;
+                let mut web_auth_token = [0u8; 16];
+                if command_code == 0xac4 {
+                    web_auth_token = reader.decode()?;
+                    reader.skip(1)?;
+                }
+                let mut char_servers = StackVec::default();
+                while reader.remaining() > 0 {
+                    let mut server = ClientTcpReader::new(reader.bytes(server_len)?);
+                    let ip_addr = u32::from_be_bytes(server.decode()?);
+                    let port = server.decode()?;
+                    let name = server.string(20)?;
+                    let server_activity = ServerActivity::try_from(server.decode::<u16>()?)?;
+                    let server_type = ServerType::try_from(server.decode::<u16>()?)?;
+                    char_servers
+                        .try_push(CharacterServerInfo {
+                            ip_addr: ip_addr.into(),
+                            port,
+                            name,
+                            active_users: 0,
+                            server_type,
+                            server_activity,
+                        })
+                        .map_err(|_| PacketError::InvalidRequest("Too many servers".to_string()))?;
                 }
+                Ok(Self::LoginSuccess(Box::new(CharacterSelectionInfo {
+                    account_id,
+                    authentication_code,
+                    user_level,
+                    sex,
+                    web_auth_token,
+                    char_servers,
+                })))
+            }
+            0x6a | 0x83e => {
+                let code = if command_code == 0x83e {
+                    reader.decode()?
+                } else {
+                    reader.decode::<u8>()? as u32
+                };
+                // The ban date is only meant for display, so it isn't recovered
+                LoginFailed::from_error_code(code)
+                    .map(Self::LoginFailed)
+                    .ok_or_else(|| {
+                        PacketError::InvalidRequest(format!("Unknown login failure {}", code))
+                    })
+            }
+            0x81 => {
+                let code = reader.decode()?;
+                LoginAborted::from_error_code(code)
+                    .map(Self::LoginAborted)
+                    .ok_or_else(|| {
+                        PacketError::InvalidRequest(format!("Unknown login abort {}", code))
+                    })
             }
+            unknown => Err(PacketError::InvalidCommand(unknown)),
         }
     }
 }
 
+#[derive(Debug, PartialEq)]
 pub struct CharacterSelectionInfo {
     pub account_id: u32,
     pub authentication_code: u32,
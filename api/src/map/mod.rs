@@ -1,21 +1,31 @@
-use crate::codec::EncodeFixed;
+use crate::codec::{encode_fixed_string, EncodeFixed};
 use serde::Deserialize;
-use std::cmp::min;
 use std::collections::HashMap;
 
 #[derive(Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
 pub struct MapName(String);
 
+impl MapName {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<String> for MapName {
+    fn from(name: String) -> Self {
+        Self(name)
+    }
+}
+
 impl EncodeFixed for MapName {
     const SIZE: usize = 16;
     fn encode(&self, buf: &mut [u8]) {
-        let name = self.0.as_bytes();
-        let len = min(Self::SIZE, name.len());
-        buf[..len].copy_from_slice(&name[..len]);
-        buf[len] = b'\0';
+        encode_fixed_string(&self.0, buf);
     }
 }
 
+/// Map names & their IDs. The default has no maps, e.g. for the login server's packets.
+#[derive(Default)]
 pub struct Maps {
     maps: Vec<MapName>,
     name_to_id: HashMap<MapName, u16>,
@@ -34,7 +44,7 @@ impl Maps {
     }
 
     pub fn name(&self, id: u16) -> Option<MapName> {
-        self.maps.get((id - 1) as usize).cloned()
+        self.maps.get(id.checked_sub(1)? as usize).cloned()
     }
 
     pub fn id(&self, name: &MapName) -> Option<u16> {
@@ -0,0 +1,188 @@
+use std::collections::HashMap;
+
+use tracing::warn;
+
+use crate::character::CharacterFrame;
+use crate::error::PacketError;
+use crate::packet_version::PacketVersion;
+use crate::utils::parse_word;
+
+/// Length of a packet, including its 2-byte opcode
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PacketLength {
+    Fixed(usize),
+    /// The length is sent in bytes 2..4 of the packet
+    Variable,
+}
+
+/// A packet's length, from the client version onwards
+struct PacketEntry {
+    opcode: u16,
+    length: PacketLength,
+    since: u32,
+}
+
+const fn fixed(opcode: u16, length: usize, since: u32) -> PacketEntry {
+    PacketEntry {
+        opcode,
+        length: PacketLength::Fixed(length),
+        since,
+    }
+}
+
+const fn variable(opcode: u16, since: u32) -> PacketEntry {
+    PacketEntry {
+        opcode,
+        length: PacketLength::Variable,
+        since,
+    }
+}
+
+/// Packets clients send to the login server
+const LOGIN_PACKETS: &[PacketEntry] = &[
+    fixed(0x0064, 55, 0),
+    fixed(0x01db, 2, 0),
+    fixed(0x01dd, 47, 0),
+    fixed(0x01fa, 48, 0),
+    fixed(0x0200, 26, 0),
+    fixed(0x0204, 18, 0),
+    fixed(0x0277, 84, 0),
+    fixed(0x027c, 60, 0),
+    fixed(0x02b0, 85, 0),
+    variable(0x0825, 20101124),
+    fixed(0x0acf, 68, 20171113),
+    // Sent by char servers
+    fixed(0x2710, 86, 0),
+];
+
+/// Packets clients send to the character server
+const CHARACTER_PACKETS: &[PacketEntry] = &[
+    fixed(0x0065, 17, 0),
+    fixed(0x0066, 3, 0),
+    fixed(0x0067, 37, 0),
+    fixed(0x0068, 46, 0),
+    fixed(0x0187, 6, 0),
+    fixed(0x01fb, 56, 0),
+    fixed(0x028d, 34, 0),
+    fixed(0x028f, 6, 0),
+    fixed(0x07e5, 8, 0),
+    fixed(0x07e7, 32, 0),
+    fixed(0x0827, 6, 20100803),
+    fixed(0x0829, 12, 20100803),
+    fixed(0x082b, 6, 20100803),
+    fixed(0x08b8, 10, 20110309),
+    fixed(0x08ba, 10, 20110309),
+    fixed(0x08be, 14, 20110309),
+    fixed(0x08c5, 6, 20110309),
+    fixed(0x08d4, 8, 20110928),
+    fixed(0x08fc, 30, 20111025),
+    fixed(0x0970, 31, 20120307),
+    fixed(0x09a1, 2, 20130522),
+    fixed(0x0a39, 36, 20151001),
+];
+
+/// Packets the login server sends to clients
+const LOGIN_RESPONSES: &[PacketEntry] = &[
+    variable(0x0069, 0),
+    fixed(0x006a, 23, 0),
+    fixed(0x0081, 3, 0),
+    fixed(0x083e, 26, 20120000),
+    variable(0x0ac4, 20170315),
+];
+
+/// Packets the character server sends to clients.
+/// The length of 0x6d depends on the character layout, so it's added by `character_responses`.
+const CHARACTER_RESPONSES: &[PacketEntry] = &[
+    variable(0x006b, 0),
+    fixed(0x006c, 3, 0),
+    fixed(0x006e, 3, 0),
+    fixed(0x0081, 3, 0),
+    variable(0x020d, 0),
+    variable(0x07e8, 0),
+    variable(0x07e9, 0),
+    variable(0x082d, 20100803),
+    fixed(0x08b9, 12, 20110309),
+    variable(0x08d5, 20110928),
+    variable(0x099d, 20130522),
+    fixed(0x09a0, 6, 20130522),
+];
+
+/// Lengths of the packets a server or client understands, for a single client version
+pub struct PacketTable {
+    lengths: HashMap<u16, PacketLength>,
+}
+
+impl PacketTable {
+    fn new(entries: &[PacketEntry], version: PacketVersion) -> Self {
+        let mut available: Vec<&PacketEntry> = entries
+            .iter()
+            .filter(|entry| entry.since <= version.date())
+            .collect();
+        // Newer definitions replace older ones
+        available.sort_by_key(|entry| entry.since);
+        let lengths = available
+            .into_iter()
+            .map(|entry| (entry.opcode, entry.length))
+            .collect();
+        Self { lengths }
+    }
+
+    pub fn login(version: PacketVersion) -> Self {
+        Self::new(LOGIN_PACKETS, version)
+    }
+
+    pub fn character(version: PacketVersion) -> Self {
+        Self::new(CHARACTER_PACKETS, version)
+    }
+
+    /// Packets sent by the login server, for client-side decoding
+    pub fn login_responses(version: PacketVersion) -> Self {
+        Self::new(LOGIN_RESPONSES, version)
+    }
+
+    /// Packets sent by the character server, for client-side decoding
+    pub fn character_responses(version: PacketVersion) -> Self {
+        let mut table = Self::new(CHARACTER_RESPONSES, version);
+        table.lengths.insert(
+            0x006d,
+            PacketLength::Fixed(2 + CharacterFrame::size(version)),
+        );
+        table
+    }
+
+    pub fn length(&self, opcode: u16) -> Option<PacketLength> {
+        self.lengths.get(&opcode).copied()
+    }
+
+    /// Determines the length of the packet at the start of `buf`, opcode included.
+    /// Fails with `PacketIncomplete` if `buf` doesn't hold the full packet yet.
+    pub fn frame_length(&self, buf: &[u8]) -> Result<usize, PacketError> {
+        if buf.len() < 2 {
+            return Err(PacketError::PacketIncomplete(2 - buf.len()));
+        }
+        let opcode = parse_word(&buf[..2]);
+        let length = match self.length(opcode) {
+            Some(PacketLength::Fixed(length)) => length,
+            Some(PacketLength::Variable) => {
+                if buf.len() < 4 {
+                    return Err(PacketError::PacketIncomplete(4 - buf.len()));
+                }
+                let length = parse_word(&buf[2..4]) as usize;
+                if length < 4 {
+                    warn!(opcode, length, "Invalid packet length");
+                    return Err(PacketError::InvalidRequest(format!(
+                        "Invalid length {} for packet {:#06x}",
+                        length, opcode
+                    )));
+                }
+                length
+            }
+            None => return Err(PacketError::InvalidCommand(opcode)),
+        };
+        if buf.len() < length {
+            Err(PacketError::PacketIncomplete(length - buf.len()))
+        } else {
+            Ok(length)
+        }
+    }
+}
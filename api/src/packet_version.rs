@@ -0,0 +1,104 @@
+use serde::Deserialize;
+
+/// Date of the client build the packet layouts are chosen for, e.g. `20180620`.
+/// Layouts change over time, so each codec checks the version for the fields & packets it supports.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Deserialize)]
+#[serde(transparent)]
+pub struct PacketVersion(u32);
+
+impl PacketVersion {
+    pub const fn new(date: u32) -> Self {
+        Self(date)
+    }
+
+    pub fn date(&self) -> u32 {
+        self.0
+    }
+
+    fn is_at_least(&self, date: u32) -> bool {
+        self.0 >= date
+    }
+
+    // Login server
+
+    /// Login failures carry a 32-bit error code (0x83e instead of 0x6a)
+    pub fn has_extended_login_failure(&self) -> bool {
+        self.is_at_least(20120000)
+    }
+
+    /// Char servers are listed with 160 byte entries (0xac4 instead of 0x69)
+    pub fn has_extended_server_list(&self) -> bool {
+        self.is_at_least(20170315)
+    }
+
+    // Character server
+
+    /// The character list carries the number of slots (0x6b)
+    pub fn has_slot_count_in_character_list(&self) -> bool {
+        self.is_at_least(20100413)
+    }
+
+    /// Characters are sent in pages of 3 (0x82d, 0x9a0 & 0x99d)
+    pub fn has_character_pages(&self) -> bool {
+        self.is_at_least(20130522)
+    }
+
+    /// Base & job experience are 64-bit
+    pub fn has_64bit_exp(&self) -> bool {
+        self.is_at_least(20170830)
+    }
+
+    /// Characters are created without allocating stats (0x970 instead of 0x67)
+    pub fn has_creation_without_stats(&self) -> bool {
+        self.is_at_least(20120307)
+    }
+
+    /// Characters are created with a starting job & sex (0xa39)
+    pub fn has_creation_with_class(&self) -> bool {
+        self.is_at_least(20151001)
+    }
+
+    pub fn has_rename_flag(&self) -> bool {
+        self.is_at_least(20061023)
+    }
+
+    pub fn has_last_map(&self) -> bool {
+        self.is_at_least(20100720)
+    }
+
+    pub fn has_delete_date(&self) -> bool {
+        self.is_at_least(20100803)
+    }
+
+    pub fn has_robe(&self) -> bool {
+        self.is_at_least(20110111)
+    }
+
+    pub fn has_slot_change(&self) -> bool {
+        self.is_at_least(20110928)
+    }
+
+    pub fn has_rename_count(&self) -> bool {
+        self.is_at_least(20111025)
+    }
+
+    pub fn has_character_sex(&self) -> bool {
+        self.is_at_least(20141016)
+    }
+
+    pub fn has_body_style(&self) -> bool {
+        self.is_at_least(20141022)
+    }
+}
+
+impl Default for PacketVersion {
+    fn default() -> Self {
+        Self(20180620)
+    }
+}
+
+impl std::fmt::Display for PacketVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
@@ -1,7 +1,9 @@
-use crate::account::db::AccountId;
+use sha2::{Digest, Sha256};
+
+use crate::account::{db::AccountId, mmo_account::PINCODE_LENGTH};
 
 #[repr(u16)]
-#[derive(Copy, Clone, Debug, int_enum::IntEnum)]
+#[derive(Copy, Clone, Debug, PartialEq, int_enum::IntEnum)]
 pub enum PincodeStatus {
     Correct = 0,
     AskForPin = 1,
@@ -15,8 +17,95 @@ pub enum PincodeStatus {
     Incorrect = 8,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct PincodeInfo {
+    /// Seed the client uses to scramble the pincode it sends back
+    pub seed: u32,
     pub status: PincodeStatus,
     pub account_id: AccountId,
 }
+
+pub type Pincode = [u8; PINCODE_LENGTH];
+
+/// Salted hash of an account's pincode, as stored in the account DB
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct PincodeHash([u8; 32]);
+
+impl PincodeHash {
+    pub fn new(account_id: AccountId, pincode: &Pincode) -> Self {
+        let mut hasher = Sha256::new();
+        hasher.update(account_id.to_le_bytes());
+        hasher.update(pincode);
+        let mut hash = [0u8; 32];
+        hash.copy_from_slice(&hasher.finalize());
+        Self(hash)
+    }
+
+    pub fn matches(&self, account_id: AccountId, pincode: &Pincode) -> bool {
+        *self == Self::new(account_id, pincode)
+    }
+}
+
+impl std::fmt::Debug for PincodeHash {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("PincodeHash(..)")
+    }
+}
+
+/// Generates a new seed for scrambling the pincode keypad
+pub fn generate_seed() -> u32 {
+    fastrand::u32(..0xFFFF)
+}
+
+/// Order of the keypad's digits, as shuffled by the client for `seed`
+fn keypad(seed: u32) -> [u8; 10] {
+    let mut keypad: [u8; 10] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9];
+    let mut seed = seed;
+    for i in 1..keypad.len() {
+        seed = seed.wrapping_mul(0x3498).wrapping_add(0x881234);
+        let pos = seed as usize % (i + 1);
+        keypad.swap(i, pos);
+    }
+    keypad
+}
+
+/// Scrambles a pincode the way the client does, i.e. the inverse of `decrypt`.
+/// Returns `None` if the pincode contains anything other than ASCII digits.
+pub fn encrypt(seed: u32, pincode: &Pincode) -> Option<Pincode> {
+    let keypad = keypad(seed);
+    let mut scrambled = [0u8; PINCODE_LENGTH];
+    for (scrambled, digit) in scrambled.iter_mut().zip(pincode.iter()) {
+        if !digit.is_ascii_digit() {
+            return None;
+        }
+        let position = keypad.iter().position(|key| *key == digit - b'0')?;
+        *scrambled = b'0' + position as u8;
+    }
+    Some(scrambled)
+}
+
+/// Recovers the pincode the user entered from the digits sent by the client.
+/// The client shuffles its keypad based on `seed`, so each digit has to be mapped back.
+/// Returns `None` if the pincode contains anything other than ASCII digits.
+pub fn decrypt(seed: u32, scrambled: &Pincode) -> Option<Pincode> {
+    let keypad = keypad(seed);
+    let mut pincode = [0u8; PINCODE_LENGTH];
+    for (digit, scrambled) in pincode.iter_mut().zip(scrambled.iter()) {
+        if !scrambled.is_ascii_digit() {
+            return None;
+        }
+        *digit = b'0' + keypad[(scrambled - b'0') as usize];
+    }
+    Some(pincode)
+}
+
+/// Rejects trivially guessable pincodes, i.e. all-identical or consecutive digits
+pub fn is_allowed(pincode: &Pincode) -> bool {
+    if !pincode.iter().all(u8::is_ascii_digit) {
+        return false;
+    }
+    let repeated = pincode.windows(2).all(|pair| pair[0] == pair[1]);
+    let ascending = pincode.windows(2).all(|pair| pair[1] == pair[0] + 1);
+    let descending = pincode.windows(2).all(|pair| pair[0] == pair[1] + 1);
+    !(repeated || ascending || descending)
+}
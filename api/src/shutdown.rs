@@ -0,0 +1,109 @@
+//! Graceful shutdown of the servers.
+//!
+//! Once its `ShutdownTrigger` fires, a server stops accepting connections & tells its sessions
+//! to finish, which notify their client after completing the request they're handling.
+//! The server then waits for the sessions to end, up to its configured timeout.
+
+use std::future::Future;
+use std::time::Duration;
+
+use async_channel::{Receiver, Sender};
+use futures_util::{
+    future::{select, Either},
+    pin_mut,
+};
+
+/// Shuts down every server & session holding one of its `Shutdown`s.
+/// Dropping the trigger shuts them down as well.
+pub struct ShutdownTrigger {
+    sender: Sender<()>,
+}
+
+impl ShutdownTrigger {
+    pub fn new() -> (Self, Shutdown) {
+        let (sender, receiver) = async_channel::bounded(1);
+        (
+            Self { sender },
+            Shutdown {
+                receiver: Some(receiver),
+            },
+        )
+    }
+
+    pub fn trigger(&self) {
+        // Nothing is ever sent, closing the channel wakes up every receiver at once
+        self.sender.close();
+    }
+}
+
+/// Signal to shut down, the default one never fires
+#[derive(Clone, Debug, Default)]
+pub struct Shutdown {
+    receiver: Option<Receiver<()>>,
+}
+
+impl Shutdown {
+    /// Completes once the shutdown is triggered
+    pub async fn wait(&self) {
+        match &self.receiver {
+            Some(receiver) => {
+                let _ = receiver.recv().await;
+            }
+            None => async_std::future::pending().await,
+        }
+    }
+
+    /// Runs `future` until the shutdown is triggered, returning `None` if it came first
+    pub async fn until<F: Future>(&self, future: F) -> Option<F::Output> {
+        let shutdown = self.wait();
+        pin_mut!(shutdown, future);
+        // The shutdown goes first, so a busy client can't hold up the server
+        match select(shutdown, future).await {
+            Either::Left(_) => None,
+            Either::Right((output, _)) => Some(output),
+        }
+    }
+}
+
+/// The sessions a server is running, so it can wait for them to end when shutting down
+pub struct Sessions {
+    sender: Sender<()>,
+    receiver: Receiver<()>,
+}
+
+/// Held by a session for as long as it's running
+pub struct SessionGuard {
+    _sender: Sender<()>,
+}
+
+impl Sessions {
+    pub fn new() -> Self {
+        let (sender, receiver) = async_channel::bounded(1);
+        Self { sender, receiver }
+    }
+
+    pub fn guard(&self) -> SessionGuard {
+        SessionGuard {
+            _sender: self.sender.clone(),
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.sender.sender_count() - 1
+    }
+
+    /// Waits up to `timeout` for every session to end, returning the number still running
+    pub async fn drain(self, timeout: Duration) -> usize {
+        let Self { sender, receiver } = self;
+        drop(sender);
+        // Fails once the last guard is dropped, as nothing is ever sent
+        let _ = async_std::future::timeout(timeout, receiver.recv()).await;
+        receiver.sender_count()
+    }
+}
+
+impl Default for Sessions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,69 @@
+use std::{
+    net::IpAddr,
+    time::{Duration, SystemTime},
+};
+
+use api::captcha::{Captcha, CaptchaProvider};
+use dashmap::DashMap;
+use tracing::debug;
+
+use crate::config::CaptchaConfig;
+
+/// Decides which clients have to solve a captcha, based on the number
+/// of characters recently created from their IP address
+pub struct CaptchaGuard {
+    enabled: bool,
+    max_creations: usize,
+    window: Duration,
+    provider: Box<dyn CaptchaProvider + Send + Sync>,
+    creations: DashMap<IpAddr, Vec<SystemTime>>,
+}
+
+impl CaptchaGuard {
+    pub fn new(config: &CaptchaConfig, provider: Box<dyn CaptchaProvider + Send + Sync>) -> Self {
+        Self {
+            enabled: config.enabled,
+            max_creations: config.max_creations_per_ip,
+            window: Duration::from_secs(config.window_minutes * 60),
+            provider,
+            creations: DashMap::new(),
+        }
+    }
+
+    pub fn generate(&self) -> Captcha {
+        self.provider.generate()
+    }
+
+    pub fn record_creation(&self, ip_addr: IpAddr) {
+        if !self.enabled {
+            return;
+        }
+        let now = SystemTime::now();
+        self.prune(now);
+        let mut creations = self.creations.entry(ip_addr).or_default();
+        creations.push(now);
+        debug!(ip = %ip_addr, count = creations.len(), "Recorded character creation");
+    }
+
+    pub fn is_suspicious(&self, ip_addr: IpAddr) -> bool {
+        if !self.enabled {
+            return false;
+        }
+        self.prune(SystemTime::now());
+        self.creations
+            .get(&ip_addr)
+            .is_some_and(|creations| creations.len() >= self.max_creations)
+    }
+
+    /// Forgets the expired creations, along with the addresses left without any
+    fn prune(&self, now: SystemTime) {
+        self.creations.retain(|_, creations| {
+            creations.retain(|time| !self.is_expired(*time, now));
+            !creations.is_empty()
+        });
+    }
+
+    fn is_expired(&self, time: SystemTime, now: SystemTime) -> bool {
+        now.duration_since(time).unwrap_or_default() > self.window
+    }
+}
@@ -1,27 +1,165 @@
-use api::character::attributes::Location;
-use api::config::ServerConfig;
+use api::character::attributes::{Class, Location, Point, Skill};
+use api::character::StatAllocation;
+use api::config::{CaptureConfig, ServerConfig, ShutdownConfig};
 use api::inventory::Item;
 use serde::Deserialize;
+use std::collections::HashMap;
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct Config {
     pub char_server: ServerConfig,
     pub character_db: CharacterDBConfig,
     pub starting_characters: StartingCharacterConfig,
+    #[serde(default)]
+    pub stat_allocation: StatAllocationConfig,
+    #[serde(default)]
+    pub character_slots: CharacterSlotConfig,
+    #[serde(default)]
+    pub pincode: PincodeConfig,
+    #[serde(default)]
+    pub captcha: CaptchaConfig,
+    #[serde(default)]
+    pub name_policy: NamePolicyConfig,
     pub maps: MapConfig,
+    #[serde(default)]
+    pub capture: CaptureConfig,
+    #[serde(default)]
+    pub shutdown: ShutdownConfig,
+}
+
+/// Limits on the stats older clients distribute when creating a character
+#[derive(Deserialize, Debug, Clone)]
+pub struct StatAllocationConfig {
+    /// Sum all six stats have to add up to
+    pub total_points: u16,
+    pub max_per_stat: u8,
+}
+
+impl Default for StatAllocationConfig {
+    fn default() -> Self {
+        Self {
+            total_points: 30,
+            max_per_stat: 9,
+        }
+    }
 }
 
 #[derive(Deserialize, Debug, Clone)]
-pub struct StartingCharacterConfig {
-    pub novice: StartingCharacter,
-    pub doram: StartingCharacter,
+#[serde(default)]
+pub struct CharacterSlotConfig {
+    /// Number of slot moves a newly created character is allowed
+    pub max_moves: u16,
+    /// Slots available to every account, slots beyond these are premium
+    pub normal: u8,
+}
+
+impl Default for CharacterSlotConfig {
+    fn default() -> Self {
+        Self {
+            max_moves: 1,
+            normal: 9,
+        }
+    }
+}
+
+/// Templates for newly created characters, keyed by their starting class
+#[derive(Deserialize, Debug, Clone)]
+pub struct StartingCharacterConfig(HashMap<Class, StartingCharacter>);
+
+impl StartingCharacterConfig {
+    pub fn template(&self, class: Class) -> Option<&StartingCharacter> {
+        self.0.get(&class)
+    }
 }
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct StartingCharacter {
+    /// Inventory of the new character, items with an `equipped_slot` are worn
+    #[serde(default)]
     pub items: Vec<Item>,
     #[serde(default)]
     pub location: Location,
+    /// Overrides the save point of `location`
+    pub save: Option<Point>,
+    pub zeny: Option<u32>,
+    pub base_level: Option<u16>,
+    pub job_level: Option<u16>,
+    /// Stats for clients that don't allocate them during creation
+    pub stats: Option<StatAllocation>,
+    #[serde(default)]
+    pub skills: Vec<Skill>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct PincodeConfig {
+    pub enabled: bool,
+    /// Require accounts without a pincode to create one before selecting a character
+    pub force: bool,
+    /// Number of days after which a pincode has to be changed, 0 to never expire
+    pub change_period_days: u64,
+    /// Number of failed attempts before the account is locked, 0 to never lock
+    pub max_failures: u8,
+    /// Duration of the lock after too many failed attempts
+    pub lockout_minutes: u64,
+}
+
+impl Default for PincodeConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            force: false,
+            change_period_days: 0,
+            max_failures: 3,
+            lockout_minutes: 60,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct CaptchaConfig {
+    pub enabled: bool,
+    /// Number of characters created from a single IP within the window,
+    /// after which further creations and selections require a captcha
+    pub max_creations_per_ip: usize,
+    pub window_minutes: u64,
+    /// Number of incorrect answers before the connection is closed
+    pub max_attempts: u8,
+}
+
+impl Default for CaptchaConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_creations_per_ip: 3,
+            window_minutes: 60,
+            max_attempts: 3,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct NamePolicyConfig {
+    pub min_length: usize,
+    pub max_length: usize,
+    /// Characters a name may consist of, any printable character if not set
+    pub allowed_characters: Option<String>,
+    /// Names which can't be used, regardless of case
+    pub reserved_names: Vec<String>,
+    /// Words which can't appear anywhere in a name, regardless of case
+    pub forbidden_words: Vec<String>,
+}
+
+impl Default for NamePolicyConfig {
+    fn default() -> Self {
+        Self {
+            min_length: 4,
+            max_length: 23,
+            allowed_characters: None,
+            reserved_names: vec![],
+            forbidden_words: vec![],
+        }
+    }
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -0,0 +1,5 @@
+mod captcha;
+pub mod config;
+mod name_policy;
+pub mod server;
+mod session;
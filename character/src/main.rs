@@ -1,12 +1,7 @@
-use crate::config::Config;
-use server::CharacterServer;
+use api::shutdown::ShutdownTrigger;
+use character::{config::Config, server::CharacterServer};
 use std::net::SocketAddr;
-use tracing::info;
-
-mod authentication_db;
-mod config;
-mod server;
-mod session;
+use tracing::{info, warn};
 
 pub fn init_config() -> Result<Config, impl std::error::Error> {
     let mut settings = ::config::Config::default();
@@ -29,10 +24,20 @@ fn main() -> Result<(), anyhow::Error> {
 
     info!("Running with config:\n{:#?}", config);
 
+    // Drain the sessions on SIGINT & SIGTERM, instead of dropping them
+    let (shutdown_trigger, shutdown) = ShutdownTrigger::new();
+    ctrlc::set_handler(move || {
+        info!("Received termination signal");
+        shutdown_trigger.trigger();
+    })?;
+
+    // Accounts & logins are shared with the login server in memory, not across processes
+    warn!("Running without a login server, use the launcher to authenticate clients");
+
     async_std::task::block_on(async {
         let addr: SocketAddr =
             format!("{}:{}", config.char_server.address, config.char_server.port).parse()?;
-        let character_server = CharacterServer::new();
+        let character_server = CharacterServer::new().with_shutdown(shutdown);
         character_server
             .run(config, addr)
             .await
@@ -0,0 +1,65 @@
+use crate::config::NamePolicyConfig;
+
+#[derive(Debug, thiserror::Error)]
+pub enum NameError {
+    #[error("Name is too short")]
+    TooShort,
+    #[error("Name is too long")]
+    TooLong,
+    #[error("Name has leading or trailing whitespace")]
+    Whitespace,
+    #[error("Character {0:?} is not allowed")]
+    InvalidCharacter(char),
+    #[error("Name is reserved")]
+    Reserved,
+    #[error("Name contains the forbidden word {0:?}")]
+    Forbidden(String),
+}
+
+/// Validates character names against the configured rules.
+/// Word lists are matched case-insensitively: reserved names against the whole name,
+/// forbidden words against any part of it.
+impl NamePolicyConfig {
+    pub fn check(&self, name: &str) -> Result<(), NameError> {
+        let length = name.chars().count();
+        if length < self.min_length {
+            return Err(NameError::TooShort);
+        }
+        if length > self.max_length {
+            return Err(NameError::TooLong);
+        }
+        if name.trim() != name {
+            return Err(NameError::Whitespace);
+        }
+        if let Some(invalid) = name.chars().find(|c| !self.is_allowed(*c)) {
+            return Err(NameError::InvalidCharacter(invalid));
+        }
+
+        let name = name.to_lowercase();
+        if self
+            .reserved_names
+            .iter()
+            .any(|reserved| reserved.to_lowercase() == name)
+        {
+            return Err(NameError::Reserved);
+        }
+        if let Some(word) = self
+            .forbidden_words
+            .iter()
+            .find(|word| name.contains(&word.to_lowercase()))
+        {
+            return Err(NameError::Forbidden(word.clone()));
+        }
+        Ok(())
+    }
+
+    fn is_allowed(&self, c: char) -> bool {
+        // Control characters & undecodable bytes are never allowed
+        if c.is_control() || c == char::REPLACEMENT_CHARACTER {
+            return false;
+        }
+        self.allowed_characters
+            .as_ref()
+            .is_none_or(|allowed| allowed.contains(c))
+    }
+}
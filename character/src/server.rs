@@ -1,6 +1,7 @@
 use std::sync::Arc;
+use std::time::Duration;
 
-use crate::authentication_db::AuthenticationDB;
+use crate::captcha::CaptchaGuard;
 use async_codec::{Framed, WriteFrameError};
 use async_std::{
     io::Error as IOError,
@@ -8,19 +9,30 @@ use async_std::{
     stream::StreamExt,
     task,
 };
+use databases::account::db::InMemoryAccountDB;
+use databases::authentication::AuthenticationDB;
 use databases::character::InMemoryCharacterDB;
 use futures_util::SinkExt;
-use tracing::{debug, error, info, info_span, trace, Instrument};
+use tracing::{debug, error, info, info_span, trace, warn, Instrument};
 
 use api::{
-    character::{db::DBError, CharacterCodec, Request, Response},
+    account::db::AccountDB,
+    capture::{CaptureHeader, RecordingCodec, ServerKind},
+    character::{
+        db::{CharacterDB, DBError},
+        CharacterCodec, Request, Response, CHARACTERS_PER_PAGE,
+    },
     error::PacketError,
+    login::LoginAborted,
+    pincode::PincodeInfo,
+    shutdown::{SessionGuard, Sessions, Shutdown},
 };
 
 use crate::config::{CharacterDBConfig, Config};
-use crate::session::CharacterSession;
+use crate::session::{CharCreationError, CharSelectionError, CharacterSession, PincodeError};
+use api::captcha::{BitmapCaptcha, CaptchaProvider};
 use api::map::Maps;
-use databases::inventory::InMemoryInventoryDB;
+use databases::inventory::{InMemoryInventoryDB, InventoryDB};
 
 #[derive(Debug, thiserror::Error)]
 pub enum ServerError {
@@ -32,11 +44,45 @@ pub enum ServerError {
     SendingResponse(#[from] WriteFrameError<PacketError>),
 }
 
-pub struct CharacterServer {}
+#[derive(Default)]
+pub struct CharacterServer {
+    /// The login server's accounts, whose bans & pincodes both servers have to see
+    account_db: Option<Arc<InMemoryAccountDB>>,
+    /// Logins handed over by the login server
+    authentication_db: Arc<AuthenticationDB>,
+    captcha_provider: Option<Box<dyn CaptchaProvider + Send + Sync>>,
+    shutdown: Shutdown,
+}
 
 impl CharacterServer {
     pub fn new() -> Self {
-        Self {}
+        Self::default()
+    }
+
+    pub fn with_account_db(mut self, account_db: Arc<InMemoryAccountDB>) -> Self {
+        self.account_db = Some(account_db);
+        self
+    }
+
+    /// Accepts the logins the login server registers in `authentication_db`
+    pub fn with_authentication_db(mut self, authentication_db: Arc<AuthenticationDB>) -> Self {
+        self.authentication_db = authentication_db;
+        self
+    }
+
+    /// Generates the captchas with `captcha_provider` instead of `BitmapCaptcha`
+    pub fn with_captcha_provider(
+        mut self,
+        captcha_provider: Box<dyn CaptchaProvider + Send + Sync>,
+    ) -> Self {
+        self.captcha_provider = Some(captcha_provider);
+        self
+    }
+
+    /// Stops the server once `shutdown` is triggered, instead of running forever
+    pub fn with_shutdown(mut self, shutdown: Shutdown) -> Self {
+        self.shutdown = shutdown;
+        self
     }
 
     pub async fn run(
@@ -44,49 +90,116 @@ impl CharacterServer {
         config: Config,
         addr: impl Into<SocketAddr>,
     ) -> Result<(), anyhow::Error> {
+        let addr = addr.into();
+        let listener = TcpListener::bind(addr).await?;
+        self.serve(config, listener).await
+    }
+
+    /// Accepts connections on an already bound listener
+    pub async fn serve(self, config: Config, listener: TcpListener) -> Result<(), anyhow::Error> {
         // Initialize DBs
-        let authentication_db = Arc::new(AuthenticationDB::default());
-        let char_db = match config.character_db {
+        let account_db = match self.account_db {
+            Some(account_db) => account_db,
+            None => Arc::new(InMemoryAccountDB::new(false).await?),
+        };
+        let char_db = match &config.character_db {
             CharacterDBConfig::InMemory { verbose } => {
-                Arc::new(InMemoryCharacterDB::new(verbose).await?)
+                Arc::new(InMemoryCharacterDB::new(*verbose).await?)
             }
         };
         let inventory_db = Arc::new(InMemoryInventoryDB::new(true));
         let maps = Arc::new(Maps::from_file(&config.maps.names_file)?);
 
-        let addr = addr.into();
-        let listener = TcpListener::bind(addr).await?;
         info!("Listening on {}", listener.local_addr()?);
 
-        let starting_char_config = Arc::new(config.starting_characters.clone());
+        let captcha_provider = self
+            .captcha_provider
+            .unwrap_or_else(|| Box::new(BitmapCaptcha::default()));
+        let captcha_guard = Arc::new(CaptchaGuard::new(&config.captcha, captcha_provider));
+        let config = Arc::new(config);
         let mut incoming = listener.incoming();
+        let sessions = Sessions::new();
 
-        while let Some(stream) = incoming.next().await {
+        while let Some(stream) = self.shutdown.until(incoming.next()).await.flatten() {
             let stream: TcpStream = stream?;
+            let peer = match stream.peer_addr() {
+                Ok(addr) => addr,
+                Err(err) => {
+                    error!(%err, "Could not retrieve peer addr");
+                    continue;
+                }
+            };
             let session = CharacterSession::new(
-                starting_char_config.clone(),
-                authentication_db.clone(),
+                config.clone(),
+                self.authentication_db.clone(),
+                account_db.clone(),
                 char_db.clone(),
                 inventory_db.clone(),
+                captcha_guard.clone(),
+                peer.ip(),
+            );
+            let packet_version = config.char_server.packet_version;
+            let codec = RecordingCodec::for_session(
+                CharacterCodec::new(maps.clone(), packet_version),
+                &config.capture,
+                CaptureHeader {
+                    server: ServerKind::Character,
+                    packet_version,
+                },
+                peer,
+            );
+            let shutdown = self.shutdown.clone();
+            let guard = sessions.guard();
+            task::spawn(async move {
+                process_connection(session, codec, stream, shutdown, guard).await
+            });
+        }
+
+        // Closes the listener, refusing new connections while the sessions finish
+        drop(incoming);
+        drop(listener);
+        info!(sessions = sessions.count(), "Shutting down");
+        let timeout = Duration::from_secs(config.shutdown.timeout_seconds);
+        let remaining = sessions.drain(timeout).await;
+        if remaining > 0 {
+            warn!(
+                remaining,
+                "Sessions did not finish in time, their changes may be lost"
             );
-            let codec = CharacterCodec::new(maps.clone());
-            task::spawn(async move { process_connection(session, codec, stream).await });
         }
+        char_db.flush().await?;
+        inventory_db.flush().await?;
+        account_db.flush().await?;
         Ok(())
     }
 }
 
 async fn process_connection(
     mut session: CharacterSession,
-    codec: CharacterCodec,
+    codec: RecordingCodec<CharacterCodec>,
     stream: TcpStream,
+    shutdown: Shutdown,
+    _guard: SessionGuard,
 ) -> Result<(), anyhow::Error> {
     let socket = stream.peer_addr().expect("Could not retrieve peer addr");
     debug!(ip = %socket.ip(), port=socket.port(), "Received incoming connection");
 
     let mut framed_stream = Framed::new(stream, codec);
     async move {
-        while let Some(request) = framed_stream.next().await {
+        loop {
+            // A request being processed completes before the session ends
+            let request = match shutdown.until(framed_stream.next()).await {
+                Some(Some(request)) => request,
+                Some(None) => break,
+                None => {
+                    debug!("Server shutting down. Terminating connection");
+                    let response = Response::Aborted(LoginAborted::ServerClosed);
+                    if let Err(err) = framed_stream.send(response).await {
+                        error!(%err, "Could not notify client");
+                    }
+                    break;
+                }
+            };
             match request {
                 Ok(request) => {
                     if let Err(err) =
@@ -112,64 +225,238 @@ async fn process_connection(
 
 async fn process_request(
     session: &mut CharacterSession,
-    stream: &mut Framed<TcpStream, CharacterCodec>,
+    stream: &mut Framed<TcpStream, RecordingCodec<CharacterCodec>>,
     request: Request,
 ) -> Result<(), anyhow::Error> {
     match request {
         Request::ConnectClient(account_info) => {
             debug!(?account_info, "Client connecting");
-            if session.is_authenticated(account_info).await {
-                debug!(status = "authenticated");
-                stream
-                    .send(Response::AccountConnected(account_info.account_id))
-                    .await?;
-                stream.send(Response::CharacterSlotCount).await?;
-                let characters = session.get_characters().await?;
-                stream.send(Response::CharacterInfo(characters)).await?;
-                stream.send(Response::BannedCharacters).await?;
-                let pincode_info = session.get_pincode_info().await?;
-                stream.send(Response::PincodeInfo(pincode_info)).await?;
-            } else {
-                stream.send(Response::Rejected).await?;
+            // Clients expect the account ID first, whether the login is accepted or not
+            stream
+                .send(Response::AccountConnected(account_info.account_id))
+                .await?;
+            match session.authenticate(account_info).await {
+                Ok(()) => {
+                    debug!(status = "authenticated");
+                    let slots = session.slot_allowance();
+                    let paged = session.packet_version().has_character_pages();
+                    if paged {
+                        stream.send(Response::CharacterSlotCount(slots)).await?;
+                    }
+                    let characters = session.get_characters().await?;
+                    stream
+                        .send(Response::CharacterInfo { characters, slots })
+                        .await?;
+                    if paged {
+                        stream
+                            .send(Response::CharacterPagesAvailable(slots.pages()))
+                            .await?;
+                    }
+                    let banned = session.get_banned_characters().await?;
+                    stream.send(Response::BannedCharacters(banned)).await?;
+                    let pincode_info = session.get_pincode_info().await;
+                    send_pincode_info(stream, pincode_info).await?;
+                }
+                Err(err) => {
+                    warn!(account_id = %account_info.account_id, %err, "Refused connection");
+                    stream.send(Response::Rejected).await?;
+                }
             }
         }
+        Request::ListCharacters
+        | Request::SelectCharacter { .. }
+        | Request::CreateCharacter(_)
+        | Request::MoveCharacterSlot { .. }
+            if session.account_id().is_none() =>
+        {
+            error!("Cannot access characters before the account is connected");
+            stream.send(Response::Rejected).await?;
+        }
         Request::ListCharacters => {
-            debug!("Client authenticated, sending character info");
-            let characters = session.get_characters().await?;
+            let (characters, is_last) = session.next_character_page().await?;
+            debug!(count = characters.len(), is_last, "Sending character page");
+            // A full page makes the client ask for more, so an empty one marks the end
+            let terminate = is_last && characters.len() == CHARACTERS_PER_PAGE;
             stream.send(Response::Characters(characters)).await?;
+            if terminate {
+                stream.send(Response::Characters(vec![])).await?;
+            }
         }
         Request::KeepAlive => trace!("Keep-alive"),
         Request::SelectCharacter { slot } => {
             debug!("Selecting char slot {}", slot);
             match session.select_character(slot).await {
                 Ok(character) => {
-                    todo!("handle character selection");
-                    todo!("Set character online in OnlineDB");
+                    // There's no map server to hand the character over to yet
+                    warn!(
+                        account_id = ?session.account_id(),
+                        character_id = %character.id,
+                        "No map server to enter the selected character"
+                    );
+                    stream.send(Response::Rejected).await?;
+                }
+                Err(CharSelectionError::CaptchaRequired) => {
+                    debug!("Captcha required for character selection");
+                    send_captcha(session, stream).await?;
                 }
                 Err(err) => {
-                    error!(%err, "Could not select character");
+                    warn!(
+                        account_id = ?session.account_id(),
+                        slot,
+                        %err,
+                        "Refused character selection"
+                    );
                     stream.send(Response::Rejected).await?;
                 }
             }
         }
         Request::CreateCharacter(new_character) => {
             debug!("Creating new character");
-            let char = session.create_character(new_character).await?;
-            debug!(character_id = %char.id, "Created character");
-            stream.send(Response::NewCharacterInfo(char)).await?;
+            let slot = new_character.slot;
+            match session.create_character(new_character).await {
+                Ok(char) => {
+                    debug!(character_id = %char.id, "Created character");
+                    stream.send(Response::NewCharacterInfo(char)).await?;
+                }
+                Err(CharCreationError::CaptchaRequired) => {
+                    debug!("Captcha required for character creation");
+                    send_captcha(session, stream).await?;
+                }
+                Err(err) => {
+                    let reason = err.refusal();
+                    warn!(
+                        account_id = ?session.account_id(),
+                        slot,
+                        ?reason,
+                        %err,
+                        "Refused character creation"
+                    );
+                    stream.send(Response::CreationRefused(reason)).await?;
+                }
+            }
+        }
+        Request::DeleteCharacter
+        | Request::RequestCharacterDeletion
+        | Request::AcceptCharacterDeletion
+            if !session.is_pincode_verified() =>
+        {
+            error!("Cannot delete character before the pincode is verified");
+            stream.send(Response::Rejected).await?;
         }
         Request::DeleteCharacter => todo!("Handle DeleteCharacter"),
         Request::RequestCharacterDeletion => todo!("Handle RequestCharacterDeletion"),
         Request::AcceptCharacterDeletion => todo!("Handle AcceptCharacterDeletion"),
         Request::CancelCharacterDeletion2 => todo!("Handle CancelCharacterDeletion2"),
         Request::RenameCharacter => todo!("Handle RenameCharacter"),
-        Request::RequestCaptcha => todo!("Handle RequestCaptcha"),
-        Request::CheckCaptcha => todo!("Handle CheckCaptcha"),
-        Request::MoveCharacterSlot => todo!("Handle MoveCharacterSlot"),
-        Request::CheckPincode => todo!("Handle CheckPincode"),
-        Request::RequestPincode => todo!("Handle RequestPincode"),
-        Request::ChangePincode => todo!("Handle ChangePincode"),
-        Request::NewPincode => todo!("Handle NewPincode"),
+        Request::RequestCaptcha { account_id } => {
+            debug!("Requesting captcha");
+            let image = session.request_captcha(account_id)?;
+            stream.send(Response::CaptchaImage(image)).await?;
+        }
+        Request::CheckCaptcha { account_id, answer } => {
+            debug!("Checking captcha");
+            match session.check_captcha(account_id, &answer) {
+                Ok(solved) => stream.send(Response::CaptchaResult { solved }).await?,
+                Err(err) => {
+                    stream
+                        .send(Response::CaptchaResult { solved: false })
+                        .await?;
+                    return Err(err.into());
+                }
+            }
+        }
+        Request::MoveCharacterSlot { from, to } => {
+            debug!(from, to, "Moving character slot");
+            match session.move_character_slot(from, to).await {
+                Ok(remaining_moves) => {
+                    let characters = session.get_characters().await?;
+                    stream
+                        .send(Response::CharacterInfo {
+                            characters,
+                            slots: session.slot_allowance(),
+                        })
+                        .await?;
+                    stream
+                        .send(Response::SlotMoveResult {
+                            moved: true,
+                            remaining_moves,
+                        })
+                        .await?;
+                }
+                Err(err) => {
+                    error!(%err, "Could not move character slot");
+                    let remaining_moves = session.remaining_slot_moves(from).await;
+                    stream
+                        .send(Response::SlotMoveResult {
+                            moved: false,
+                            remaining_moves,
+                        })
+                        .await?;
+                }
+            }
+        }
+        Request::CheckPincode {
+            account_id,
+            pincode,
+        } => {
+            debug!("Checking pincode");
+            let pincode_info = session.check_pincode(account_id, pincode).await;
+            send_pincode_info(stream, pincode_info).await?;
+        }
+        Request::RequestPincode { account_id } => {
+            debug!("Requesting pincode");
+            let pincode_info = session.request_pincode(account_id).await;
+            send_pincode_info(stream, pincode_info).await?;
+        }
+        Request::ChangePincode {
+            account_id,
+            old_pincode,
+            new_pincode,
+        } => {
+            debug!("Changing pincode");
+            let pincode_info = session
+                .change_pincode(account_id, old_pincode, new_pincode)
+                .await;
+            send_pincode_info(stream, pincode_info).await?;
+        }
+        Request::NewPincode {
+            account_id,
+            pincode,
+        } => {
+            debug!("Creating new pincode");
+            let pincode_info = session.new_pincode(account_id, pincode).await;
+            send_pincode_info(stream, pincode_info).await?;
+        }
+    }
+    Ok(())
+}
+
+async fn send_pincode_info(
+    stream: &mut Framed<TcpStream, RecordingCodec<CharacterCodec>>,
+    pincode_info: Result<PincodeInfo, PincodeError>,
+) -> Result<(), anyhow::Error> {
+    match pincode_info {
+        Ok(pincode_info) => {
+            debug!(status = ?pincode_info.status, "Sending pincode status");
+            stream.send(Response::PincodeInfo(pincode_info)).await?;
+            Ok(())
+        }
+        Err(err) => {
+            // The session can't continue, e.g. because the account got locked
+            stream.send(Response::Rejected).await?;
+            Err(err.into())
+        }
     }
+}
+
+async fn send_captcha(
+    session: &mut CharacterSession,
+    stream: &mut Framed<TcpStream, RecordingCodec<CharacterCodec>>,
+) -> Result<(), anyhow::Error> {
+    let account_id = session
+        .account_id()
+        .ok_or_else(|| anyhow::anyhow!("Account is not authenticated"))?;
+    let image = session.request_captcha(account_id)?;
+    stream.send(Response::CaptchaImage(image)).await?;
     Ok(())
 }
@@ -1,30 +1,53 @@
+use std::net::IpAddr;
 use std::sync::Arc;
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
 
-use crate::authentication_db::AuthenticationDB;
-use crate::config::StartingCharacterConfig;
-use api::account::db::AccountId;
-use api::character::{attributes, NewCharacter, MAX_CHARACTERS_PER_ACCOUNT};
+use crate::captcha::CaptchaGuard;
+use crate::config::Config;
+use crate::name_policy::NameError;
+use api::account::{
+    db::{AccountDB, AccountId, DBError as AccountDBError},
+    mmo_account::{AccountState, MmoAccount},
+};
+use api::captcha::Captcha;
+use api::character::{
+    attributes, NewCharacter, SlotAllowance, StatAllocation, CHARACTERS_PER_PAGE,
+    CHARACTER_SLOT_MOVE_ENABLED,
+};
 use api::inventory::Inventory;
+use api::packet_version::PacketVersion;
 use api::{
     character::{
         db::{CharacterDB, DBError as CharacterDBError, DBResult},
-        AccountInfo, Character,
+        AccountInfo, BannedCharacter, Character, CharacterName, CreationRefused,
     },
-    pincode::{PincodeInfo, PincodeStatus},
+    pincode::{self, Pincode, PincodeHash, PincodeInfo, PincodeStatus},
 };
+use databases::authentication::AuthenticationDB;
 use databases::inventory::{Error as InventoryDBError, InventoryDB};
-use tracing::error;
+use tracing::{debug, error, warn};
 use tracing_attributes::instrument;
 
 #[derive(Debug, thiserror::Error)]
 pub enum CharCreationError {
+    #[error("Account is not authenticated")]
+    UnAuthenticated,
     #[error("Slot {0} is invalid")]
     InvalidSlot(u8),
+    #[error("Slot {0} is already in use")]
+    SlotOccupied(u8),
     #[error("Too many characters exist: {0}")]
     TooManyCharacters(u8),
     #[error("Invalid starting class {0:?}")]
     InvalidClass(attributes::Class),
+    #[error("Invalid stat allocation {0:?}")]
+    InvalidStats(StatAllocation),
+    #[error("A captcha has to be solved first")]
+    CaptchaRequired,
+    #[error("Invalid name: {0}")]
+    InvalidName(#[from] NameError),
+    #[error("Name {0} is already taken")]
+    NameTaken(CharacterName),
     #[error("No such account {0}")]
     NoSuchAccount(AccountId),
     #[error("Could not access inventory: {0}")]
@@ -33,6 +56,29 @@ pub enum CharCreationError {
     CharacterDB(#[from] CharacterDBError),
 }
 
+impl CharCreationError {
+    /// The refusal shown to the client
+    pub fn refusal(&self) -> CreationRefused {
+        match self {
+            Self::NameTaken(_) | Self::InvalidName(NameError::Reserved) => {
+                CreationRefused::NameExists
+            }
+            Self::InvalidName(NameError::InvalidCharacter(_)) => CreationRefused::SymbolsForbidden,
+            Self::InvalidSlot(_) => CreationRefused::SlotNotEligible,
+            Self::InvalidName(_)
+            | Self::SlotOccupied(_)
+            | Self::TooManyCharacters(_)
+            | Self::InvalidClass(_)
+            | Self::InvalidStats(_)
+            | Self::CaptchaRequired
+            | Self::UnAuthenticated
+            | Self::NoSuchAccount(_)
+            | Self::InventoryDB(_)
+            | Self::CharacterDB(_) => CreationRefused::Denied,
+        }
+    }
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum CharSelectionError {
     #[error("Account is not authenticated")]
@@ -45,59 +91,343 @@ pub enum CharSelectionError {
     Deleted,
     #[error("Cannot select character while retrieving guild bound items")]
     RetrievingGuildBoundItems,
+    #[error("Pincode has not been verified")]
+    PincodeNotVerified,
+    #[error("A captcha has to be solved first")]
+    CaptchaRequired,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SlotMoveError {
+    #[error("Account is not authenticated")]
+    UnAuthenticated,
+    #[error("Moving character slots is disabled")]
+    Disabled,
+    #[error("Slot {0} is invalid")]
+    InvalidSlot(u8),
+    #[error("No character on slot {0}")]
+    NoSuchSlot(u8),
+    #[error("Character on slot {0} has no moves left")]
+    NoMovesLeft(u8),
+    #[error("Internal {0}")]
+    CharacterDB(#[from] CharacterDBError),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum AuthenticationError {
+    #[error("Already authenticated as account {0}")]
+    AlreadyAuthenticated(AccountId),
+    #[error("Account {0} was not handed over by the login server")]
+    NotAuthenticated(AccountId),
+    #[error("Account {0} is banned")]
+    Banned(AccountId),
+    #[error("Account {0} has expired")]
+    Expired(AccountId),
+    #[error("Could not access account: {0}")]
+    AccountDB(#[from] AccountDBError),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum PincodeError {
+    #[error("Account is not authenticated")]
+    UnAuthenticated,
+    #[error("Pincode request for another account {0}")]
+    AccountMismatch(AccountId),
+    #[error("Account {0} is locked after too many failed pincode attempts")]
+    Locked(AccountId),
+    #[error("Could not access account: {0}")]
+    AccountDB(#[from] AccountDBError),
+}
+
+#[derive(Debug, Default)]
+struct PincodeState {
+    /// Seed of the last keypad sent to the client
+    seed: u32,
+    verified: bool,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum CaptchaError {
+    #[error("Account is not authenticated")]
+    UnAuthenticated,
+    #[error("Captcha request for another account {0}")]
+    AccountMismatch(AccountId),
+    #[error("Too many incorrect captcha answers")]
+    TooManyAttempts,
+}
+
+#[derive(Debug, Default)]
+struct CaptchaState {
+    /// Answer to the last captcha sent to the client
+    answer: Option<Captcha>,
+    /// Whether the last captcha was solved, which lets a single creation or selection through
+    solved: bool,
+    failures: u8,
 }
 
 pub struct CharacterSession {
-    starting_char_config: Arc<StartingCharacterConfig>,
+    config: Arc<Config>,
     authentication_db: Arc<AuthenticationDB>,
+    account_db: Arc<dyn AccountDB + Send + Sync>,
     character_db: Arc<dyn CharacterDB + Send + Sync>,
     inventory_db: Arc<dyn InventoryDB + Send + Sync>,
+    captcha_guard: Arc<CaptchaGuard>,
+    ip_addr: IpAddr,
     account_info: Option<AccountInfo>,
+    slots: SlotAllowance,
+    /// Next page of the character list the client will ask for
+    character_page: usize,
+    pincode: PincodeState,
+    captcha: CaptchaState,
 }
 
 impl CharacterSession {
     pub fn new(
-        starting_char_config: Arc<StartingCharacterConfig>,
+        config: Arc<Config>,
         authentication_db: Arc<AuthenticationDB>,
+        account_db: Arc<dyn AccountDB + Send + Sync>,
         character_db: Arc<dyn CharacterDB + Send + Sync>,
         inventory_db: Arc<dyn InventoryDB + Send + Sync>,
+        captcha_guard: Arc<CaptchaGuard>,
+        ip_addr: IpAddr,
     ) -> Self {
+        let normal_slots = config.character_slots.normal;
         Self {
-            starting_char_config,
+            config,
             authentication_db,
+            account_db,
             character_db,
             inventory_db,
+            captcha_guard,
+            ip_addr,
             account_info: None,
+            slots: SlotAllowance::new(normal_slots, normal_slots),
+            character_page: 0,
+            pincode: PincodeState::default(),
+            captcha: CaptchaState::default(),
         }
     }
 
+    /// Accepts the client if the login server handed over its account info
+    /// & the account may still log in
     #[instrument(skip(self), level = "debug")]
-    pub async fn is_authenticated(&mut self, account_info: AccountInfo) -> bool {
-        // TODO: remove
+    pub async fn authenticate(
+        &mut self,
+        account_info: AccountInfo,
+    ) -> Result<(), AuthenticationError> {
+        let account_id = account_info.account_id;
+        if let Some(info) = self.account_info {
+            return Err(AuthenticationError::AlreadyAuthenticated(info.account_id));
+        }
+        let authentication = self
+            .authentication_db
+            .claim(account_info)
+            .ok_or(AuthenticationError::NotAuthenticated(account_id))?;
+        // The account may have been locked since, e.g. by incorrect pincodes in another session
+        let account = self.account_db.get_account_by_id(account_id).await?;
+        let now = SystemTime::now();
+        match account.state {
+            AccountState::Banned(until) if until > now => {
+                return Err(AuthenticationError::Banned(account_id))
+            }
+            AccountState::ExpireOn(expiry) if expiry <= now => {
+                return Err(AuthenticationError::Expired(account_id))
+            }
+            _ => {}
+        }
+
         self.account_info = Some(account_info);
+        let normal = self.config.character_slots.normal;
+        self.slots = SlotAllowance::new(normal, authentication.char_slots.unwrap_or(normal));
+        self.character_page = 0;
+        Ok(())
+    }
 
-        if let Some(info) = self.account_info.as_ref() {
-            // TODO: Re-enable
-            // error!(account_id = %account_info.account_id, "Already authenticated");
-            // false
-            true
-        } else {
-            if self.authentication_db.check_if_authenticated(account_info) {
-                self.account_info = Some(account_info);
-                true
-            } else {
-                error!(account_id = %account_info.account_id, "Not authenticated");
-                false
-            }
+    pub fn slot_allowance(&self) -> SlotAllowance {
+        self.slots
+    }
+
+    pub fn packet_version(&self) -> PacketVersion {
+        self.config.char_server.packet_version
+    }
+
+    #[instrument(skip(self), level = "debug")]
+    pub async fn get_pincode_info(&mut self) -> Result<PincodeInfo, PincodeError> {
+        let account_id = self
+            .account_info
+            .ok_or(PincodeError::UnAuthenticated)?
+            .account_id;
+        if !self.config.pincode.enabled {
+            self.pincode.verified = true;
+            return Ok(self.pincode_info(account_id, PincodeStatus::Correct));
         }
+
+        let account = self.account_db.get_account_by_id(account_id).await?;
+        let status = match account.pincode {
+            None if self.config.pincode.force => PincodeStatus::CreateNewPin,
+            None => {
+                self.pincode.verified = true;
+                PincodeStatus::ShowButton
+            }
+            Some(_) if self.is_pincode_expired(&account) => PincodeStatus::NeedNewPin,
+            Some(_) if self.pincode.verified => PincodeStatus::ShowButton,
+            Some(_) => PincodeStatus::AskForPin,
+        };
+        Ok(self.pincode_info(account_id, status))
     }
 
     #[instrument(skip(self), level = "debug")]
-    pub async fn get_pincode_info(&self) -> DBResult<PincodeInfo> {
-        Ok(PincodeInfo {
-            status: PincodeStatus::Correct,
-            account_id: self.account_info.unwrap().account_id,
-        })
+    pub async fn request_pincode(
+        &mut self,
+        account_id: AccountId,
+    ) -> Result<PincodeInfo, PincodeError> {
+        self.check_pincode_account(account_id)?;
+        self.get_pincode_info().await
+    }
+
+    #[instrument(skip(self, scrambled), level = "debug")]
+    pub async fn check_pincode(
+        &mut self,
+        account_id: AccountId,
+        scrambled: Pincode,
+    ) -> Result<PincodeInfo, PincodeError> {
+        self.check_pincode_account(account_id)?;
+        let mut account = self.account_db.get_account_by_id(account_id).await?;
+        if account.pincode.is_none() {
+            return self.get_pincode_info().await;
+        }
+        if !self.verify_pincode(&mut account, &scrambled).await? {
+            return Ok(self.pincode_info(account_id, PincodeStatus::Incorrect));
+        }
+
+        self.pincode.verified = true;
+        Ok(self.pincode_info(account_id, PincodeStatus::ShowButton))
+    }
+
+    #[instrument(skip(self, old_scrambled, new_scrambled), level = "debug")]
+    pub async fn change_pincode(
+        &mut self,
+        account_id: AccountId,
+        old_scrambled: Pincode,
+        new_scrambled: Pincode,
+    ) -> Result<PincodeInfo, PincodeError> {
+        self.check_pincode_account(account_id)?;
+        let mut account = self.account_db.get_account_by_id(account_id).await?;
+        if account.pincode.is_none() {
+            return self.get_pincode_info().await;
+        }
+        if !self.verify_pincode(&mut account, &old_scrambled).await? {
+            return Ok(self.pincode_info(account_id, PincodeStatus::Incorrect));
+        }
+        self.store_pincode(account, &new_scrambled).await
+    }
+
+    #[instrument(skip(self, scrambled), level = "debug")]
+    pub async fn new_pincode(
+        &mut self,
+        account_id: AccountId,
+        scrambled: Pincode,
+    ) -> Result<PincodeInfo, PincodeError> {
+        self.check_pincode_account(account_id)?;
+        let account = self.account_db.get_account_by_id(account_id).await?;
+        if account.pincode.is_some() {
+            warn!(%account_id, "Account already has a pincode");
+            return self.get_pincode_info().await;
+        }
+        self.store_pincode(account, &scrambled).await
+    }
+
+    pub fn is_pincode_verified(&self) -> bool {
+        self.pincode.verified
+    }
+
+    fn pincode_info(&mut self, account_id: AccountId, status: PincodeStatus) -> PincodeInfo {
+        // Every prompt uses a freshly shuffled keypad
+        self.pincode.seed = pincode::generate_seed();
+        PincodeInfo {
+            seed: self.pincode.seed,
+            status,
+            account_id,
+        }
+    }
+
+    fn check_pincode_account(&self, account_id: AccountId) -> Result<(), PincodeError> {
+        let info = self.account_info.ok_or(PincodeError::UnAuthenticated)?;
+        if info.account_id != account_id {
+            return Err(PincodeError::AccountMismatch(account_id));
+        }
+        Ok(())
+    }
+
+    fn is_pincode_expired(&self, account: &MmoAccount) -> bool {
+        if self.config.pincode.change_period_days == 0 {
+            return false;
+        }
+        let change_period = Duration::from_secs(self.config.pincode.change_period_days * 86_400);
+        account
+            .pincode_change
+            .checked_add(change_period)
+            .is_some_and(|expiry| expiry <= SystemTime::now())
+    }
+
+    /// Checks the scrambled pincode against the stored hash,
+    /// locking the account once too many attempts have failed
+    async fn verify_pincode(
+        &mut self,
+        account: &mut MmoAccount,
+        scrambled: &Pincode,
+    ) -> Result<bool, PincodeError> {
+        let account_id = account.account_id;
+        let is_correct = match (
+            account.pincode,
+            pincode::decrypt(self.pincode.seed, scrambled),
+        ) {
+            (Some(hash), Some(pincode)) => hash.matches(account_id, &pincode),
+            _ => false,
+        };
+        if is_correct {
+            if account.pincode_failures > 0 {
+                account.pincode_failures = 0;
+                self.account_db.save_account(account).await?;
+            }
+            return Ok(true);
+        }
+
+        // Counted on the account, so reconnecting doesn't grant more attempts
+        account.pincode_failures = account.pincode_failures.saturating_add(1);
+        warn!(%account_id, failures = account.pincode_failures, "Incorrect pincode");
+        let max_failures = self.config.pincode.max_failures;
+        if max_failures > 0 && account.pincode_failures >= max_failures {
+            let lockout = Duration::from_secs(self.config.pincode.lockout_minutes * 60);
+            account.state = AccountState::Banned(SystemTime::now() + lockout);
+            // The attempts start over once the lockout ends
+            account.pincode_failures = 0;
+            self.account_db.save_account(account).await?;
+            return Err(PincodeError::Locked(account_id));
+        }
+        self.account_db.save_account(account).await?;
+        Ok(false)
+    }
+
+    async fn store_pincode(
+        &mut self,
+        mut account: MmoAccount,
+        scrambled: &Pincode,
+    ) -> Result<PincodeInfo, PincodeError> {
+        let account_id = account.account_id;
+        let pincode = match pincode::decrypt(self.pincode.seed, scrambled) {
+            Some(pincode) if pincode::is_allowed(&pincode) => pincode,
+            _ => {
+                warn!(%account_id, "Rejected new pincode");
+                return Ok(self.pincode_info(account_id, PincodeStatus::ClientWarning));
+            }
+        };
+        account.pincode = Some(PincodeHash::new(account_id, &pincode));
+        account.pincode_change = SystemTime::now();
+        self.account_db.save_account(&account).await?;
+
+        self.pincode.verified = true;
+        Ok(self.pincode_info(account_id, PincodeStatus::ShowButton))
     }
 
     #[instrument(skip(self), level = "debug")]
@@ -107,9 +437,40 @@ impl CharacterSession {
             .await
     }
 
+    /// Retrieves the characters of the account whose ban hasn't expired yet
+    pub async fn get_banned_characters(&self) -> DBResult<Vec<BannedCharacter>> {
+        let characters = self.get_characters().await?;
+        Ok(characters
+            .iter()
+            .filter_map(|char| {
+                char.status
+                    .ban
+                    .as_ref()
+                    .filter(|ban| ban.is_active())
+                    .map(|ban| BannedCharacter {
+                        id: char.id,
+                        unban_on: ban.unban_on,
+                    })
+            })
+            .collect())
+    }
+
+    /// Retrieves the next page of the character list, ordered by slot.
+    /// Also returns whether no characters remain after this page.
+    #[instrument(skip(self), level = "debug")]
+    pub async fn next_character_page(&mut self) -> DBResult<(Vec<Character>, bool)> {
+        let mut characters = self.get_characters().await?;
+        characters.sort_by_key(|char| char.slot);
+        let start = (self.character_page * CHARACTERS_PER_PAGE).min(characters.len());
+        let end = (start + CHARACTERS_PER_PAGE).min(characters.len());
+        self.character_page += 1;
+        let is_last = end == characters.len();
+        Ok((characters.drain(start..end).collect(), is_last))
+    }
+
     #[instrument(skip(self), level = "debug")]
     pub async fn create_character(
-        &self,
+        &mut self,
         new_character: NewCharacter,
     ) -> Result<Character, CharCreationError> {
         let NewCharacter {
@@ -120,62 +481,114 @@ impl CharacterSession {
             class,
             sex,
         } = new_character;
-        if slot as usize >= MAX_CHARACTERS_PER_ACCOUNT {
-            return Err(CharCreationError::InvalidSlot(new_character.slot));
+        if !self.slots.contains(slot) {
+            return Err(CharCreationError::InvalidSlot(slot));
+        }
+        if self.requires_captcha() {
+            return Err(CharCreationError::CaptchaRequired);
+        }
+        self.config.name_policy.check(name.as_str())?;
+        if let Some(stats) = stats {
+            let limits = &self.config.stat_allocation;
+            let total: u16 = stats.iter().map(u16::from).sum();
+            if total != limits.total_points
+                || stats
+                    .iter()
+                    .any(|stat| stat < 1 || stat > limits.max_per_stat)
+            {
+                return Err(CharCreationError::InvalidStats(stats));
+            }
         }
 
         // Check preconditions
-        let account_id = self.account_info.unwrap().account_id;
-        if self
+        let account_info = self
+            .account_info
+            .ok_or(CharCreationError::UnAuthenticated)?;
+        let account_id = account_info.account_id;
+        let characters = self
             .character_db
             .get_by_account_id(account_id)
             .await
             .map_err(|err| {
                 error!(account_id = %account_id, %err, "Could not retrieve characters");
                 CharCreationError::NoSuchAccount(account_id)
-            })?
-            .len()
-            >= MAX_CHARACTERS_PER_ACCOUNT
+            })?;
+        if characters.len() >= self.slots.total() as usize {
+            return Err(CharCreationError::TooManyCharacters(self.slots.total()));
+        }
+        if characters.iter().any(|char| char.slot == slot as u16) {
+            return Err(CharCreationError::SlotOccupied(slot));
+        }
+        let template = self
+            .config
+            .starting_characters
+            .template(class)
+            .ok_or(CharCreationError::InvalidClass(class))?;
+
+        // Build the whole character first, the DB assigns its ID
+        let mut char = Character::new(0, account_id);
+        char.name = name;
+        char.slot = slot as u16;
+        if let Some(stats) = stats {
+            char.stats = stats.into();
+            // The points were spent during creation
+            char.experience.status_points = 0;
+        } else if let Some(stats) = template.stats {
+            char.stats = stats.into();
+        }
+        char.currency.zeny = template.zeny.unwrap_or(char.currency.zeny);
+        char.experience.base_level = template.base_level.unwrap_or(char.experience.base_level);
+        char.experience.job_level = template.job_level.unwrap_or(char.experience.job_level);
+        char.location = template.location;
+        if template.save.is_some() {
+            char.location.save = template.save;
+        }
+        char.skills = template.skills.clone();
+        for item in template
+            .items
+            .iter()
+            .filter(|item| item.equipped_slot.is_some())
         {
-            return Err(CharCreationError::TooManyCharacters(
-                MAX_CHARACTERS_PER_ACCOUNT as u8,
-            ));
+            char.equipment.equip(item);
         }
-        // Just ignore the slot & index ourselves
-        let starting_config = match class {
-            attributes::Class::Novice => Ok(&self.starting_char_config.novice),
-            attributes::Class::Summoner => Ok(&self.starting_char_config.doram),
-            invalid => Err(CharCreationError::InvalidClass(invalid)),
-        }?;
+        char.appearance = appearance;
+        char.class = class;
+        char.sex = sex.unwrap_or(account_info.sex);
+        char.settings.slot_moves = self.config.character_slots.max_moves;
 
-        // Create empty character
-        let char_id = self.character_db.create(account_id).await?;
+        // Store the character, which fails if its name or slot got claimed in the meantime
+        char.id = self
+            .character_db
+            .create(char.clone())
+            .await
+            .map_err(|err| match err {
+                CharacterDBError::NameTaken(name) => CharCreationError::NameTaken(name),
+                CharacterDBError::SlotOccupied(slot) => CharCreationError::SlotOccupied(slot),
+                err => CharCreationError::CharacterDB(err),
+            })?;
 
         // Initialize inventory
-        let mut inventory = Inventory::new(char_id);
-        inventory.items = starting_config.items.clone();
+        let mut inventory = Inventory::new(char.id);
+        inventory.items = template.items.clone();
         self.inventory_db.create(inventory).await?;
 
-        // Retrieve character
-        let mut char = self.character_db.get_by_id(char_id).await?;
-
-        // Update with creation info
-        char.name = name;
-        char.slot = slot as u16;
-        char.stats = stats;
-        char.appearance = appearance;
-        char.class = class;
-        char.sex = sex;
-        self.character_db.update(&char).await?;
+        self.captcha_guard.record_creation(self.ip_addr);
+        self.captcha.solved = false;
 
         Ok(char)
     }
 
-    pub async fn select_character(&self, slot: u8) -> Result<Character, CharSelectionError> {
+    pub async fn select_character(&mut self, slot: u8) -> Result<Character, CharSelectionError> {
         let account_id = self
             .account_info
             .ok_or(CharSelectionError::UnAuthenticated)?
             .account_id;
+        if !self.pincode.verified {
+            return Err(CharSelectionError::PincodeNotVerified);
+        }
+        if self.requires_captcha() {
+            return Err(CharSelectionError::CaptchaRequired);
+        }
         let char = self
             .character_db
             .get_by_slot(account_id, slot)
@@ -184,14 +597,101 @@ impl CharacterSession {
         if char.status.delete_date.is_some() {
             return Err(CharSelectionError::Deleted);
         }
-        if let Some(time) = char.status.unban_on {
-            if time < SystemTime::now() {
-                return Err(CharSelectionError::Banned);
-            }
+        if let Some(ban) = char.status.ban.as_ref().filter(|ban| ban.is_active()) {
+            debug!(character_id = %char.id, reason = %ban.reason, "Character is banned");
+            return Err(CharSelectionError::Banned);
         }
         if char.status.option & 1 > 0 {
             return Err(CharSelectionError::RetrievingGuildBoundItems);
         }
+        self.captcha.solved = false;
         Ok(char)
     }
+
+    #[instrument(skip(self), level = "debug")]
+    pub async fn move_character_slot(&self, from: u8, to: u8) -> Result<u16, SlotMoveError> {
+        let account_id = self
+            .account_info
+            .ok_or(SlotMoveError::UnAuthenticated)?
+            .account_id;
+        if !CHARACTER_SLOT_MOVE_ENABLED {
+            return Err(SlotMoveError::Disabled);
+        }
+        if !self.slots.contains(to) {
+            return Err(SlotMoveError::InvalidSlot(to));
+        }
+        self.character_db
+            .move_slot(account_id, from, to)
+            .await
+            .map_err(|err| match err {
+                CharacterDBError::NoSuchSlot(slot) => SlotMoveError::NoSuchSlot(slot),
+                CharacterDBError::NoMovesLeft(slot) => SlotMoveError::NoMovesLeft(slot),
+                err => SlotMoveError::CharacterDB(err),
+            })
+    }
+
+    pub async fn remaining_slot_moves(&self, slot: u8) -> u16 {
+        match self.account_info {
+            Some(info) => self
+                .character_db
+                .get_by_slot(info.account_id, slot)
+                .await
+                .map(|char| char.settings.slot_moves)
+                .unwrap_or_default(),
+            None => 0,
+        }
+    }
+
+    pub fn account_id(&self) -> Option<AccountId> {
+        self.account_info.map(|info| info.account_id)
+    }
+
+    /// Whether the client has to solve a captcha before creating or selecting characters
+    pub fn requires_captcha(&self) -> bool {
+        !self.captcha.solved && self.captcha_guard.is_suspicious(self.ip_addr)
+    }
+
+    #[instrument(skip(self), level = "debug")]
+    pub fn request_captcha(&mut self, account_id: AccountId) -> Result<Vec<u8>, CaptchaError> {
+        self.check_captcha_account(account_id)?;
+        let captcha = self.captcha_guard.generate();
+        let image = captcha.image.clone();
+        self.captcha.answer = Some(captcha);
+        Ok(image)
+    }
+
+    #[instrument(skip(self), level = "debug")]
+    pub fn check_captcha(
+        &mut self,
+        account_id: AccountId,
+        answer: &str,
+    ) -> Result<bool, CaptchaError> {
+        self.check_captcha_account(account_id)?;
+        // Every captcha can only be answered once
+        let is_solved = self
+            .captcha
+            .answer
+            .take()
+            .is_some_and(|captcha| captcha.is_solved_by(answer));
+        if is_solved {
+            self.captcha.solved = true;
+            self.captcha.failures = 0;
+            return Ok(true);
+        }
+
+        self.captcha.failures += 1;
+        warn!(ip = %self.ip_addr, failures = self.captcha.failures, "Incorrect captcha");
+        if self.captcha.failures >= self.config.captcha.max_attempts {
+            return Err(CaptchaError::TooManyAttempts);
+        }
+        Ok(false)
+    }
+
+    fn check_captcha_account(&self, account_id: AccountId) -> Result<(), CaptchaError> {
+        let info = self.account_info.ok_or(CaptchaError::UnAuthenticated)?;
+        if info.account_id != account_id {
+            return Err(CaptchaError::AccountMismatch(account_id));
+        }
+        Ok(())
+    }
 }
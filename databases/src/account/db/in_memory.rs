@@ -125,4 +125,11 @@ impl AccountDB for InMemoryAccountDB {
         }
         todo!()
     }
+
+    async fn flush(&self) -> DBResult<()> {
+        if self.verbose {
+            debug!("Nothing to flush, accounts are only kept in memory");
+        }
+        Ok(())
+    }
 }
@@ -0,0 +1,38 @@
+use api::{account::db::AccountId, character::AccountInfo};
+use dashmap::DashMap;
+use tracing::debug;
+
+/// A successful login, as handed over by the login server
+#[derive(Debug, Clone, Copy)]
+pub struct Authentication {
+    pub account_info: AccountInfo,
+    /// Character slots of the account, premium ones included.
+    /// `None` leaves the account with the character server's normal slots.
+    pub char_slots: Option<u8>,
+}
+
+/// Logins the login server hands over to the character server,
+/// which is why both servers have to share it
+#[derive(Default)]
+pub struct AuthenticationDB {
+    accounts: DashMap<AccountId, Authentication>,
+}
+
+impl AuthenticationDB {
+    /// Registers a successful login, replacing the account's previous one
+    pub fn authenticate(&self, authentication: Authentication) {
+        let account_id = authentication.account_info.account_id;
+        debug!(%account_id, "Handing over login");
+        self.accounts.insert(account_id, authentication);
+    }
+
+    /// Takes the login matching the account info the client presents, if the login server
+    /// handed one over. A login can only be used once.
+    pub fn claim(&self, account_info: AccountInfo) -> Option<Authentication> {
+        self.accounts
+            .remove_if(&account_info.account_id, |_, login| {
+                login.account_info == account_info
+            })
+            .map(|(_, authentication)| authentication)
+    }
+}
@@ -2,7 +2,7 @@ use api::{
     account::db::AccountId,
     character::{
         db::{CharacterDB, CharacterId, DBError, DBResult},
-        Character,
+        Character, CharacterName,
     },
 };
 use async_std::sync::RwLock;
@@ -13,6 +13,12 @@ pub struct InMemoryCharacterDB {
     verbose: bool,
     characters: RwLock<HashMap<CharacterId, Character>>,
     accounts: RwLock<HashMap<AccountId, Vec<CharacterId>>>,
+    /// Secondary index of lowercase names, to keep names unique regardless of case
+    names: RwLock<HashMap<String, CharacterId>>,
+}
+
+fn name_key(name: &CharacterName) -> String {
+    name.as_str().to_lowercase()
 }
 
 impl InMemoryCharacterDB {
@@ -21,6 +27,7 @@ impl InMemoryCharacterDB {
             verbose,
             characters: RwLock::new(HashMap::new()),
             accounts: RwLock::new(HashMap::new()),
+            names: RwLock::new(HashMap::new()),
         };
         s.init().await?;
         Ok(s)
@@ -41,22 +48,37 @@ impl CharacterDB for InMemoryCharacterDB {
         Ok(())
     }
 
-    async fn create(&self, account_id: AccountId) -> DBResult<CharacterId> {
+    async fn create(&self, mut character: Character) -> DBResult<CharacterId> {
+        let account_id = character.account_id;
+        let name = &character.name;
+        let slot = character.slot;
         if self.verbose {
-            debug!(%account_id, "Creating a new character");
+            debug!(%account_id, %name, slot, "Creating a new character");
+        }
+        // Hold on to the name index until the character exists, so the name can't be claimed twice
+        let mut names = self.names.write().await;
+        let name_entry = match names.entry(name_key(name)) {
+            Entry::Occupied(_) => return Err(DBError::NameTaken(name.clone())),
+            Entry::Vacant(e) => e,
+        };
+
+        // Likewise, the slot is checked & claimed under a single lock
+        let mut chars = self.characters.write().await;
+        if chars
+            .values()
+            .any(|c| c.account_id == account_id && c.slot == slot)
+        {
+            return Err(DBError::SlotOccupied(slot as u8));
         }
         let mut char_id = fastrand::u32(2_000_000..);
-        loop {
-            let mut chars = self.characters.write().await;
-            match chars.entry(char_id) {
-                Entry::Occupied(_) => {}
-                Entry::Vacant(e) => {
-                    e.insert(Character::new(char_id, account_id));
-                    break;
-                }
-            }
+        while chars.contains_key(&char_id) {
             char_id = fastrand::u32(2_000_000..);
         }
+        character.id = char_id;
+        chars.insert(char_id, character);
+        drop(chars);
+        name_entry.insert(char_id);
+        drop(names);
 
         match self.accounts.write().await.entry(account_id) {
             Entry::Occupied(mut chars) => {
@@ -70,6 +92,19 @@ impl CharacterDB for InMemoryCharacterDB {
     }
 
     async fn update(&self, character: &Character) -> DBResult<()> {
+        let mut names = self.names.write().await;
+        let key = name_key(&character.name);
+        match names.get(&key) {
+            Some(id) if *id != character.id => {
+                return Err(DBError::NameTaken(character.name.clone()));
+            }
+            Some(_) => {}
+            None => {
+                // The character was renamed, release its previous name
+                names.retain(|_, id| *id != character.id);
+                names.insert(key, character.id);
+            }
+        }
         self.characters
             .write()
             .await
@@ -126,4 +161,66 @@ impl CharacterDB for InMemoryCharacterDB {
             Err(DBError::NoSuchSlot(slot))
         }
     }
+
+    async fn get_by_name(&self, name: &CharacterName) -> DBResult<Character> {
+        let id = self
+            .names
+            .read()
+            .await
+            .get(&name_key(name))
+            .copied()
+            .ok_or_else(|| DBError::NoSuchName(name.clone()))?;
+        self.get_by_id(id).await
+    }
+
+    async fn move_slot(&self, account_id: AccountId, from: u8, to: u8) -> DBResult<u16> {
+        if self.verbose {
+            debug!(%account_id, from, to, "Moving character slot");
+        }
+        let char_ids = self
+            .accounts
+            .read()
+            .await
+            .get(&account_id)
+            .cloned()
+            .unwrap_or_default();
+
+        // Hold the write lock for both updates, so no two characters are ever seen on the same slot
+        // & concurrent moves can't spend the same move twice
+        let mut chars = self.characters.write().await;
+        let find_slot = |chars: &HashMap<CharacterId, Character>, slot: u8| {
+            char_ids
+                .iter()
+                .copied()
+                .find(|id| chars.get(id).is_some_and(|c| c.slot == slot as u16))
+        };
+        let source = find_slot(&chars, from).ok_or(DBError::NoSuchSlot(from))?;
+        let moves = chars
+            .get(&source)
+            .map(|c| c.settings.slot_moves)
+            .unwrap_or_default();
+        if from == to {
+            return Ok(moves);
+        }
+        if moves == 0 {
+            return Err(DBError::NoMovesLeft(from));
+        }
+        if let Some(target) = find_slot(&chars, to) {
+            if let Some(char) = chars.get_mut(&target) {
+                char.slot = from as u16;
+            }
+        }
+        if let Some(char) = chars.get_mut(&source) {
+            char.slot = to as u16;
+            char.settings.slot_moves -= 1;
+        }
+        Ok(moves - 1)
+    }
+
+    async fn flush(&self) -> DBResult<()> {
+        if self.verbose {
+            debug!("Nothing to flush, characters are only kept in memory");
+        }
+        Ok(())
+    }
 }
@@ -39,4 +39,11 @@ impl InventoryDB for InMemoryInventoryDB {
         self.db.insert(inventory.character_id, inventory.clone());
         Ok(())
     }
+
+    async fn flush(&self) -> DBResult<()> {
+        if self.verbose {
+            debug!("Nothing to flush, inventories are only kept in memory");
+        }
+        Ok(())
+    }
 }
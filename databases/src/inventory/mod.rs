@@ -18,4 +18,6 @@ pub trait InventoryDB {
     async fn create(&self, inventory: Inventory) -> DBResult<()>;
     async fn get(&self, character_id: CharacterId) -> DBResult<Inventory>;
     async fn update(&self, inventory: &Inventory) -> DBResult<()>;
+    /// Persists pending writes, before the server exits
+    async fn flush(&self) -> DBResult<()>;
 }
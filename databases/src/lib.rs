@@ -1,3 +1,4 @@
 pub mod account;
+pub mod authentication;
 pub mod character;
 pub mod inventory;
@@ -0,0 +1,292 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::sync::Arc;
+
+use api::{
+    capture::Direction,
+    character::{CharacterCommand, Response as CharacterResponse},
+    error::PacketError,
+    login::{Error as LoginError, LoginCommand, Response as LoginResponse},
+    map::Maps,
+    packet_table::{PacketLength, PacketTable},
+    packet_version::PacketVersion,
+    utils::parse_word,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    Login,
+    Character,
+}
+
+/// Why a packet couldn't be dissected
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Issue {
+    /// The opcode isn't in the packet table, so the rest of the stream can't be framed
+    UnknownOpcode,
+    /// The packet is shorter than the table or the codec expects
+    LengthMismatch(String),
+    DecodeError(String),
+    /// The codec knows the packet, but can't parse it yet
+    Unsupported,
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownOpcode => write!(f, "UNKNOWN OPCODE"),
+            Self::LengthMismatch(reason) => write!(f, "LENGTH MISMATCH: {}", reason),
+            Self::DecodeError(reason) => write!(f, "DECODE ERROR: {}", reason),
+            Self::Unsupported => write!(f, "UNSUPPORTED by the codec"),
+        }
+    }
+}
+
+pub struct Packet {
+    /// Absent for the bare account ID the char server answers a connection with
+    pub opcode: Option<u16>,
+    pub name: String,
+    pub length: usize,
+    pub fields: String,
+    pub issue: Option<Issue>,
+}
+
+impl Packet {
+    fn flagged(opcode: Option<u16>, length: usize, issue: Issue) -> Self {
+        Self {
+            opcode,
+            name: "?".to_string(),
+            length,
+            fields: String::new(),
+            issue: Some(issue),
+        }
+    }
+}
+
+/// Splits one direction of a connection into packets & decodes them
+pub struct StreamDissector {
+    protocol: Protocol,
+    direction: Direction,
+    version: PacketVersion,
+    maps: Arc<Maps>,
+    table: PacketTable,
+    buffer: Vec<u8>,
+    /// The char server answers `ConnectClient` with the bare account ID, without an opcode
+    awaiting_account_id: bool,
+    /// Bytes dropped after the stream couldn't be framed anymore
+    skipped: Option<usize>,
+}
+
+impl StreamDissector {
+    pub fn new(
+        protocol: Protocol,
+        direction: Direction,
+        version: PacketVersion,
+        maps: Arc<Maps>,
+    ) -> Self {
+        let table = match (protocol, direction) {
+            (Protocol::Login, Direction::Inbound) => PacketTable::login(version),
+            (Protocol::Login, Direction::Outbound) => PacketTable::login_responses(version),
+            (Protocol::Character, Direction::Inbound) => PacketTable::character(version),
+            (Protocol::Character, Direction::Outbound) => PacketTable::character_responses(version),
+        };
+        Self {
+            protocol,
+            direction,
+            version,
+            maps,
+            table,
+            buffer: vec![],
+            awaiting_account_id: false,
+            skipped: None,
+        }
+    }
+
+    pub fn expect_account_id(&mut self) {
+        self.awaiting_account_id = true;
+    }
+
+    /// Gives up on framing the rest of the stream, e.g. after a gap in a capture
+    pub fn lose_sync(&mut self) {
+        let buffered = self.buffer.len();
+        self.buffer.clear();
+        *self.skipped.get_or_insert(0) += buffered;
+    }
+
+    /// Dissects every complete packet once `data` is appended to the stream
+    pub fn push(&mut self, data: &[u8]) -> Vec<Packet> {
+        if let Some(skipped) = &mut self.skipped {
+            *skipped += data.len();
+            return vec![];
+        }
+        self.buffer.extend_from_slice(data);
+        let mut packets = vec![];
+        while let Some(packet) = self.next_packet() {
+            if packet.issue == Some(Issue::UnknownOpcode) {
+                self.skipped = Some(0);
+            }
+            self.buffer.drain(..packet.length);
+            packets.push(packet);
+            if self.skipped.is_some() {
+                break;
+            }
+        }
+        packets
+    }
+
+    /// Reports what's left at the end of the stream
+    pub fn finish(&mut self) -> Option<String> {
+        match self.skipped {
+            Some(skipped) if skipped > 0 => Some(format!(
+                "{} byte(s) not dissected after losing sync",
+                skipped
+            )),
+            Some(_) => None,
+            None if !self.buffer.is_empty() => Some(format!(
+                "LENGTH MISMATCH: {} trailing byte(s) don't form a whole packet: {:02x?}",
+                self.buffer.len(),
+                self.buffer
+            )),
+            None => None,
+        }
+    }
+
+    fn next_packet(&mut self) -> Option<Packet> {
+        if self.awaiting_account_id {
+            if self.buffer.len() < 4 {
+                return None;
+            }
+            self.awaiting_account_id = false;
+            let mut account_id = [0u8; 4];
+            account_id.copy_from_slice(&self.buffer[..4]);
+            return Some(Packet {
+                opcode: None,
+                name: "AccountConnected".to_string(),
+                length: 4,
+                fields: format!("account_id: {}", u32::from_le_bytes(account_id)),
+                issue: None,
+            });
+        }
+        if self.buffer.len() < 2 {
+            return None;
+        }
+
+        let opcode = parse_word(&self.buffer[..2]);
+        let length = match self.table.length(opcode) {
+            Some(PacketLength::Fixed(length)) => length,
+            Some(PacketLength::Variable) => {
+                if self.buffer.len() < 4 {
+                    return None;
+                }
+                let length = parse_word(&self.buffer[2..4]) as usize;
+                if length < 4 {
+                    let reason = format!("declared length {} is shorter than its header", length);
+                    // Without a usable length, nothing after this packet can be framed
+                    self.skipped = Some(0);
+                    return Some(Packet::flagged(
+                        Some(opcode),
+                        self.buffer.len(),
+                        Issue::LengthMismatch(reason),
+                    ));
+                }
+                length
+            }
+            None => {
+                return Some(Packet::flagged(
+                    Some(opcode),
+                    self.buffer.len(),
+                    Issue::UnknownOpcode,
+                ))
+            }
+        };
+        if self.buffer.len() < length {
+            return None;
+        }
+
+        let (name, fields, issue) = match self.decode(opcode, &self.buffer[2..length]) {
+            Ok((name, fields)) => (name, fields, None),
+            Err(issue) => {
+                let name = self.request_name(opcode).unwrap_or_else(|| "?".to_string());
+                (name, String::new(), Some(issue))
+            }
+        };
+        Some(Packet {
+            opcode: Some(opcode),
+            name,
+            length,
+            fields,
+            issue,
+        })
+    }
+
+    /// Name of a request's command, known even if its payload can't be decoded
+    fn request_name(&self, opcode: u16) -> Option<String> {
+        match (self.protocol, self.direction) {
+            (Protocol::Login, Direction::Inbound) => LoginCommand::try_from(opcode)
+                .ok()
+                .map(|command| format!("{:?}", command)),
+            (Protocol::Character, Direction::Inbound) => CharacterCommand::try_from(opcode)
+                .ok()
+                .map(|command| format!("{:?}", command)),
+            _ => None,
+        }
+    }
+
+    /// Decodes a payload into the packet's name & fields
+    fn decode(&self, opcode: u16, payload: &[u8]) -> Result<(String, String), Issue> {
+        match (self.protocol, self.direction) {
+            (Protocol::Login, Direction::Inbound) => {
+                let command = LoginCommand::try_from(opcode).map_err(login_issue)?;
+                let request = command.parse(payload).map_err(login_issue)?;
+                Ok((format!("{:?}", command), format!("{:?}", request)))
+            }
+            (Protocol::Login, Direction::Outbound) => {
+                let response =
+                    LoginResponse::parse(opcode, self.version, payload).map_err(packet_issue)?;
+                Ok(describe(&response))
+            }
+            (Protocol::Character, Direction::Inbound) => {
+                let command = CharacterCommand::try_from(opcode).map_err(packet_issue)?;
+                let request = command.parse(payload).map_err(packet_issue)?;
+                Ok((format!("{:?}", command), format!("{:?}", request)))
+            }
+            (Protocol::Character, Direction::Outbound) => {
+                let response = CharacterResponse::parse(opcode, &self.maps, self.version, payload)
+                    .map_err(packet_issue)?;
+                Ok(describe(&response))
+            }
+        }
+    }
+}
+
+/// Splits a response's debug output into its variant name & fields
+fn describe<T: fmt::Debug>(response: &T) -> (String, String) {
+    let fields = format!("{:?}", response);
+    let name = fields
+        .split(|c: char| !c.is_alphanumeric() && c != '_')
+        .next()
+        .unwrap_or_default()
+        .to_string();
+    (name, fields)
+}
+
+fn packet_issue(err: PacketError) -> Issue {
+    match err {
+        PacketError::InvalidCommand(_) => Issue::Unsupported,
+        PacketError::PacketIncomplete(count) => {
+            Issue::LengthMismatch(format!("the codec needs {} more byte(s)", count))
+        }
+        PacketError::InvalidRequest(reason) => Issue::DecodeError(reason),
+        PacketError::UnsupportedRequest(_) => Issue::Unsupported,
+    }
+}
+
+fn login_issue(err: LoginError) -> Issue {
+    match err {
+        LoginError::InvalidCommand(_) | LoginError::UnsupportedRequest(_) => Issue::Unsupported,
+        LoginError::PacketIncomplete(count) => {
+            Issue::LengthMismatch(format!("the codec needs {} more byte(s)", count))
+        }
+        err => Issue::DecodeError(err.to_string()),
+    }
+}
@@ -0,0 +1,36 @@
+use anyhow::anyhow;
+
+/// Parses plain hex (any whitespace, optional `0x` prefixes & commas),
+/// `xxd` output or `hexdump -C` output
+pub fn parse(text: &str) -> Result<Vec<u8>, anyhow::Error> {
+    let mut bytes = vec![];
+    for line in text.lines() {
+        let mut line = line.trim();
+        let first = line.split_whitespace().next().unwrap_or_default();
+        if first.ends_with(':') {
+            // xxd: offset, hex groups & the ASCII column after two spaces
+            line = line[first.len()..].trim_start();
+            line = line.split("  ").next().unwrap_or_default();
+        } else if line.contains('|') && first.len() == 8 {
+            // hexdump -C: offset, hex bytes & the ASCII column between bars
+            line = line[first.len()..].split('|').next().unwrap_or_default();
+        }
+
+        let digits: String = line
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .map(|token| token.trim_start_matches("0x"))
+            .collect();
+        if !digits.is_ascii() {
+            return Err(anyhow!("Invalid hex in line: {}", line));
+        }
+        if !digits.len().is_multiple_of(2) {
+            return Err(anyhow!("Odd number of hex digits in line: {}", line));
+        }
+        for index in (0..digits.len()).step_by(2) {
+            let byte = u8::from_str_radix(&digits[index..index + 2], 16)
+                .map_err(|_| anyhow!("Invalid hex in line: {}", line))?;
+            bytes.push(byte);
+        }
+    }
+    Ok(bytes)
+}
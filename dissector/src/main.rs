@@ -0,0 +1,291 @@
+use std::collections::HashMap;
+use std::io::Read;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use serde::Deserialize;
+
+use crate::dissect::{Packet, Protocol, StreamDissector};
+use api::{capture::Direction, map::Maps, packet_version::PacketVersion};
+
+mod dissect;
+mod hexdump;
+mod pcap;
+
+#[derive(Deserialize, Debug)]
+pub struct Config {
+    /// Client version the packets are decoded for
+    pub packet_version: PacketVersion,
+    pub maps: MapConfig,
+    pub ports: PortConfig,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct MapConfig {
+    pub names_file: String,
+}
+
+/// Server ports the TCP connections of a pcap are assigned to a protocol by
+#[derive(Deserialize, Debug)]
+pub struct PortConfig {
+    pub login: Vec<u16>,
+    pub character: Vec<u16>,
+}
+
+pub fn init_config() -> Result<Config, impl std::error::Error> {
+    let mut settings = ::config::Config::default();
+    settings
+        .merge(::config::File::with_name("config"))?
+        .merge(::config::Environment::with_prefix("APP"))?;
+    settings.try_into()
+}
+
+const USAGE: &str = "Usage:
+    dissector hex <login|char> <requests|responses> <file|->
+    dissector pcap <file>";
+
+fn main() -> Result<(), anyhow::Error> {
+    let config = init_config()?;
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let maps = Arc::new(Maps::from_file(&config.maps.names_file)?);
+
+    let mut summary = Summary::default();
+    match args.as_slice() {
+        [mode, protocol, direction, file] if mode == "hex" => {
+            let protocol = match protocol.as_str() {
+                "login" => Protocol::Login,
+                "char" => Protocol::Character,
+                _ => return Err(anyhow!(USAGE)),
+            };
+            let direction = match direction.as_str() {
+                "requests" => Direction::Inbound,
+                "responses" => Direction::Outbound,
+                _ => return Err(anyhow!(USAGE)),
+            };
+            let text = if file == "-" {
+                let mut text = String::new();
+                std::io::stdin().read_to_string(&mut text)?;
+                text
+            } else {
+                std::fs::read_to_string(file)?
+            };
+            let mut stream = StreamDissector::new(protocol, direction, config.packet_version, maps);
+            for packet in stream.push(&hexdump::parse(&text)?) {
+                summary.print("", direction, &packet);
+            }
+            if let Some(remainder) = stream.finish() {
+                summary.note("", &remainder);
+            }
+        }
+        [mode, file] if mode == "pcap" => {
+            let segments = pcap::read_segments(&std::fs::read(file)?)?;
+            dissect_pcap(&config, maps, &segments, &mut summary);
+        }
+        _ => return Err(anyhow!(USAGE)),
+    }
+    summary.print_totals();
+    Ok(())
+}
+
+/// Both directions of a TCP connection to a server
+struct Connection {
+    id: usize,
+    protocol: Protocol,
+    requests: StreamDissector,
+    responses: StreamDissector,
+    /// Next sequence number expected from the client & the server
+    next_sequence: [Option<u32>; 2],
+}
+
+fn dissect_pcap(
+    config: &Config,
+    maps: Arc<Maps>,
+    segments: &[pcap::Segment],
+    summary: &mut Summary,
+) {
+    let start = segments
+        .first()
+        .map(|segment| segment.timestamp)
+        .unwrap_or_default();
+    let mut connections: HashMap<(SocketAddr, SocketAddr), Connection> = HashMap::new();
+    for segment in segments {
+        let protocol_of = |port| {
+            if config.ports.login.contains(&port) {
+                Some(Protocol::Login)
+            } else if config.ports.character.contains(&port) {
+                Some(Protocol::Character)
+            } else {
+                None
+            }
+        };
+        let (client, server, direction, protocol) =
+            if let Some(protocol) = protocol_of(segment.destination.port()) {
+                (
+                    segment.source,
+                    segment.destination,
+                    Direction::Inbound,
+                    protocol,
+                )
+            } else if let Some(protocol) = protocol_of(segment.source.port()) {
+                (
+                    segment.destination,
+                    segment.source,
+                    Direction::Outbound,
+                    protocol,
+                )
+            } else {
+                continue;
+            };
+
+        let count = connections.len();
+        let connection = connections.entry((client, server)).or_insert_with(|| {
+            summary.note(
+                &format!("#{}", count),
+                &format!("{:?} connection {} -> {}", protocol, client, server),
+            );
+            Connection {
+                id: count,
+                protocol,
+                requests: StreamDissector::new(
+                    protocol,
+                    Direction::Inbound,
+                    config.packet_version,
+                    maps.clone(),
+                ),
+                responses: StreamDissector::new(
+                    protocol,
+                    Direction::Outbound,
+                    config.packet_version,
+                    maps.clone(),
+                ),
+                next_sequence: [None, None],
+            }
+        });
+        let label = format!(
+            "{:>12.6} #{}",
+            (segment.timestamp.checked_sub(start).unwrap_or_default()).as_secs_f64(),
+            connection.id
+        );
+
+        let (next_sequence, stream) = match direction {
+            Direction::Inbound => (&mut connection.next_sequence[0], &mut connection.requests),
+            Direction::Outbound => (&mut connection.next_sequence[1], &mut connection.responses),
+        };
+        let payload = match in_sequence(next_sequence, segment) {
+            Ok(payload) => payload,
+            Err(missing) => {
+                summary.note(
+                    &label,
+                    &format!("{} byte(s) missing from the capture, losing sync", missing),
+                );
+                stream.lose_sync();
+                continue;
+            }
+        };
+        let packets = stream.push(payload);
+        for packet in &packets {
+            summary.print(&label, direction, packet);
+        }
+        let connecting = packets
+            .iter()
+            .any(|packet| packet.name == "ConnectClient" && packet.issue.is_none());
+        if connection.protocol == Protocol::Character && connecting {
+            connection.responses.expect_account_id();
+        }
+    }
+
+    let mut connections: Vec<_> = connections.into_values().collect();
+    connections.sort_by_key(|connection| connection.id);
+    for mut connection in connections {
+        let label = format!("#{}", connection.id);
+        for stream in [&mut connection.requests, &mut connection.responses].iter_mut() {
+            if let Some(remainder) = stream.finish() {
+                summary.note(&label, &remainder);
+            }
+        }
+    }
+}
+
+/// Returns the new part of a segment's payload, or the number of bytes missing before it
+fn in_sequence<'a>(
+    next_sequence: &mut Option<u32>,
+    segment: &'a pcap::Segment,
+) -> Result<&'a [u8], u32> {
+    if segment.syn {
+        *next_sequence = Some(segment.sequence.wrapping_add(1));
+        return Ok(&[]);
+    }
+    let expected = *next_sequence.get_or_insert(segment.sequence);
+    let offset = segment.sequence.wrapping_sub(expected) as i32;
+    let end = segment.sequence.wrapping_add(segment.payload.len() as u32);
+    if offset > 0 {
+        *next_sequence = Some(end);
+        return Err(offset as u32);
+    }
+    // Retransmissions overlap with what was already received
+    let overlap = (-offset) as usize;
+    if overlap >= segment.payload.len() {
+        return Ok(&[]);
+    }
+    *next_sequence = Some(end);
+    Ok(&segment.payload[overlap..])
+}
+
+#[derive(Default)]
+struct Summary {
+    packets: usize,
+    flagged: usize,
+    notes: usize,
+}
+
+impl Summary {
+    fn print(&mut self, label: &str, direction: Direction, packet: &Packet) {
+        self.packets += 1;
+        let arrow = match direction {
+            Direction::Inbound => "C>S",
+            Direction::Outbound => "S>C",
+        };
+        let opcode = packet
+            .opcode
+            .map(|opcode| format!("{:#06x}", opcode))
+            .unwrap_or_else(|| "------".to_string());
+        let issue = match &packet.issue {
+            Some(issue) => {
+                self.flagged += 1;
+                format!("  !! {}", issue)
+            }
+            None => String::new(),
+        };
+        println!(
+            "{}{} {} {:<26} {:>5} B  {}{}",
+            prefix(label),
+            arrow,
+            opcode,
+            packet.name,
+            packet.length,
+            packet.fields,
+            issue
+        );
+    }
+
+    fn note(&mut self, label: &str, note: &str) {
+        self.notes += 1;
+        println!("{}-- {}", prefix(label), note);
+    }
+
+    fn print_totals(&self) {
+        println!(
+            "{} packet(s), {} flagged, {} note(s)",
+            self.packets, self.flagged, self.notes
+        );
+    }
+}
+
+fn prefix(label: &str) -> String {
+    if label.is_empty() {
+        String::new()
+    } else {
+        format!("{} ", label)
+    }
+}
@@ -0,0 +1,152 @@
+//! Minimal reader for libpcap files, extracting the TCP segments of IPv4 & IPv6 packets
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+
+use anyhow::anyhow;
+
+const TCP: u8 = 6;
+
+pub struct Segment {
+    /// Since the UNIX epoch
+    pub timestamp: Duration,
+    pub source: SocketAddr,
+    pub destination: SocketAddr,
+    pub sequence: u32,
+    pub syn: bool,
+    pub payload: Vec<u8>,
+}
+
+fn word(data: &[u8], offset: usize, big_endian: bool) -> Option<u16> {
+    let bytes = [*data.get(offset)?, *data.get(offset + 1)?];
+    Some(if big_endian {
+        u16::from_be_bytes(bytes)
+    } else {
+        u16::from_le_bytes(bytes)
+    })
+}
+
+fn long(data: &[u8], offset: usize, big_endian: bool) -> Option<u32> {
+    let mut bytes = [0u8; 4];
+    bytes.copy_from_slice(data.get(offset..offset + 4)?);
+    Some(if big_endian {
+        u32::from_be_bytes(bytes)
+    } else {
+        u32::from_le_bytes(bytes)
+    })
+}
+
+/// Reads every TCP segment of a capture, skipping other & truncated packets
+pub fn read_segments(data: &[u8]) -> Result<Vec<Segment>, anyhow::Error> {
+    let (big_endian, nanos) = match long(data, 0, false) {
+        Some(0xa1b2_c3d4) => (false, false),
+        Some(0xa1b2_3c4d) => (false, true),
+        Some(0xd4c3_b2a1) => (true, false),
+        Some(0x4d3c_b2a1) => (true, true),
+        Some(0x0a0d_0d0a) => {
+            return Err(anyhow!(
+                "pcapng isn't supported, convert it with `editcap -F pcap`"
+            ))
+        }
+        _ => return Err(anyhow!("Not a pcap file")),
+    };
+    let link_type = long(data, 20, big_endian).ok_or_else(|| anyhow!("Truncated pcap header"))?;
+
+    let mut segments = vec![];
+    let mut offset = 24;
+    while let Some(captured) = long(data, offset + 8, big_endian) {
+        let seconds = long(data, offset, big_endian).unwrap_or_default();
+        let fraction = long(data, offset + 4, big_endian).unwrap_or_default();
+        let start = offset + 16;
+        let end = start + captured as usize;
+        let packet = match data.get(start..end) {
+            Some(packet) => packet,
+            None => break,
+        };
+        offset = end;
+
+        let fraction = if nanos {
+            Duration::from_nanos(fraction as u64)
+        } else {
+            Duration::from_micros(fraction as u64)
+        };
+        let timestamp = Duration::from_secs(seconds as u64) + fraction;
+        if let Some(segment) =
+            ip_packet(link_type, packet).and_then(|ip| tcp_segment(ip, timestamp))
+        {
+            segments.push(segment);
+        }
+    }
+    Ok(segments)
+}
+
+/// Strips the link layer, returning the IP packet
+fn ip_packet(link_type: u32, packet: &[u8]) -> Option<&[u8]> {
+    match link_type {
+        // BSD loopback, with the address family in host byte order
+        0 => packet.get(4..),
+        // Ethernet, possibly VLAN tagged
+        1 => {
+            let mut offset = 12;
+            while word(packet, offset, true)? == 0x8100 {
+                offset += 4;
+            }
+            packet.get(offset + 2..)
+        }
+        // Raw IP
+        12 | 14 | 101 => Some(packet),
+        // Linux cooked capture v1 & v2
+        113 => packet.get(16..),
+        276 => packet.get(20..),
+        _ => None,
+    }
+}
+
+fn tcp_segment(ip: &[u8], timestamp: Duration) -> Option<Segment> {
+    let (source, destination, tcp) = match ip.first()? >> 4 {
+        4 => {
+            let header_length = (ip[0] & 0x0f) as usize * 4;
+            let total_length = word(ip, 2, true)? as usize;
+            if *ip.get(9)? != TCP {
+                return None;
+            }
+            let mut source = [0u8; 4];
+            source.copy_from_slice(ip.get(12..16)?);
+            let mut destination = [0u8; 4];
+            destination.copy_from_slice(ip.get(16..20)?);
+            (
+                IpAddr::V4(Ipv4Addr::from(source)),
+                IpAddr::V4(Ipv4Addr::from(destination)),
+                ip.get(header_length..total_length.min(ip.len()))?,
+            )
+        }
+        6 => {
+            // Extension headers aren't supported
+            if *ip.get(6)? != TCP {
+                return None;
+            }
+            let payload_length = word(ip, 4, true)? as usize;
+            let mut source = [0u8; 16];
+            source.copy_from_slice(ip.get(8..24)?);
+            let mut destination = [0u8; 16];
+            destination.copy_from_slice(ip.get(24..40)?);
+            (
+                IpAddr::V6(Ipv6Addr::from(source)),
+                IpAddr::V6(Ipv6Addr::from(destination)),
+                ip.get(40..(40 + payload_length).min(ip.len()))?,
+            )
+        }
+        _ => return None,
+    };
+
+    let data_offset = (*tcp.get(12)? >> 4) as usize * 4;
+    let flags = *tcp.get(13)?;
+    Some(Segment {
+        timestamp,
+        source: SocketAddr::new(source, word(tcp, 0, true)?),
+        destination: SocketAddr::new(destination, word(tcp, 2, true)?),
+        sequence: long(tcp, 4, true)?,
+        syn: flags & 0x02 != 0,
+        payload: tcp.get(data_offset..)?.to_vec(),
+    })
+}
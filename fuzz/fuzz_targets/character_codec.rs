@@ -0,0 +1,22 @@
+#![no_main]
+use std::sync::{Arc, OnceLock};
+
+use libfuzzer_sys::fuzz_target;
+
+use api::{character::CharacterCodec, map::Maps};
+use fuzz::{decode_all, VERSIONS};
+
+fn maps() -> Arc<Maps> {
+    static MAPS: OnceLock<Arc<Maps>> = OnceLock::new();
+    MAPS.get_or_init(|| {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/../resources/maps.yaml");
+        Arc::new(Maps::from_file(path).expect("Could not load maps"))
+    })
+    .clone()
+}
+
+fuzz_target!(|data: &[u8]| {
+    for version in VERSIONS {
+        decode_all(&mut CharacterCodec::new(maps(), *version), data);
+    }
+});
@@ -0,0 +1,11 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+
+use api::login::LoginCodec;
+use fuzz::{decode_all, VERSIONS};
+
+fuzz_target!(|data: &[u8]| {
+    for version in VERSIONS {
+        decode_all(&mut LoginCodec::new(*version), data);
+    }
+});
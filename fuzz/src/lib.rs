@@ -0,0 +1,34 @@
+//! Helpers shared by the fuzz targets, which feed arbitrary bytes to the server codecs.
+//! Run a target with `cargo +nightly fuzz run login_codec`, starting from the packets in `corpus/`.
+
+use async_codec::{Decode, DecodeResult};
+
+use api::packet_version::PacketVersion;
+
+/// One client version per era of packet layouts, so every packet table gets exercised
+pub const VERSIONS: &[PacketVersion] = &[
+    PacketVersion::new(20090000),
+    PacketVersion::new(20100413),
+    PacketVersion::new(20120307),
+    PacketVersion::new(20130522),
+    PacketVersion::new(20151001),
+    PacketVersion::new(20180620),
+];
+
+/// Decodes `data` the way `Framed` does, until the codec needs more bytes or fails
+pub fn decode_all<D: Decode>(codec: &mut D, data: &[u8]) {
+    let mut buffer = data.to_vec();
+    let mut offset = 0;
+    while offset < buffer.len() {
+        let (consumed, result) = codec.decode(&mut buffer[offset..]);
+        assert!(
+            consumed <= buffer.len() - offset,
+            "Consumed more than the buffer holds"
+        );
+        offset += consumed;
+        match result {
+            DecodeResult::Ok(_) => {}
+            DecodeResult::UnexpectedEnd | DecodeResult::Err(_) => break,
+        }
+    }
+}
@@ -0,0 +1,96 @@
+//! Runs the login & character servers in a single process.
+//!
+//! Logins are handed over to the character server in memory, so both servers have to share the
+//! account & authentication DBs. Each server reads its own crate's config, so run the launcher
+//! from its crate directory.
+
+use std::{net::SocketAddr, sync::Arc};
+
+use futures_util::future::try_join;
+use tracing::info;
+
+use api::character::TcpServer as CharTcpServer;
+use api::config::login::{AccountDBConfig, Config as LoginConfig};
+use api::shutdown::ShutdownTrigger;
+use character::{config::Config as CharacterConfig, server::CharacterServer};
+use databases::account::db::InMemoryAccountDB;
+use databases::authentication::AuthenticationDB;
+use login::{agent::LoginAgent, server::LoginServer};
+
+fn load_config<T: serde::de::DeserializeOwned>(name: &str) -> Result<T, ::config::ConfigError> {
+    let mut settings = ::config::Config::default();
+    settings
+        .merge(::config::File::with_name(name))?
+        .merge(::config::Environment::with_prefix("APP"))?;
+    settings.try_into()
+}
+
+fn main() -> Result<(), anyhow::Error> {
+    // Initialize config
+    let login_config: LoginConfig = load_config("../login/config")?;
+    let character_config: CharacterConfig = load_config("../character/config")?;
+
+    // Initialize logging
+    tracing_subscriber::fmt()
+        .with_target(true)
+        .with_env_filter("debug")
+        .try_init()
+        .expect("Could not initialize logging");
+
+    info!("Running with login config:\n{:#?}", login_config);
+    info!("Running with character config:\n{:#?}", character_config);
+
+    // Drain the sessions of both servers on SIGINT & SIGTERM, instead of dropping them
+    let (shutdown_trigger, shutdown) = ShutdownTrigger::new();
+    ctrlc::set_handler(move || {
+        info!("Received termination signal");
+        shutdown_trigger.trigger();
+    })?;
+
+    let verbose = match login_config.account_db {
+        AccountDBConfig::InMemory { verbose } => verbose,
+        AccountDBConfig::SQL {} => {
+            todo!("Implement me");
+        }
+    };
+    let login_addr: SocketAddr = format!(
+        "{}:{}",
+        login_config.login_server.address, login_config.login_server.port
+    )
+    .parse()?;
+    let char_addr: SocketAddr = format!(
+        "{}:{}",
+        character_config.char_server.address, character_config.char_server.port
+    )
+    .parse()?;
+    // Clients are sent to the character server running here, whatever the login config lists
+    let char_servers = vec![Arc::new(CharTcpServer::new(&character_config.char_server))];
+
+    async_std::task::block_on(async {
+        let account_db = Arc::new(InMemoryAccountDB::new(verbose).await?);
+        let authentication_db = Arc::new(AuthenticationDB::default());
+
+        let character_server = CharacterServer::new()
+            .with_account_db(account_db.clone())
+            .with_authentication_db(authentication_db.clone())
+            .with_shutdown(shutdown.clone());
+        let login_server = LoginServer::new(
+            LoginAgent::new(account_db, authentication_db),
+            char_servers,
+            login_config.login_server.packet_version,
+        )
+        .with_capture(login_config.capture)
+        .with_shutdown(shutdown, login_config.shutdown);
+
+        try_join(character_server.run(character_config, char_addr), async {
+            login_server
+                .run(login_addr)
+                .await
+                .map_err(anyhow::Error::from)
+        })
+        .await
+    })?;
+
+    info!("Done...");
+    Ok(())
+}
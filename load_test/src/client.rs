@@ -0,0 +1,275 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::anyhow;
+use async_codec::Framed;
+use async_std::{net::TcpStream, task};
+use futures_util::SinkExt;
+use tracing::debug;
+
+use crate::config::MixConfig;
+use crate::stats::Stats;
+use api::{
+    character::{
+        attributes::{Appearance, Class},
+        AccountInfo, CharacterClientCodec, NewCharacter, Request as CharacterRequest,
+        Response as CharacterResponse, SlotAllowance, CHARACTERS_PER_PAGE,
+    },
+    login::{
+        LoginClientCodec, LoginCredentials, Request as LoginRequest, Response as LoginResponse,
+    },
+    map::Maps,
+    packet_version::PacketVersion,
+};
+use test_support::{connect, receive, TestAccount};
+
+type CharacterStream = Framed<TcpStream, CharacterClientCodec>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Operation {
+    Login,
+    ListCharacters,
+    CreateCharacter,
+}
+
+impl Operation {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Login => "login",
+            Self::ListCharacters => "list_characters",
+            Self::CreateCharacter => "create_character",
+        }
+    }
+}
+
+/// Where the virtual clients connect to
+#[derive(Clone)]
+pub struct Target {
+    pub login_addr: SocketAddr,
+    pub maps: Arc<Maps>,
+    pub packet_version: PacketVersion,
+}
+
+/// A connection to the char server, along with the account's state on it
+struct Connection {
+    stream: CharacterStream,
+    slots: SlotAllowance,
+    used_slots: Vec<u16>,
+    /// The server's position in the character list, which only restarts on a new connection
+    page: usize,
+}
+
+/// Repeatedly picks a random operation & runs it, like a player idling in the character selection
+pub struct VirtualClient {
+    id: usize,
+    account: TestAccount,
+    target: Target,
+    mix: MixConfig,
+    think_time: Duration,
+    connection: Option<Connection>,
+    created: usize,
+    stats: Stats,
+}
+
+impl VirtualClient {
+    pub fn new(
+        id: usize,
+        account: TestAccount,
+        target: Target,
+        mix: MixConfig,
+        think_time: Duration,
+    ) -> Self {
+        Self {
+            id,
+            account,
+            target,
+            mix,
+            think_time,
+            connection: None,
+            created: 0,
+            stats: Stats::default(),
+        }
+    }
+
+    /// Runs operations until `until`, then reports their latencies
+    pub async fn run(mut self, until: Instant) -> Stats {
+        while Instant::now() < until {
+            let operation = self.next_operation();
+            let started = Instant::now();
+            let result = match operation {
+                Operation::Login => self.login().await,
+                Operation::ListCharacters => self.list_characters().await,
+                Operation::CreateCharacter => self.create_character().await,
+            };
+            match result {
+                Ok(()) => self.stats.record(operation, Ok(started.elapsed())),
+                Err(err) => {
+                    debug!(client = self.id, operation = operation.name(), %err, "Operation failed");
+                    self.stats.record(operation, Err(()));
+                    // The connection's state is unknown, so the next operation logs in again
+                    self.connection = None;
+                }
+            }
+
+            let think_time = self.think_time.mul_f64(0.5 + fastrand::f64());
+            task::sleep(think_time).await;
+        }
+        self.stats
+    }
+
+    fn next_operation(&self) -> Operation {
+        let connection = match &self.connection {
+            Some(connection) => connection,
+            None => return Operation::Login,
+        };
+        let total = self.mix.login + self.mix.list_characters + self.mix.create_character;
+        let pick = fastrand::u32(..total);
+        if pick < self.mix.login {
+            Operation::Login
+        } else if pick < self.mix.login + self.mix.list_characters {
+            Operation::ListCharacters
+        } else if connection.used_slots.len() < connection.slots.total() as usize {
+            Operation::CreateCharacter
+        } else {
+            // The account is full, as characters are never deleted
+            Operation::ListCharacters
+        }
+    }
+
+    async fn login(&mut self) -> Result<(), anyhow::Error> {
+        self.connection = None;
+        let stream = connect(self.target.login_addr)?;
+        let mut stream = Framed::new(stream, LoginClientCodec::new(self.target.packet_version));
+        stream
+            .send(LoginRequest::ClientLogin(LoginCredentials::ClearText {
+                client_type: 0,
+                username: self.account.username.clone(),
+                password: self.account.password.clone(),
+            }))
+            .await?;
+        let info = match receive(&mut stream).await? {
+            LoginResponse::LoginSuccess(info) => info,
+            LoginResponse::LoginFailed(failure) => {
+                return Err(anyhow!("Login failed: {}", failure))
+            }
+            LoginResponse::LoginAborted(aborted) => {
+                return Err(anyhow!("Login aborted: {}", aborted))
+            }
+        };
+
+        let server = info
+            .char_servers
+            .iter()
+            .next()
+            .ok_or_else(|| anyhow!("No char server available"))?;
+        let stream = connect(server.address().into())?;
+        let codec = CharacterClientCodec::new(self.target.maps.clone(), self.target.packet_version);
+        let mut stream = Framed::new(stream, codec);
+        stream
+            .send(CharacterRequest::ConnectClient(AccountInfo {
+                account_id: info.account_id,
+                authentication_code: info.authentication_code,
+                user_level: info.user_level,
+                sex: info.sex,
+            }))
+            .await?;
+
+        // The server sends the account's state, ending with the pincode status
+        let mut account = None;
+        loop {
+            match receive(&mut stream).await? {
+                CharacterResponse::Rejected => return Err(anyhow!("Rejected by the char server")),
+                CharacterResponse::CharacterInfo { characters, slots } => {
+                    let used_slots = characters.iter().map(|character| character.slot).collect();
+                    account = Some((slots, used_slots));
+                }
+                CharacterResponse::PincodeInfo(_) => break,
+                _ => {}
+            }
+        }
+        let (slots, used_slots) = account.ok_or_else(|| anyhow!("No character info received"))?;
+        self.connection = Some(Connection {
+            stream,
+            slots,
+            used_slots,
+            page: 0,
+        });
+        Ok(())
+    }
+
+    async fn list_characters(&mut self) -> Result<(), anyhow::Error> {
+        let connection = self
+            .connection
+            .as_mut()
+            .ok_or_else(|| anyhow!("Not logged in"))?;
+        // Mirrors the server's paging, to know whether a full page is followed by an empty one
+        let count = connection.used_slots.len();
+        loop {
+            connection
+                .stream
+                .send(CharacterRequest::ListCharacters)
+                .await?;
+            let start = (connection.page * CHARACTERS_PER_PAGE).min(count);
+            let end = (start + CHARACTERS_PER_PAGE).min(count);
+            connection.page += 1;
+            let page = match receive(&mut connection.stream).await? {
+                CharacterResponse::Characters(page) => page,
+                _ => return Err(anyhow!("Unexpected response to the character list")),
+            };
+            if page.len() != end - start {
+                return Err(anyhow!(
+                    "Expected {} character(s), received {}",
+                    end - start,
+                    page.len()
+                ));
+            }
+            if end == count {
+                if page.len() == CHARACTERS_PER_PAGE {
+                    match receive(&mut connection.stream).await? {
+                        CharacterResponse::Characters(page) if page.is_empty() => {}
+                        _ => return Err(anyhow!("Expected the end of the character list")),
+                    }
+                }
+                return Ok(());
+            }
+        }
+    }
+
+    async fn create_character(&mut self) -> Result<(), anyhow::Error> {
+        let connection = self
+            .connection
+            .as_mut()
+            .ok_or_else(|| anyhow!("Not logged in"))?;
+        let slot = (0..connection.slots.total())
+            .find(|slot| !connection.used_slots.contains(&(*slot as u16)))
+            .ok_or_else(|| anyhow!("No free slot"))?;
+        // Names are unique per server, so they include the client
+        let name = format!("Load{}x{}", self.id, self.created);
+        self.created += 1;
+        connection
+            .stream
+            .send(CharacterRequest::CreateCharacter(NewCharacter {
+                name: name.into(),
+                slot,
+                stats: None,
+                appearance: Appearance {
+                    hair: 1,
+                    ..Default::default()
+                },
+                class: Class::Novice,
+                sex: None,
+            }))
+            .await?;
+        match receive(&mut connection.stream).await? {
+            CharacterResponse::NewCharacterInfo(character) => {
+                connection.used_slots.push(character.slot);
+                Ok(())
+            }
+            CharacterResponse::CreationRefused(reason) => {
+                Err(anyhow!("Creation refused: {:?}", reason))
+            }
+            _ => Err(anyhow!("Unexpected response to the character creation")),
+        }
+    }
+}
@@ -0,0 +1,27 @@
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Config {
+    pub load: LoadConfig,
+    pub mix: MixConfig,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct LoadConfig {
+    /// Number of virtual clients, each with its own account
+    pub clients: usize,
+    /// Clients are started evenly spread over this period
+    pub ramp_up_seconds: u64,
+    /// How long every client keeps running operations, including the ramp-up
+    pub duration_seconds: u64,
+    /// Pause between two operations of a client, randomized by +/- 50%
+    pub think_time_ms: u64,
+}
+
+/// Relative weight of each operation
+#[derive(Deserialize, Debug, Clone)]
+pub struct MixConfig {
+    pub login: u32,
+    pub list_characters: u32,
+    pub create_character: u32,
+}
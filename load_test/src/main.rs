@@ -0,0 +1,117 @@
+use std::time::{Duration, Instant};
+
+use anyhow::anyhow;
+use async_std::task;
+use tracing::info;
+
+use crate::client::{Target, VirtualClient};
+use crate::config::Config;
+use crate::stats::{OperationStats, Stats};
+use test_support::{TestAccount, TestServers};
+
+mod client;
+mod config;
+mod stats;
+
+pub fn init_config() -> Result<Config, impl std::error::Error> {
+    let mut settings = ::config::Config::default();
+    settings
+        .merge(::config::File::with_name("config"))?
+        .merge(::config::Environment::with_prefix("APP"))?;
+    settings.try_into()
+}
+
+fn main() -> Result<(), anyhow::Error> {
+    // Initialize config
+    let config = init_config()?;
+    let mix = &config.mix;
+    if mix.login + mix.list_characters + mix.create_character == 0 {
+        return Err(anyhow!("At least one operation needs a weight"));
+    }
+
+    // Initialize logging, the servers' own logs would drown out the report
+    tracing_subscriber::fmt()
+        .with_target(true)
+        .with_env_filter("warn,load_test=info")
+        .try_init()
+        .expect("Could not initialize logging");
+
+    info!("Running with config:\n{:#?}", config);
+
+    let (stats, elapsed) = task::block_on(run(&config))?;
+    print_report(&stats, elapsed);
+    Ok(())
+}
+
+async fn run(config: &Config) -> Result<(Stats, Duration), anyhow::Error> {
+    let load = &config.load;
+    let accounts: Vec<_> = (0..load.clients)
+        .map(|id| TestAccount::new(&format!("load{}", id), "load-test"))
+        .collect();
+    let servers = TestServers::start(&accounts).await?;
+    info!(
+        login = %servers.login.addr(),
+        character = %servers.character.addr(),
+        "Servers started"
+    );
+    let target = Target {
+        login_addr: servers.login.addr(),
+        maps: servers.maps.clone(),
+        packet_version: servers.packet_version,
+    };
+
+    let started = Instant::now();
+    let until = started + Duration::from_secs(load.duration_seconds);
+    let ramp_up = Duration::from_secs(load.ramp_up_seconds);
+    let think_time = Duration::from_millis(load.think_time_ms);
+    let clients: Vec<_> = accounts
+        .into_iter()
+        .enumerate()
+        .map(|(id, account)| {
+            let client =
+                VirtualClient::new(id, account, target.clone(), config.mix.clone(), think_time);
+            let delay = ramp_up.mul_f64(id as f64 / load.clients as f64);
+            task::spawn(async move {
+                task::sleep(delay).await;
+                client.run(until).await
+            })
+        })
+        .collect();
+
+    let mut stats = Stats::default();
+    for client in clients {
+        stats.merge(client.await);
+    }
+    let elapsed = started.elapsed();
+    servers.shutdown().await?;
+    Ok((stats, elapsed))
+}
+
+fn print_report(stats: &Stats, elapsed: Duration) {
+    println!(
+        "{:<20} {:>8} {:>8} {:>10} {:>10} {:>10}",
+        "operation", "count", "errors", "ops/s", "p50 ms", "p99 ms"
+    );
+    let print = |name: &str, stats: &OperationStats| {
+        let millis = |percentile| {
+            stats
+                .percentile(percentile)
+                .map(|latency: Duration| format!("{:.1}", latency.as_secs_f64() * 1000.0))
+                .unwrap_or_else(|| "-".to_string())
+        };
+        println!(
+            "{:<20} {:>8} {:>8} {:>10.1} {:>10} {:>10}",
+            name,
+            stats.count(),
+            stats.errors(),
+            stats.count() as f64 / elapsed.as_secs_f64(),
+            millis(50),
+            millis(99)
+        );
+    };
+    for (operation, operation_stats) in stats.operations() {
+        print(operation.name(), operation_stats);
+    }
+    print("total", &stats.total());
+    println!("over {:.1} s", elapsed.as_secs_f64());
+}
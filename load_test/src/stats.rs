@@ -0,0 +1,69 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use crate::client::Operation;
+
+/// Latencies & errors of a single operation
+#[derive(Default)]
+pub struct OperationStats {
+    latencies: Vec<Duration>,
+    errors: usize,
+}
+
+impl OperationStats {
+    pub fn count(&self) -> usize {
+        self.latencies.len() + self.errors
+    }
+
+    pub fn errors(&self) -> usize {
+        self.errors
+    }
+
+    /// Latency below which `percentile`% of the successful operations completed
+    pub fn percentile(&self, percentile: usize) -> Option<Duration> {
+        if self.latencies.is_empty() {
+            return None;
+        }
+        let mut sorted = self.latencies.clone();
+        sorted.sort_unstable();
+        let rank = (sorted.len() * percentile).div_ceil(100);
+        Some(sorted[rank.saturating_sub(1)])
+    }
+}
+
+/// Results of every client, by operation
+#[derive(Default)]
+pub struct Stats {
+    operations: BTreeMap<Operation, OperationStats>,
+}
+
+impl Stats {
+    pub fn record(&mut self, operation: Operation, result: Result<Duration, ()>) {
+        let stats = self.operations.entry(operation).or_default();
+        match result {
+            Ok(latency) => stats.latencies.push(latency),
+            Err(()) => stats.errors += 1,
+        }
+    }
+
+    pub fn merge(&mut self, other: Stats) {
+        for (operation, other) in other.operations {
+            let stats = self.operations.entry(operation).or_default();
+            stats.latencies.extend(other.latencies);
+            stats.errors += other.errors;
+        }
+    }
+
+    pub fn operations(&self) -> impl Iterator<Item = (&Operation, &OperationStats)> {
+        self.operations.iter()
+    }
+
+    pub fn total(&self) -> OperationStats {
+        let mut total = OperationStats::default();
+        for stats in self.operations.values() {
+            total.latencies.extend(&stats.latencies);
+            total.errors += stats.errors;
+        }
+        total
+    }
+}
@@ -1,10 +1,11 @@
 use api::{
-    account::db::{AccountDB, AccountId},
+    account::db::{AccountDB, AccountId, DBError},
     account::mmo_account::{AccountState, MmoAccount, Password},
     character::CharacterServer,
     login::{LoginCredentials, LoginFailed},
 };
 use dashmap::DashMap;
+use databases::authentication::{Authentication, AuthenticationDB};
 use std::{
     marker::PhantomData,
     sync::Arc,
@@ -18,6 +19,8 @@ where
     C: CharacterServer + Send + Sync + 'static,
 {
     account_db: Arc<A>,
+    /// Shared with the character servers, which accept the logins handed over through it
+    authentication_db: Arc<AuthenticationDB>,
     active_users: Arc<DashMap<AccountId, SystemTime>>,
     _phantom: PhantomData<C>,
 }
@@ -27,9 +30,10 @@ where
     A: AccountDB + Send + Sync + 'static,
     C: CharacterServer + Send + Sync + 'static,
 {
-    pub fn new(account_db: Arc<A>) -> Self {
+    pub fn new(account_db: Arc<A>, authentication_db: Arc<AuthenticationDB>) -> Self {
         Self {
             account_db,
+            authentication_db,
             active_users: Arc::new(DashMap::new()),
             _phantom: PhantomData::default(),
         }
@@ -129,7 +133,10 @@ where
         Ok(account)
     }
 
-    pub fn create_session(&self, account_id: AccountId) {
+    /// Hands the login over to the character servers, which the client connects to next
+    pub fn create_session(&self, authentication: Authentication) {
+        let account_id = authentication.account_info.account_id;
+        self.authentication_db.authenticate(authentication);
         self.active_users.insert(
             account_id,
             SystemTime::now()
@@ -137,4 +144,9 @@ where
                 .unwrap(),
         );
     }
+
+    /// Persists the accounts' pending writes, before the server exits
+    pub async fn flush(&self) -> Result<(), DBError> {
+        self.account_db.flush().await
+    }
 }
@@ -1,14 +1,12 @@
-use agent::LoginAgent;
 use databases::account::db::InMemoryAccountDB;
+use databases::authentication::AuthenticationDB;
 use std::{net::SocketAddr, sync::Arc};
-use tracing::info;
+use tracing::{info, warn};
 
-use crate::server::LoginServer;
 use api::config::login::AccountDBConfig;
+use api::shutdown::ShutdownTrigger;
 use api::{character::TcpServer as CharTcpServer, config::login::Config};
-
-mod agent;
-mod server;
+use login::{agent::LoginAgent, server::LoginServer};
 
 pub fn init_config() -> Result<Config, impl std::error::Error> {
     let mut settings = config::Config::default();
@@ -31,6 +29,13 @@ fn main() -> Result<(), anyhow::Error> {
 
     info!("Running with config:\n{:#?}", config);
 
+    // Drain the sessions on SIGINT & SIGTERM, instead of dropping them
+    let (shutdown_trigger, shutdown) = ShutdownTrigger::new();
+    ctrlc::set_handler(move || {
+        info!("Received termination signal");
+        shutdown_trigger.trigger();
+    })?;
+
     let char_servers = config
         .char_servers
         .iter()
@@ -48,8 +53,17 @@ fn main() -> Result<(), anyhow::Error> {
                 let account_db = InMemoryAccountDB::new(verbose)
                     .await
                     .map_err(anyhow::Error::from)?;
-                let login_agent = LoginAgent::new(Arc::new(account_db));
-                let login_server = LoginServer::new(login_agent, char_servers);
+                // Only character servers running in this process could share the logins
+                warn!("Running without a character server, use the launcher to hand logins over");
+                let authentication_db = Arc::new(AuthenticationDB::default());
+                let login_agent = LoginAgent::new(Arc::new(account_db), authentication_db);
+                let login_server = LoginServer::new(
+                    login_agent,
+                    char_servers,
+                    config.login_server.packet_version,
+                )
+                .with_capture(config.capture)
+                .with_shutdown(shutdown, config.shutdown);
                 login_server.run(addr).await.map_err(anyhow::Error::from)
             })?;
         }
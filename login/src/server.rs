@@ -1,4 +1,5 @@
 use std::sync::Arc;
+use std::time::Duration;
 
 use async_codec::Framed;
 use async_std::{
@@ -13,15 +14,22 @@ use tracing::{debug, error, info, warn};
 
 use crate::agent::LoginAgent;
 use api::{
-    account::db::AccountDB,
-    character::{CharacterServer, ServerInfo as CharacterServerInfo},
-    login::{CharacterSelectionInfo, LoginCodec, Request, Response},
+    account::db::{AccountDB, DBError},
+    capture::{CaptureHeader, RecordingCodec, ServerKind},
+    character::{AccountInfo, CharacterServer, ServerInfo as CharacterServerInfo},
+    config::{CaptureConfig, ShutdownConfig},
+    login::{CharacterSelectionInfo, LoginAborted, LoginCodec, Request, Response},
+    packet_version::PacketVersion,
+    shutdown::{SessionGuard, Sessions, Shutdown},
 };
+use databases::authentication::Authentication;
 
 #[derive(Debug, thiserror::Error)]
 pub enum ServerError {
     #[error("{0}")]
     IO(#[from] IOError),
+    #[error("{0}")]
+    AccountDB(#[from] DBError),
 }
 
 pub struct LoginServer<A, C>
@@ -31,6 +39,10 @@ where
 {
     login_agent: Arc<LoginAgent<A, C>>,
     char_servers: Vec<Arc<C>>,
+    packet_version: PacketVersion,
+    capture: CaptureConfig,
+    shutdown: Shutdown,
+    shutdown_config: ShutdownConfig,
 }
 
 impl<A, C> LoginServer<A, C>
@@ -38,13 +50,33 @@ where
     A: AccountDB + Send + Sync + 'static,
     C: CharacterServer + Send + Sync + 'static,
 {
-    pub fn new(login_agent: LoginAgent<A, C>, char_servers: Vec<Arc<C>>) -> Self {
+    pub fn new(
+        login_agent: LoginAgent<A, C>,
+        char_servers: Vec<Arc<C>>,
+        packet_version: PacketVersion,
+    ) -> Self {
         Self {
             login_agent: Arc::new(login_agent),
             char_servers,
+            packet_version,
+            capture: CaptureConfig::default(),
+            shutdown: Shutdown::default(),
+            shutdown_config: ShutdownConfig::default(),
         }
     }
 
+    pub fn with_capture(mut self, capture: CaptureConfig) -> Self {
+        self.capture = capture;
+        self
+    }
+
+    /// Stops the server once `shutdown` is triggered, instead of running forever
+    pub fn with_shutdown(mut self, shutdown: Shutdown, config: ShutdownConfig) -> Self {
+        self.shutdown = shutdown;
+        self.shutdown_config = config;
+        self
+    }
+
     fn character_server_info(&self) -> StackVec<[CharacterServerInfo; 5]> {
         self.char_servers
             .iter()
@@ -55,18 +87,48 @@ where
     pub async fn run(self, addr: impl Into<SocketAddr>) -> Result<(), ServerError> {
         let addr = addr.into();
         let listener = TcpListener::bind(addr).await?;
+        self.serve(listener).await
+    }
+
+    /// Accepts connections on an already bound listener
+    pub async fn serve(self, listener: TcpListener) -> Result<(), ServerError> {
         info!("Listening on {}", listener.local_addr()?);
 
         let mut incoming = listener.incoming();
+        let sessions = Sessions::new();
 
-        while let Some(stream) = incoming.next().await {
+        while let Some(stream) = self.shutdown.until(incoming.next()).await.flatten() {
             let stream: TcpStream = stream?;
             let login_agent = self.login_agent.clone();
             let char_server_info = self.character_server_info();
-            task::spawn(
-                async move { process_connection(login_agent, stream, char_server_info).await },
-            );
+            let packet_version = self.packet_version;
+            let capture = self.capture.clone();
+            let shutdown = self.shutdown.clone();
+            let guard = sessions.guard();
+            task::spawn(async move {
+                process_connection(
+                    login_agent,
+                    stream,
+                    char_server_info,
+                    packet_version,
+                    capture,
+                    shutdown,
+                    guard,
+                )
+                .await
+            });
         }
+
+        // Closes the listener, refusing new connections while the sessions finish
+        drop(incoming);
+        drop(listener);
+        info!(sessions = sessions.count(), "Shutting down");
+        let timeout = Duration::from_secs(self.shutdown_config.timeout_seconds);
+        let remaining = sessions.drain(timeout).await;
+        if remaining > 0 {
+            warn!(remaining, "Sessions did not finish in time");
+        }
+        self.login_agent.flush().await?;
         Ok(())
     }
 }
@@ -75,6 +137,10 @@ async fn process_connection<A, C>(
     login_agent: Arc<LoginAgent<A, C>>,
     stream: TcpStream,
     char_server_info: StackVec<[CharacterServerInfo; 5]>,
+    packet_version: PacketVersion,
+    capture: CaptureConfig,
+    shutdown: Shutdown,
+    _guard: SessionGuard,
 ) where
     A: AccountDB + Send + Sync + 'static,
     C: CharacterServer + Send + Sync + 'static,
@@ -82,10 +148,30 @@ async fn process_connection<A, C>(
     let ip_addr = stream.peer_addr().expect("Could not retrieve peer addr");
     debug!(ip = %ip_addr, "Received incoming connection");
 
-    let mut framed_stream = Framed::new(stream, LoginCodec {});
+    let codec = RecordingCodec::for_session(
+        LoginCodec::new(packet_version),
+        &capture,
+        CaptureHeader {
+            server: ServerKind::Login,
+            packet_version,
+        },
+        ip_addr,
+    );
+    let mut framed_stream = Framed::new(stream, codec);
 
     loop {
-        match framed_stream.next().await {
+        let request = match shutdown.until(framed_stream.next()).await {
+            Some(request) => request,
+            None => {
+                debug!(ip = %ip_addr, "Server shutting down. Terminating connection");
+                let response = Response::LoginAborted(LoginAborted::ServerClosed);
+                if let Err(err) = framed_stream.send(response).await {
+                    error!(%err);
+                }
+                break;
+            }
+        };
+        match request {
             Some(Ok(request)) => {
                 let response = match request {
                     Request::KeepAlive => {
@@ -106,8 +192,16 @@ async fn process_connection<A, C>(
                                     web_auth_token: account.web_auth_token,
                                     char_servers: char_server_info.clone(),
                                 };
-                                login_agent.create_session(account.account_id);
-                                Some(Response::LoginSuccessV3(info))
+                                login_agent.create_session(Authentication {
+                                    account_info: AccountInfo {
+                                        account_id: info.account_id,
+                                        authentication_code: info.authentication_code,
+                                        user_level: info.user_level,
+                                        sex: info.sex,
+                                    },
+                                    char_slots: account.char_slots,
+                                });
+                                Some(Response::LoginSuccess(Box::new(info)))
                             }
                             Err(failure) => Some(Response::LoginFailed(failure)),
                         }
@@ -0,0 +1,285 @@
+//! `#[derive(Packet)]` generates the encoding, decoding & size of a packet struct.
+//!
+//! Fields are encoded in declaration order, using their `EncodeFixed`/`DecodeFixed` impls.
+//! The struct may be annotated with `#[packet(opcode = 0x6b)]`, each field with:
+//! - `width = N`: encode the integer in N bytes, e.g. a `u16` level sent as a `u32`.
+//!   Decoding fails if the value doesn't fit the field, encoding saturates at the width's bounds.
+//! - `string = N`: encode the `String` NUL-padded to N bytes
+//! - `padding = N`: N zero bytes preceding the field
+//! - `since = DATE` / `before = DATE`: only present for (older than) that packet version
+//! - `skip`: not part of the packet, decoded as `Default::default()`
+//!
+//! Packets get a `size(version)`, as well as a constant `SIZE` unless a field depends on the version:
+//!
+//! ```compile_fail
+//! #[derive(api::codec::Packet)]
+//! struct Experience {
+//!     #[packet(since = 20170830)]
+//!     exp: u64,
+//! }
+//!
+//! let _ = Experience::SIZE;
+//! ```
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::{
+    parse_macro_input, spanned::Spanned, Attribute, Data, DeriveInput, Error, Fields, Lit, Meta,
+    NestedMeta, Type,
+};
+
+#[proc_macro_derive(Packet, attributes(packet))]
+pub fn derive_packet(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input)
+        .unwrap_or_else(|error| error.to_compile_error())
+        .into()
+}
+
+#[derive(Default)]
+struct FieldOptions {
+    width: Option<usize>,
+    string: Option<usize>,
+    padding: usize,
+    since: Option<u32>,
+    before: Option<u32>,
+    skip: bool,
+}
+
+fn packet_attributes(attrs: &[Attribute]) -> syn::Result<Vec<NestedMeta>> {
+    let mut nested = vec![];
+    for attr in attrs.iter().filter(|attr| attr.path.is_ident("packet")) {
+        match attr.parse_meta()? {
+            Meta::List(list) => nested.extend(list.nested),
+            meta => return Err(Error::new(meta.span(), "expected #[packet(...)]")),
+        }
+    }
+    Ok(nested)
+}
+
+fn int_value<T: std::str::FromStr>(lit: &Lit) -> syn::Result<T>
+where
+    T::Err: std::fmt::Display,
+{
+    match lit {
+        Lit::Int(int) => int.base10_parse(),
+        lit => Err(Error::new(lit.span(), "expected an integer")),
+    }
+}
+
+fn struct_opcode(attrs: &[Attribute]) -> syn::Result<Option<u16>> {
+    let mut opcode = None;
+    for meta in packet_attributes(attrs)? {
+        match meta {
+            NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident("opcode") => {
+                opcode = Some(int_value(&nv.lit)?);
+            }
+            meta => return Err(Error::new(meta.span(), "unknown packet attribute")),
+        }
+    }
+    Ok(opcode)
+}
+
+fn field_options(attrs: &[Attribute]) -> syn::Result<FieldOptions> {
+    let mut options = FieldOptions::default();
+    for meta in packet_attributes(attrs)? {
+        match meta {
+            NestedMeta::Meta(Meta::Path(path)) if path.is_ident("skip") => options.skip = true,
+            NestedMeta::Meta(Meta::NameValue(nv)) => {
+                let name = nv.path.get_ident().map(|ident| ident.to_string());
+                match name.as_deref() {
+                    Some("width") => {
+                        let width = int_value(&nv.lit)?;
+                        if ![1, 2, 4, 8].contains(&width) {
+                            return Err(Error::new(nv.lit.span(), "width must be 1, 2, 4 or 8"));
+                        }
+                        options.width = Some(width);
+                    }
+                    Some("string") => options.string = Some(int_value(&nv.lit)?),
+                    Some("padding") => options.padding = int_value(&nv.lit)?,
+                    Some("since") => options.since = Some(int_value(&nv.lit)?),
+                    Some("before") => options.before = Some(int_value(&nv.lit)?),
+                    _ => return Err(Error::new(nv.path.span(), "unknown packet attribute")),
+                }
+            }
+            meta => return Err(Error::new(meta.span(), "unknown packet attribute")),
+        }
+    }
+    Ok(options)
+}
+
+fn width_type(width: usize) -> Type {
+    let ident = format_ident!("u{}", width * 8);
+    syn::parse_quote!(#ident)
+}
+
+fn expand(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let opcode = struct_opcode(&input.attrs)?.map(|opcode| {
+        quote! {
+            pub const OPCODE: u16 = #opcode;
+        }
+    });
+
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => return Err(Error::new(input.span(), "Packet requires named fields")),
+        },
+        _ => {
+            return Err(Error::new(
+                input.span(),
+                "Packet can only be derived for structs",
+            ))
+        }
+    };
+
+    let mut encodes = vec![];
+    let mut decodes = vec![];
+    let mut sizes = vec![];
+    let mut versioned_sizes = vec![];
+    let mut is_versioned = false;
+    let mut idents = vec![];
+    for field in fields {
+        let ident = field.ident.as_ref().expect("named field");
+        let ty = &field.ty;
+        let options = field_options(&field.attrs)?;
+        idents.push(ident);
+        if options.skip {
+            decodes.push(quote! { let #ident = ::core::default::Default::default(); });
+            continue;
+        }
+
+        let padding = options.padding;
+        let (encode, decode, size) = match (options.string, options.width) {
+            (Some(length), _) => (
+                quote! { ::api::codec::encode_string(codec, &self.#ident, #length); },
+                quote! { reader.string(#length)? },
+                quote! { #length },
+            ),
+            (None, Some(width)) => {
+                let width_ty = width_type(width);
+                let overflow = format!("{} out of range: {{}}", ident);
+                (
+                    quote! {
+                        let value = <#width_ty as ::core::convert::TryFrom<#ty>>::try_from(self.#ident)
+                            .unwrap_or_else(|_| {
+                                if self.#ident < <#ty as ::core::default::Default>::default() {
+                                    #width_ty::MIN
+                                } else {
+                                    #width_ty::MAX
+                                }
+                            });
+                        codec.encode(&value);
+                    },
+                    quote! {{
+                        let value = reader.decode::<#width_ty>()?;
+                        <#ty as ::core::convert::TryFrom<#width_ty>>::try_from(value).map_err(|_| {
+                            ::api::error::PacketError::InvalidRequest(format!(#overflow, value))
+                        })?
+                    }},
+                    quote! { #width },
+                )
+            }
+            (None, None) => (
+                quote! { codec.encode(&self.#ident); },
+                quote! { reader.decode::<#ty>()? },
+                quote! { <#ty as ::api::codec::EncodeFixed>::SIZE },
+            ),
+        };
+
+        let (encode, decode) = if padding > 0 {
+            (
+                quote! {
+                    codec.padding(#padding);
+                    #encode
+                },
+                quote! {{
+                    reader.skip(#padding)?;
+                    #decode
+                }},
+            )
+        } else {
+            (encode, decode)
+        };
+        sizes.push(quote! { + #padding + #size });
+        is_versioned |= options.since.is_some() || options.before.is_some();
+        let present = match (options.since, options.before) {
+            (None, None) => {
+                encodes.push(encode);
+                decodes.push(quote! { let #ident = #decode; });
+                versioned_sizes.push(quote! { size += #padding + #size; });
+                continue;
+            }
+            (Some(since), None) => quote! { version.date() >= #since },
+            (None, Some(before)) => quote! { version.date() < #before },
+            (Some(since), Some(before)) => {
+                quote! { version.date() >= #since && version.date() < #before }
+            }
+        };
+        encodes.push(quote! {
+            if #present {
+                #encode
+            }
+        });
+        decodes.push(quote! {
+            let #ident = if #present {
+                #decode
+            } else {
+                ::core::default::Default::default()
+            };
+        });
+        versioned_sizes.push(quote! {
+            if #present {
+                size += #padding + #size;
+            }
+        });
+    }
+
+    // Fields missing from some versions leave no single size to speak of
+    let size = if is_versioned {
+        None
+    } else {
+        Some(quote! {
+            /// Size of the packet, for every client version
+            pub const SIZE: usize = 0 #(#sizes)*;
+        })
+    };
+
+    Ok(quote! {
+        impl #impl_generics #name #ty_generics #where_clause {
+            #opcode
+
+            #size
+
+            /// Size of the packet for the given client version
+            #[allow(unused_variables, unused_mut)]
+            pub fn size(version: ::api::packet_version::PacketVersion) -> usize {
+                let mut size = 0;
+                #(#versioned_sizes)*
+                size
+            }
+        }
+
+        impl #impl_generics ::api::codec::EncodeStruct for #name #ty_generics #where_clause {
+            #[allow(unused_variables)]
+            fn encode<C: ::api::codec::RagnarokCodec>(&self, codec: &mut C) {
+                let version = codec.version();
+                #(#encodes)*
+            }
+        }
+
+        impl #impl_generics ::api::codec::DecodeStruct for #name #ty_generics #where_clause {
+            #[allow(unused_variables)]
+            fn decode(
+                reader: &mut ::api::codec::ClientTcpReader,
+            ) -> ::core::result::Result<Self, ::api::error::PacketError> {
+                let version = reader.version();
+                #(#decodes)*
+                Ok(Self { #(#idents),* })
+            }
+        }
+    })
+}
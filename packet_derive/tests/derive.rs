@@ -0,0 +1,179 @@
+use api::{
+    codec::{ClientTcpCodec, ClientTcpReader, DecodeStruct, EncodeStruct, Packet},
+    error::PacketError,
+    map::Maps,
+    packet_version::PacketVersion,
+};
+
+#[derive(Debug, Default, PartialEq, Packet)]
+#[packet(opcode = 0x0123)]
+struct Fixed {
+    id: u32,
+    #[packet(width = 4)]
+    level: u16,
+    #[packet(padding = 2)]
+    flag: u8,
+    #[packet(string = 8)]
+    name: String,
+    #[packet(skip)]
+    cached: u32,
+}
+
+#[derive(Debug, Default, PartialEq, Packet)]
+struct Narrowed {
+    #[packet(width = 2)]
+    zeny: u32,
+    #[packet(width = 1)]
+    delta: i16,
+}
+
+#[derive(Debug, Default, PartialEq, Packet)]
+struct Versioned {
+    id: u32,
+    #[packet(since = 20170830)]
+    exp: u64,
+    #[packet(before = 20170830)]
+    legacy_exp: u32,
+    #[packet(since = 20100720, before = 20170830, padding = 1)]
+    between: u16,
+}
+
+const OLD: PacketVersion = PacketVersion::new(20090101);
+const MIDDLE: PacketVersion = PacketVersion::new(20120101);
+const NEW: PacketVersion = PacketVersion::new(20180620);
+
+fn maps() -> Maps {
+    Maps::from_file(concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/../resources/maps.yaml"
+    ))
+    .unwrap()
+}
+
+fn encode<T: EncodeStruct>(packet: &T, version: PacketVersion) -> Vec<u8> {
+    let maps = maps();
+    let mut buf = vec![0u8; 64];
+    let mut codec = ClientTcpCodec::new(&maps, version, &mut buf);
+    packet.encode(&mut codec);
+    let length = codec.finish().unwrap();
+    buf.truncate(length);
+    buf
+}
+
+fn decode<T: DecodeStruct>(bytes: &[u8], version: PacketVersion) -> Result<T, PacketError> {
+    T::decode(&mut ClientTcpReader::with_version(bytes, version))
+}
+
+fn fixed() -> Fixed {
+    Fixed {
+        id: 0x0403_0201,
+        level: 99,
+        flag: 1,
+        name: "Poring".to_string(),
+        cached: 0,
+    }
+}
+
+#[test]
+fn fixed_packets_have_a_constant_size() {
+    assert_eq!(Fixed::OPCODE, 0x0123);
+    assert_eq!(Fixed::SIZE, 4 + 4 + 2 + 1 + 8);
+    for version in [OLD, MIDDLE, NEW].iter() {
+        assert_eq!(Fixed::size(*version), Fixed::SIZE);
+    }
+}
+
+#[test]
+fn fields_are_encoded_in_order() {
+    let bytes = encode(&fixed(), NEW);
+    assert_eq!(
+        bytes,
+        [
+            0x01, 0x02, 0x03, 0x04, // id
+            99, 0, 0, 0, // level, widened to 4 bytes
+            0, 0, 1, // padding & flag
+            b'P', b'o', b'r', b'i', b'n', b'g', 0, 0, // name
+        ]
+    );
+    assert_eq!(bytes.len(), Fixed::SIZE);
+}
+
+#[test]
+fn skipped_fields_decode_as_default() {
+    let packet = Fixed {
+        cached: 42,
+        ..fixed()
+    };
+    let decoded: Fixed = decode(&encode(&packet, NEW), NEW).unwrap();
+    assert_eq!(decoded, fixed());
+}
+
+#[test]
+fn widened_fields_out_of_range_fail_to_decode() {
+    let mut bytes = encode(&fixed(), NEW);
+    bytes[4..8].copy_from_slice(&0x0001_0000u32.to_le_bytes());
+    match decode::<Fixed>(&bytes, NEW) {
+        Err(PacketError::InvalidRequest(message)) => assert!(message.contains("level")),
+        result => panic!("Unexpected result {:?}", result),
+    }
+}
+
+#[test]
+fn narrowed_fields_out_of_range_saturate() {
+    let packet = Narrowed {
+        zeny: 0x0001_0000,
+        delta: -1,
+    };
+    assert_eq!(encode(&packet, NEW), [0xff, 0xff, 0]);
+    let packet = Narrowed {
+        zeny: 1,
+        delta: 0x100,
+    };
+    assert_eq!(encode(&packet, NEW), [1, 0, 0xff]);
+}
+
+#[test]
+fn versioned_fields_follow_the_packet_version() {
+    let packet = Versioned {
+        id: 1,
+        exp: 2,
+        legacy_exp: 3,
+        between: 4,
+    };
+    let expected = [
+        (
+            OLD,
+            4 + 4,
+            Versioned {
+                id: 1,
+                legacy_exp: 3,
+                ..Default::default()
+            },
+        ),
+        (
+            MIDDLE,
+            4 + 4 + 1 + 2,
+            Versioned {
+                id: 1,
+                legacy_exp: 3,
+                between: 4,
+                ..Default::default()
+            },
+        ),
+        (
+            NEW,
+            4 + 8,
+            Versioned {
+                id: 1,
+                exp: 2,
+                ..Default::default()
+            },
+        ),
+    ];
+    for (version, size, decoded) in expected.iter() {
+        let bytes = encode(&packet, *version);
+        assert_eq!(bytes.len(), *size, "{:?}", version);
+        assert_eq!(Versioned::size(*version), *size, "{:?}", version);
+        assert_eq!(&decode::<Versioned>(&bytes, *version).unwrap(), decoded);
+    }
+}
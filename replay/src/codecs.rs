@@ -0,0 +1,101 @@
+use std::sync::Arc;
+
+use async_codec::{Decode, DecodeResult, Encode};
+
+use api::{
+    capture::{CaptureHeader, ServerKind},
+    character::{CharacterClientCodec, CharacterCodec, Request as CharacterRequest},
+    login::{LoginClientCodec, LoginCodec},
+    map::Maps,
+};
+
+/// Decodes both sides of a session, the requests as the server does & the responses as the client
+pub enum Codecs {
+    Login {
+        requests: LoginCodec,
+        responses: LoginClientCodec,
+    },
+    Character {
+        requests: CharacterCodec,
+        responses: CharacterClientCodec,
+    },
+}
+
+impl Codecs {
+    pub fn new(header: CaptureHeader, maps: Arc<Maps>) -> Self {
+        let version = header.packet_version;
+        match header.server {
+            ServerKind::Login => Self::Login {
+                requests: LoginCodec::new(version),
+                responses: LoginClientCodec::new(version),
+            },
+            ServerKind::Character => Self::Character {
+                requests: CharacterCodec::new(maps.clone(), version),
+                responses: CharacterClientCodec::new(maps, version),
+            },
+        }
+    }
+
+    /// Describes the requests of an inbound frame, which may include packets the server skipped
+    pub fn describe_requests(&mut self, data: &[u8]) -> Vec<String> {
+        let mut data = data.to_vec();
+        let mut descriptions = vec![];
+        let mut offset = 0;
+        while offset < data.len() {
+            let (consumed, description) = match self {
+                Self::Login { requests, .. } => {
+                    let (consumed, result) = requests.decode(&mut data[offset..]);
+                    (consumed, describe(result))
+                }
+                Self::Character {
+                    requests,
+                    responses,
+                } => {
+                    let (consumed, result) = requests.decode(&mut data[offset..]);
+                    // The response codec has to know the bare account ID follows
+                    if let DecodeResult::Ok(request @ CharacterRequest::ConnectClient(_)) = &result
+                    {
+                        let _ = responses.encode(request, &mut [0u8; 64]);
+                    }
+                    (consumed, describe(result))
+                }
+            };
+            match description {
+                Some(description) => descriptions.push(description),
+                None => {
+                    descriptions.push(format!("Incomplete: {:02x?}", &data[offset..]));
+                    break;
+                }
+            }
+            if consumed == 0 {
+                break;
+            }
+            offset += consumed;
+        }
+        descriptions
+    }
+
+    /// Decodes the next response in `buffer`, `None` if it's incomplete
+    pub fn decode_response(&mut self, buffer: &mut [u8]) -> (usize, Option<String>) {
+        match self {
+            Self::Login { responses, .. } => {
+                let (consumed, result) = responses.decode(buffer);
+                (consumed, describe(result))
+            }
+            Self::Character { responses, .. } => {
+                let (consumed, result) = responses.decode(buffer);
+                (consumed, describe(result))
+            }
+        }
+    }
+}
+
+fn describe<T: std::fmt::Debug, E: std::fmt::Display>(
+    result: DecodeResult<T, E>,
+) -> Option<String> {
+    match result {
+        DecodeResult::Ok(item) => Some(format!("{:?}", item)),
+        DecodeResult::Err(err) => Some(format!("Could not decode: {}", err)),
+        DecodeResult::UnexpectedEnd => None,
+    }
+}
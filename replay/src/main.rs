@@ -0,0 +1,177 @@
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use anyhow::anyhow;
+use serde::Deserialize;
+
+use crate::codecs::Codecs;
+use api::{
+    capture::{CaptureReader, CapturedFrame, Direction},
+    map::Maps,
+};
+
+mod codecs;
+
+#[derive(Deserialize, Debug)]
+pub struct Config {
+    pub maps: MapConfig,
+    pub replay: ReplayConfig,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct MapConfig {
+    pub names_file: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ReplayConfig {
+    /// How long to wait for each response the capture expects
+    pub timeout_seconds: u64,
+}
+
+pub fn init_config() -> Result<Config, impl std::error::Error> {
+    let mut settings = ::config::Config::default();
+    settings
+        .merge(::config::File::with_name("config"))?
+        .merge(::config::Environment::with_prefix("APP"))?;
+    settings.try_into()
+}
+
+const USAGE: &str = "Usage: replay decode <capture> | replay send <capture> <address:port>";
+
+fn main() -> Result<(), anyhow::Error> {
+    let config = init_config()?;
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let maps = Arc::new(Maps::from_file(&config.maps.names_file)?);
+    match args.as_slice() {
+        [mode, capture] if mode == "decode" => decode(capture, maps),
+        [mode, capture, addr] if mode == "send" => {
+            let timeout = Duration::from_secs(config.replay.timeout_seconds);
+            send(capture, addr.parse()?, maps, timeout)
+        }
+        _ => Err(anyhow!(USAGE)),
+    }
+}
+
+fn print_frame(start: SystemTime, frame: &CapturedFrame, descriptions: &[String]) {
+    let offset = frame
+        .timestamp
+        .duration_since(start)
+        .unwrap_or_default()
+        .as_secs_f64();
+    let arrow = match frame.direction {
+        Direction::Inbound => "->",
+        Direction::Outbound => "<-",
+    };
+    for description in descriptions {
+        println!(
+            "{:>10.3}s {} {:>5} B  {}",
+            offset,
+            arrow,
+            frame.data.len(),
+            description
+        );
+    }
+}
+
+/// Prints every frame of the capture as decoded requests & responses
+fn decode(capture: &str, maps: Arc<Maps>) -> Result<(), anyhow::Error> {
+    let reader = CaptureReader::open(capture)?;
+    let header = reader.header();
+    println!(
+        "{:?} server, packet version {}",
+        header.server,
+        header.packet_version.date()
+    );
+    let mut codecs = Codecs::new(header, maps);
+    let mut start = None;
+    for frame in reader {
+        let frame = frame?;
+        let start = *start.get_or_insert(frame.timestamp);
+        let descriptions = match frame.direction {
+            Direction::Inbound => codecs.describe_requests(&frame.data),
+            Direction::Outbound => {
+                let mut data = frame.data.clone();
+                let (_, description) = codecs.decode_response(&mut data);
+                vec![description.unwrap_or_else(|| format!("Incomplete: {:02x?}", frame.data))]
+            }
+        };
+        print_frame(start, &frame, &descriptions);
+    }
+    Ok(())
+}
+
+/// Sends the client's frames to a server, waiting for as many responses as were captured
+fn send(
+    capture: &str,
+    addr: SocketAddr,
+    maps: Arc<Maps>,
+    timeout: Duration,
+) -> Result<(), anyhow::Error> {
+    let reader = CaptureReader::open(capture)?;
+    let mut codecs = Codecs::new(reader.header(), maps);
+    let mut stream = TcpStream::connect(addr)?;
+    stream.set_read_timeout(Some(timeout))?;
+    let mut received = vec![];
+    let mut start = None;
+    let (mut identical, mut different) = (0, 0);
+    for frame in reader {
+        let frame = frame?;
+        let start = *start.get_or_insert(frame.timestamp);
+        match frame.direction {
+            Direction::Inbound => {
+                stream.write_all(&frame.data)?;
+                print_frame(start, &frame, &codecs.describe_requests(&frame.data));
+            }
+            Direction::Outbound => {
+                let (response, description) =
+                    next_response(&mut stream, &mut codecs, &mut received)?;
+                // Random values, e.g. authentication codes or pincode seeds, cause differences
+                let comparison = if response == frame.data {
+                    identical += 1;
+                    "same"
+                } else {
+                    different += 1;
+                    "differs"
+                };
+                println!(
+                    "{:>10}  <- {:>5} B  {}",
+                    comparison,
+                    response.len(),
+                    description
+                );
+            }
+        }
+    }
+    println!(
+        "{} response(s) identical to the capture, {} different",
+        identical, different
+    );
+    Ok(())
+}
+
+/// Reads until a whole response is buffered, returning its bytes & description
+fn next_response(
+    stream: &mut TcpStream,
+    codecs: &mut Codecs,
+    buffer: &mut Vec<u8>,
+) -> Result<(Vec<u8>, String), anyhow::Error> {
+    loop {
+        if !buffer.is_empty() {
+            let (consumed, description) = codecs.decode_response(buffer);
+            if let Some(description) = description {
+                let response = buffer.drain(..consumed).collect();
+                return Ok((response, description));
+            }
+        }
+        let mut chunk = [0u8; 4096];
+        let count = match stream.read(&mut chunk) {
+            Ok(0) => return Err(anyhow!("Connection closed by the server")),
+            Ok(count) => count,
+            Err(err) => return Err(anyhow!("No response from the server: {}", err)),
+        };
+        buffer.extend_from_slice(&chunk[..count]);
+    }
+}
@@ -0,0 +1,364 @@
+use std::convert::TryInto;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::anyhow;
+use async_codec::{Decode, Encode, Framed};
+use async_std::{
+    net::{SocketAddr, TcpStream},
+    stream::StreamExt,
+};
+use futures_util::SinkExt;
+use tracing::{error, info};
+
+use crate::config::Config;
+use crate::script::{Script, Step};
+use api::{
+    character::{
+        attributes::{Appearance, Class},
+        AccountInfo, CharacterClientCodec, NewCharacter, Request as CharacterRequest,
+        Response as CharacterResponse, CHARACTERS_PER_PAGE, MAX_CHARACTERS_PER_ACCOUNT,
+    },
+    login::{
+        CharacterSelectionInfo, LoginClientCodec, LoginCredentials, Request as LoginRequest,
+        Response as LoginResponse,
+    },
+    map::Maps,
+    pincode::{self, Pincode, PincodeInfo, PincodeStatus},
+};
+
+type CharacterStream = Framed<TcpStream, CharacterClientCodec>;
+
+/// Outcome of a single step, as reported to the user
+pub struct StepReport {
+    pub name: String,
+    pub latency: Duration,
+    pub outcome: Result<String, String>,
+}
+
+/// Logs in & runs a script against the character server, like the game client would
+pub struct TestClient {
+    config: Config,
+    maps: Arc<Maps>,
+    reports: Vec<StepReport>,
+    /// The latest pincode state sent by the server
+    pincode: Option<PincodeInfo>,
+}
+
+impl TestClient {
+    pub fn new(config: Config, maps: Arc<Maps>) -> Self {
+        Self {
+            config,
+            maps,
+            reports: vec![],
+            pincode: None,
+        }
+    }
+
+    pub fn reports(&self) -> &[StepReport] {
+        &self.reports
+    }
+
+    /// Runs every step until one fails
+    pub async fn run(&mut self, script: &Script) -> Result<(), anyhow::Error> {
+        let info = self.login().await?;
+        let mut stream = self.connect_char_server(&info).await?;
+        for step in &script.steps {
+            let started = Instant::now();
+            let result = self.run_step(&mut stream, &info, step).await;
+            self.record(step.name(), started, result)?;
+        }
+        Ok(())
+    }
+
+    fn timeout(&self) -> Duration {
+        Duration::from_secs(self.config.script.timeout_seconds)
+    }
+
+    fn record<T>(
+        &mut self,
+        name: &str,
+        started: Instant,
+        result: Result<(T, String), anyhow::Error>,
+    ) -> Result<T, anyhow::Error> {
+        let latency = started.elapsed();
+        let latency_ms = latency.as_millis() as u64;
+        let (outcome, result) = match result {
+            Ok((value, summary)) => {
+                info!(step = name, latency_ms, "{}", summary);
+                (Ok(summary), Ok(value))
+            }
+            Err(err) => {
+                error!(step = name, latency_ms, %err, "Step failed");
+                (Err(err.to_string()), Err(err))
+            }
+        };
+        self.reports.push(StepReport {
+            name: name.to_string(),
+            latency,
+            outcome,
+        });
+        result
+    }
+
+    async fn login(&mut self) -> Result<Box<CharacterSelectionInfo>, anyhow::Error> {
+        let started = Instant::now();
+        let result = async {
+            let server = &self.config.login_server;
+            let addr: SocketAddr = format!("{}:{}", server.address, server.port).parse()?;
+            let stream = connect(addr)?;
+            let mut stream = Framed::new(stream, LoginClientCodec::new(server.packet_version));
+            let account = &self.config.account;
+            stream
+                .send(LoginRequest::ClientLogin(LoginCredentials::ClearText {
+                    client_type: account.client_type,
+                    username: account.username.clone(),
+                    password: account.password.clone(),
+                }))
+                .await?;
+            match receive(&mut stream, self.timeout()).await? {
+                LoginResponse::LoginSuccess(info) => {
+                    let summary = format!(
+                        "Logged in as account {}, {} char server(s) available",
+                        info.account_id,
+                        info.char_servers.len()
+                    );
+                    Ok((info, summary))
+                }
+                LoginResponse::LoginFailed(failure) => Err(anyhow!("Login failed: {}", failure)),
+                LoginResponse::LoginAborted(aborted) => Err(anyhow!("Login aborted: {}", aborted)),
+            }
+        }
+        .await;
+        self.record("login", started, result)
+    }
+
+    async fn connect_char_server(
+        &mut self,
+        info: &CharacterSelectionInfo,
+    ) -> Result<CharacterStream, anyhow::Error> {
+        let started = Instant::now();
+        let result = async {
+            let server = match &self.config.char_server {
+                Some(name) => info
+                    .char_servers
+                    .iter()
+                    .find(|server| server.name() == name),
+                None => info.char_servers.iter().next(),
+            }
+            .ok_or_else(|| anyhow!("No matching char server"))?;
+
+            let stream = connect(server.address().into())?;
+            let codec = CharacterClientCodec::new(
+                self.maps.clone(),
+                self.config.login_server.packet_version,
+            );
+            let mut stream = Framed::new(stream, codec);
+            stream
+                .send(CharacterRequest::ConnectClient(AccountInfo {
+                    account_id: info.account_id,
+                    authentication_code: info.authentication_code,
+                    user_level: info.user_level,
+                    sex: info.sex,
+                }))
+                .await?;
+
+            // The server sends the account's state, ending with the pincode status
+            let mut characters = 0;
+            loop {
+                match receive(&mut stream, self.timeout()).await? {
+                    CharacterResponse::Rejected => {
+                        return Err(anyhow!("Rejected by {}", server.name()))
+                    }
+                    CharacterResponse::CharacterInfo {
+                        characters: list, ..
+                    } => characters = list.len(),
+                    CharacterResponse::PincodeInfo(pincode) => {
+                        let summary = format!(
+                            "Connected to {}, {} character(s), pincode status {:?}",
+                            server.name(),
+                            characters,
+                            pincode.status
+                        );
+                        return Ok(((stream, pincode), summary));
+                    }
+                    _ => {}
+                }
+            }
+        }
+        .await;
+        let (stream, pincode) = self.record("connect_char_server", started, result)?;
+        self.pincode = Some(pincode);
+        Ok(stream)
+    }
+
+    async fn run_step(
+        &mut self,
+        stream: &mut CharacterStream,
+        info: &CharacterSelectionInfo,
+        step: &Step,
+    ) -> Result<((), String), anyhow::Error> {
+        let timeout = self.timeout();
+        match step {
+            Step::ListCharacters => {
+                let mut names = vec![];
+                // Pages are requested until one isn't full
+                for _ in 0..=MAX_CHARACTERS_PER_ACCOUNT / CHARACTERS_PER_PAGE {
+                    stream.send(CharacterRequest::ListCharacters).await?;
+                    let page = match receive(stream, timeout).await? {
+                        CharacterResponse::Characters(page) => page,
+                        response => return Err(unexpected(&response)),
+                    };
+                    names.extend(
+                        page.iter().map(|character| {
+                            format!("{} (slot {})", character.name, character.slot)
+                        }),
+                    );
+                    if page.len() < CHARACTERS_PER_PAGE {
+                        break;
+                    }
+                }
+                Ok((
+                    (),
+                    format!("{} character(s): {}", names.len(), names.join(", ")),
+                ))
+            }
+            Step::CreateCharacter {
+                name,
+                slot,
+                hair,
+                hair_color,
+                class,
+                sex,
+                stats,
+            } => {
+                stream
+                    .send(CharacterRequest::CreateCharacter(NewCharacter {
+                        name: name.clone().into(),
+                        slot: *slot,
+                        stats: *stats,
+                        appearance: Appearance {
+                            hair: *hair,
+                            hair_color: *hair_color,
+                            ..Default::default()
+                        },
+                        class: class.unwrap_or(Class::Novice),
+                        sex: Some(sex.unwrap_or(info.sex)),
+                    }))
+                    .await?;
+                match receive(stream, timeout).await? {
+                    CharacterResponse::NewCharacterInfo(character) => Ok((
+                        (),
+                        format!(
+                            "Created {} (id {}) in slot {}",
+                            character.name, character.id, character.slot
+                        ),
+                    )),
+                    CharacterResponse::CreationRefused(reason) => {
+                        Err(anyhow!("Creation refused: {:?}", reason))
+                    }
+                    response => Err(unexpected(&response)),
+                }
+            }
+            Step::SelectCharacter { slot } => {
+                stream
+                    .send(CharacterRequest::SelectCharacter { slot: *slot })
+                    .await?;
+                match receive(stream, timeout).await? {
+                    CharacterResponse::Rejected => Err(anyhow!("Selection rejected")),
+                    CharacterResponse::CaptchaImage(_) => Err(anyhow!("Captcha required")),
+                    _ => Ok(((), format!("Selected slot {}", slot))),
+                }
+            }
+            Step::Pincode {
+                pincode,
+                new_pincode,
+            } => {
+                let current = self
+                    .pincode
+                    .ok_or_else(|| anyhow!("No pincode status received"))?;
+                let scramble = |pincode: &str| -> Result<Pincode, anyhow::Error> {
+                    let pincode: Pincode = pincode
+                        .as_bytes()
+                        .try_into()
+                        .map_err(|_| anyhow!("Pincodes are 4 digits"))?;
+                    pincode::encrypt(current.seed, &pincode)
+                        .ok_or_else(|| anyhow!("Pincodes are 4 digits"))
+                };
+                let request = match current.status {
+                    // The server shows the pincode button once the pincode is verified
+                    PincodeStatus::Correct | PincodeStatus::ShowButton => {
+                        return Ok(((), "Pincode already verified".to_string()))
+                    }
+                    PincodeStatus::NeedNewPin | PincodeStatus::CreateNewPin => {
+                        CharacterRequest::NewPincode {
+                            account_id: current.account_id,
+                            pincode: scramble(pincode)?,
+                        }
+                    }
+                    PincodeStatus::PinMustBeChanged => {
+                        let new_pincode = new_pincode
+                            .as_ref()
+                            .ok_or_else(|| anyhow!("The pincode must be changed"))?;
+                        CharacterRequest::ChangePincode {
+                            account_id: current.account_id,
+                            old_pincode: scramble(pincode)?,
+                            new_pincode: scramble(new_pincode)?,
+                        }
+                    }
+                    _ => CharacterRequest::CheckPincode {
+                        account_id: current.account_id,
+                        pincode: scramble(pincode)?,
+                    },
+                };
+                stream.send(request).await?;
+                match receive(stream, timeout).await? {
+                    CharacterResponse::PincodeInfo(info) => {
+                        self.pincode = Some(info);
+                        match info.status {
+                            PincodeStatus::Correct | PincodeStatus::ShowButton => {
+                                Ok(((), "Pincode accepted".to_string()))
+                            }
+                            status => Err(anyhow!("Pincode not accepted: {:?}", status)),
+                        }
+                    }
+                    CharacterResponse::Rejected => Err(anyhow!("Pincode rejected")),
+                    response => Err(unexpected(&response)),
+                }
+            }
+        }
+    }
+}
+
+/// Connects through the standard library, as async-std's own connect fails with EAFNOSUPPORT
+/// on some kernels. Blocking is fine, the client only ever waits on a single connection.
+fn connect(addr: SocketAddr) -> Result<TcpStream, std::io::Error> {
+    Ok(std::net::TcpStream::connect(addr)?.into())
+}
+
+/// Waits for the next packet from the server
+async fn receive<C: Decode + Encode + Unpin>(
+    stream: &mut Framed<TcpStream, C>,
+    timeout: Duration,
+) -> Result<<C as Decode>::Item, anyhow::Error>
+where
+    <C as Decode>::Error: std::fmt::Display,
+{
+    match async_std::future::timeout(timeout, stream.next()).await {
+        Ok(Some(Ok(response))) => Ok(response),
+        Ok(Some(Err(err))) => Err(anyhow!("Could not decode response: {}", err)),
+        Ok(None) => Err(anyhow!("Connection closed by the server")),
+        Err(_) => Err(anyhow!("No response within {:?}", timeout)),
+    }
+}
+
+fn unexpected(response: &CharacterResponse) -> anyhow::Error {
+    match response {
+        CharacterResponse::CaptchaImage(_) => anyhow!("Captcha required"),
+        CharacterResponse::Rejected => anyhow!("Request rejected"),
+        response => anyhow!(
+            "Unexpected response {:#06x}",
+            response.command_code().unwrap_or_default()
+        ),
+    }
+}
@@ -0,0 +1,56 @@
+use serde::Deserialize;
+
+use api::packet_version::PacketVersion;
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Config {
+    pub login_server: LoginServerConfig,
+    pub account: AccountConfig,
+    /// Name of the char server to connect to, the first one listed if absent
+    pub char_server: Option<String>,
+    pub maps: MapConfig,
+    pub script: ScriptConfig,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct LoginServerConfig {
+    pub address: String,
+    pub port: u16,
+    /// Client version to impersonate
+    #[serde(default)]
+    pub packet_version: PacketVersion,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct AccountConfig {
+    pub username: String,
+    pub password: String,
+    #[serde(default)]
+    pub client_type: u8,
+}
+
+impl std::fmt::Debug for AccountConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AccountConfig")
+            .field("username", &self.username)
+            .field("client_type", &self.client_type)
+            .finish()
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct MapConfig {
+    pub names_file: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ScriptConfig {
+    pub file: String,
+    /// How long to wait for each server response
+    #[serde(default = "default_timeout")]
+    pub timeout_seconds: u64,
+}
+
+fn default_timeout() -> u64 {
+    5
+}
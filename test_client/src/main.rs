@@ -0,0 +1,64 @@
+use std::sync::Arc;
+
+use tracing::info;
+
+use crate::client::TestClient;
+use crate::config::Config;
+use crate::script::Script;
+use api::map::Maps;
+
+mod client;
+mod config;
+mod script;
+
+pub fn init_config() -> Result<Config, impl std::error::Error> {
+    let mut settings = ::config::Config::default();
+    settings
+        .merge(::config::File::with_name("config"))?
+        .merge(::config::Environment::with_prefix("APP"))?;
+    settings.try_into()
+}
+
+fn main() -> Result<(), anyhow::Error> {
+    // Initialize config
+    let config = init_config()?;
+
+    // Initialize logging
+    tracing_subscriber::fmt()
+        .with_target(true)
+        .with_env_filter("info")
+        .try_init()
+        .expect("Could not initialize logging");
+
+    info!("Running with config:\n{:#?}", config);
+
+    // The script can be overridden on the command line
+    let script_file = std::env::args()
+        .nth(1)
+        .unwrap_or_else(|| config.script.file.clone());
+    let script = Script::from_file(&script_file)?;
+    let maps = Arc::new(Maps::from_file(&config.maps.names_file)?);
+
+    let mut client = TestClient::new(config, maps);
+    let result = async_std::task::block_on(client.run(&script));
+
+    println!("{:<20} {:>10}  result", "step", "latency");
+    for report in client.reports() {
+        let outcome = match &report.outcome {
+            Ok(summary) => format!("ok     {}", summary),
+            Err(err) => format!("FAILED {}", err),
+        };
+        println!(
+            "{:<20} {:>7} ms  {}",
+            report.name,
+            report.latency.as_millis(),
+            outcome
+        );
+    }
+    let skipped = script.steps.len() + 2 - client.reports().len();
+    if skipped > 0 {
+        println!("{} step(s) skipped", skipped);
+    }
+
+    result
+}
@@ -0,0 +1,58 @@
+use serde::Deserialize;
+
+use api::account::mmo_account::Sex;
+use api::character::attributes::Class;
+use api::character::StatAllocation;
+
+/// The character server actions to run, in order, once logged in
+#[derive(Deserialize, Debug, Clone)]
+pub struct Script {
+    pub steps: Vec<Step>,
+}
+
+impl Script {
+    pub fn from_file(path: &str) -> Result<Self, config::ConfigError> {
+        let mut script = config::Config::default();
+        script.merge(config::File::with_name(path))?;
+        script.try_into()
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Step {
+    ListCharacters,
+    CreateCharacter {
+        name: String,
+        slot: u8,
+        #[serde(default)]
+        hair: u16,
+        #[serde(default)]
+        hair_color: u16,
+        /// Only sent by clients from 2015-10-01, defaults to novice
+        class: Option<Class>,
+        /// Only sent by clients from 2015-10-01, defaults to the account's
+        sex: Option<Sex>,
+        /// Only sent by clients before 2012-03-07
+        stats: Option<StatAllocation>,
+    },
+    SelectCharacter {
+        slot: u8,
+    },
+    /// Creates, verifies or changes the pincode, depending on what the server asks for
+    Pincode {
+        pincode: String,
+        new_pincode: Option<String>,
+    },
+}
+
+impl Step {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::ListCharacters => "list_characters",
+            Self::CreateCharacter { .. } => "create_character",
+            Self::SelectCharacter { .. } => "select_character",
+            Self::Pincode { .. } => "pincode",
+        }
+    }
+}
@@ -0,0 +1,240 @@
+//! Starts the login & character servers in-process, for integration tests.
+//!
+//! Both servers listen on ephemeral loopback ports and share a single in-memory account DB,
+//! seeded with the accounts the test asks for, as well as the logins handed over between them.
+//! The login server advertises the character server's actual address, so a client can follow
+//! the regular login -> char server flow.
+
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use async_codec::{Decode, Encode, Framed};
+use async_std::{
+    net::{TcpListener, TcpStream},
+    stream::StreamExt,
+    task::{self, JoinHandle},
+};
+
+use api::{
+    account::{
+        db::AccountDB,
+        mmo_account::{MmoAccount, Password},
+    },
+    captcha::{Captcha, CaptchaProvider},
+    character::{CharacterClientCodec, TcpServer as CharTcpServer},
+    config::ServerConfig,
+    login::LoginClientCodec,
+    map::Maps,
+    packet_version::PacketVersion,
+    shutdown::ShutdownTrigger,
+};
+use character::{config::Config as CharacterConfig, server::CharacterServer};
+use databases::account::db::InMemoryAccountDB;
+use databases::authentication::AuthenticationDB;
+use login::{agent::LoginAgent, server::LoginServer};
+
+/// Answer to every captcha the character server sends
+pub const CAPTCHA_ANSWER: &str = "kafra";
+
+/// Generates captchas with a known answer, so tests can solve them
+struct FixedCaptcha;
+
+impl CaptchaProvider for FixedCaptcha {
+    fn generate(&self) -> Captcha {
+        Captcha {
+            image: b"BM".to_vec(),
+            answer: CAPTCHA_ANSWER.to_string(),
+        }
+    }
+}
+
+/// How long `receive` waits for the next packet
+pub const RECEIVE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// An account seeded into the account DB before the servers start
+#[derive(Debug, Clone)]
+pub struct TestAccount {
+    pub username: String,
+    pub password: String,
+}
+
+impl TestAccount {
+    pub fn new(username: &str, password: &str) -> Self {
+        Self {
+            username: username.to_string(),
+            password: password.to_string(),
+        }
+    }
+}
+
+/// A server running in the background
+pub struct ServerHandle {
+    addr: SocketAddr,
+    shutdown: ShutdownTrigger,
+    task: JoinHandle<Result<(), anyhow::Error>>,
+}
+
+impl ServerHandle {
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Stops accepting connections & waits for the server to close its sessions,
+    /// whose clients are told the server closed
+    pub async fn shutdown(self) -> Result<(), anyhow::Error> {
+        self.shutdown.trigger();
+        self.task.await
+    }
+}
+
+/// The login & character servers, started with `TestServers::start`
+pub struct TestServers {
+    pub login: ServerHandle,
+    pub character: ServerHandle,
+    pub account_db: Arc<InMemoryAccountDB>,
+    pub authentication_db: Arc<AuthenticationDB>,
+    pub maps: Arc<Maps>,
+    pub packet_version: PacketVersion,
+}
+
+impl TestServers {
+    /// Starts both servers with the character server's default config,
+    /// without pincodes or captchas
+    pub async fn start(accounts: &[TestAccount]) -> Result<Self, anyhow::Error> {
+        Self::start_with(accounts, |_| {}).await
+    }
+
+    /// Starts both servers, after `configure` adjusted the character server's config
+    pub async fn start_with(
+        accounts: &[TestAccount],
+        configure: impl FnOnce(&mut CharacterConfig),
+    ) -> Result<Self, anyhow::Error> {
+        let mut config = character_config()?;
+        configure(&mut config);
+        let packet_version = config.char_server.packet_version;
+        let maps = Arc::new(Maps::from_file(&config.maps.names_file)?);
+
+        let account_db = Arc::new(InMemoryAccountDB::new(false).await?);
+        let authentication_db = Arc::new(AuthenticationDB::default());
+        for account in accounts {
+            seed_account(&account_db, account).await?;
+        }
+
+        // The character server has to be listening before the login server can advertise it
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let char_addr = listener.local_addr()?;
+        let (char_shutdown, shutdown) = ShutdownTrigger::new();
+        let char_server = CharacterServer::new()
+            .with_account_db(account_db.clone())
+            .with_authentication_db(authentication_db.clone())
+            .with_captcha_provider(Box::new(FixedCaptcha))
+            .with_shutdown(shutdown);
+        let character = ServerHandle {
+            addr: char_addr,
+            shutdown: char_shutdown,
+            task: task::spawn(char_server.serve(config.clone(), listener)),
+        };
+
+        let char_servers = vec![Arc::new(CharTcpServer::new(&ServerConfig {
+            name: config.char_server.name.clone(),
+            address: char_addr.ip().to_string(),
+            port: char_addr.port(),
+            packet_version,
+        }))];
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let login_addr = listener.local_addr()?;
+        let (login_shutdown, shutdown) = ShutdownTrigger::new();
+        let login_server = LoginServer::new(
+            LoginAgent::new(account_db.clone(), authentication_db.clone()),
+            char_servers,
+            packet_version,
+        )
+        .with_capture(config.capture.clone())
+        .with_shutdown(shutdown, config.shutdown.clone());
+        let login = ServerHandle {
+            addr: login_addr,
+            shutdown: login_shutdown,
+            task: task::spawn(async move { Ok(login_server.serve(listener).await?) }),
+        };
+
+        Ok(Self {
+            login,
+            character,
+            account_db,
+            authentication_db,
+            maps,
+            packet_version,
+        })
+    }
+
+    pub fn connect_login(&self) -> Result<Framed<TcpStream, LoginClientCodec>, anyhow::Error> {
+        let stream = connect(self.login.addr())?;
+        Ok(Framed::new(
+            stream,
+            LoginClientCodec::new(self.packet_version),
+        ))
+    }
+
+    pub fn connect_character(
+        &self,
+    ) -> Result<Framed<TcpStream, CharacterClientCodec>, anyhow::Error> {
+        let stream = connect(self.character.addr())?;
+        let codec = CharacterClientCodec::new(self.maps.clone(), self.packet_version);
+        Ok(Framed::new(stream, codec))
+    }
+
+    pub async fn shutdown(self) -> Result<(), anyhow::Error> {
+        self.login.shutdown().await?;
+        self.character.shutdown().await
+    }
+}
+
+/// The character server's own config, with paths resolved against its crate
+pub fn character_config() -> Result<CharacterConfig, anyhow::Error> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("../character");
+    let mut settings = ::config::Config::default();
+    settings.merge(::config::File::from(dir.join("config.toml")))?;
+    let mut config: CharacterConfig = settings.try_into()?;
+    config.maps.names_file = dir
+        .join(&config.maps.names_file)
+        .to_string_lossy()
+        .into_owned();
+    config.pincode.enabled = false;
+    config.captcha.enabled = false;
+    Ok(config)
+}
+
+async fn seed_account(
+    account_db: &InMemoryAccountDB,
+    account: &TestAccount,
+) -> Result<MmoAccount, anyhow::Error> {
+    let mut created = account_db.create_account().await?;
+    created.user_id = account.username.clone();
+    created.password = Password::Cleartext(account.password.clone());
+    account_db.save_account(&created).await?;
+    Ok(created)
+}
+
+/// Connects through the standard library, as async-std's own connect fails with EAFNOSUPPORT
+/// on some kernels
+pub fn connect(addr: SocketAddr) -> Result<TcpStream, std::io::Error> {
+    Ok(std::net::TcpStream::connect(addr)?.into())
+}
+
+/// Waits up to `RECEIVE_TIMEOUT` for the next packet
+pub async fn receive<C: Decode + Encode + Unpin>(
+    stream: &mut Framed<TcpStream, C>,
+) -> Result<<C as Decode>::Item, anyhow::Error>
+where
+    <C as Decode>::Error: std::fmt::Display,
+{
+    match async_std::future::timeout(RECEIVE_TIMEOUT, stream.next()).await {
+        Ok(Some(Ok(response))) => Ok(response),
+        Ok(Some(Err(err))) => Err(anyhow!("Could not decode response: {}", err)),
+        Ok(None) => Err(anyhow!("Connection closed by the server")),
+        Err(_) => Err(anyhow!("No response within {:?}", RECEIVE_TIMEOUT)),
+    }
+}
@@ -0,0 +1,124 @@
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use async_std::task;
+use futures_util::SinkExt;
+
+use api::{
+    capture::{CaptureReader, CapturedFrame, Direction, ServerKind},
+    character::{AccountInfo, Request as CharacterRequest, Response as CharacterResponse},
+    login::{LoginCredentials, Request as LoginRequest, Response as LoginResponse},
+};
+use databases::authentication::Authentication;
+use test_support::{receive, TestAccount, TestServers};
+
+const USERNAME: &str = "recorded";
+const PASSWORD: &str = "hunter22";
+
+fn capture_directory() -> PathBuf {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    std::env::temp_dir().join(format!("captures-{}-{}", std::process::id(), nanos))
+}
+
+fn read_capture(directory: &Path, server: ServerKind) -> Vec<CapturedFrame> {
+    let path = std::fs::read_dir(directory)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .find(|path| {
+            let name = path.file_name().unwrap().to_string_lossy();
+            name.starts_with(&format!("{}-", server.name()))
+        })
+        .unwrap_or_else(|| panic!("No {} capture", server.name()));
+    let reader = CaptureReader::open(path).unwrap();
+    assert_eq!(reader.header().server, server);
+    reader.map(Result::unwrap).collect()
+}
+
+fn frames(frames: &[CapturedFrame], direction: Direction) -> Vec<&CapturedFrame> {
+    frames
+        .iter()
+        .filter(|frame| frame.direction == direction)
+        .collect()
+}
+
+#[test]
+fn sessions_are_recorded_and_replayable() {
+    let directory = capture_directory();
+    task::block_on(async {
+        let servers = TestServers::start_with(&[TestAccount::new(USERNAME, PASSWORD)], |config| {
+            config.capture.enabled = true;
+            config.capture.directory = directory.to_string_lossy().into_owned();
+        })
+        .await
+        .unwrap();
+
+        let mut stream = servers.connect_login().unwrap();
+        stream
+            .send(LoginRequest::ClientLogin(LoginCredentials::ClearText {
+                client_type: 0,
+                username: USERNAME.to_string(),
+                password: PASSWORD.to_string(),
+            }))
+            .await
+            .unwrap();
+        let info = match receive(&mut stream).await.unwrap() {
+            LoginResponse::LoginSuccess(info) => info,
+            response => panic!("Login failed: {:?}", response),
+        };
+
+        let account_info = AccountInfo {
+            account_id: info.account_id,
+            authentication_code: info.authentication_code,
+            user_level: info.user_level,
+            sex: info.sex,
+        };
+        let mut stream = servers.connect_character().unwrap();
+        stream
+            .send(CharacterRequest::ConnectClient(account_info))
+            .await
+            .unwrap();
+        while !matches!(
+            receive(&mut stream).await.unwrap(),
+            CharacterResponse::PincodeInfo(_)
+        ) {}
+        stream.send(CharacterRequest::ListCharacters).await.unwrap();
+        receive(&mut stream).await.unwrap();
+
+        let login = read_capture(&directory, ServerKind::Login);
+        assert_eq!(frames(&login, Direction::Inbound).len(), 1);
+        assert_eq!(frames(&login, Direction::Outbound).len(), 1);
+
+        let character = read_capture(&directory, ServerKind::Character);
+        let requests = frames(&character, Direction::Inbound);
+        let responses = frames(&character, Direction::Outbound);
+        assert_eq!(requests.len(), 2);
+        assert_eq!(character[0].direction, Direction::Inbound);
+        // ConnectClient is answered with the bare account ID
+        assert_eq!(responses[0].data, info.account_id.to_le_bytes());
+
+        // Replaying the client's frames gets a response of the same size for each captured one,
+        // once the login server handed the captured login over again
+        servers.authentication_db.authenticate(Authentication {
+            account_info,
+            char_slots: None,
+        });
+        let mut replay = std::net::TcpStream::connect(servers.character.addr()).unwrap();
+        replay
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        for request in &requests {
+            replay.write_all(&request.data).unwrap();
+        }
+        let expected: usize = responses.iter().map(|response| response.data.len()).sum();
+        let mut received = vec![0u8; expected];
+        replay.read_exact(&mut received).unwrap();
+        assert_eq!(&received[..4], &responses[0].data[..]);
+
+        servers.shutdown().await.unwrap();
+    });
+    std::fs::remove_dir_all(&directory).unwrap();
+}
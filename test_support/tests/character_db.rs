@@ -0,0 +1,116 @@
+use std::sync::Arc;
+
+use async_std::task;
+use futures_util::future::join_all;
+
+use api::character::{
+    db::{CharacterDB, DBError},
+    Character,
+};
+use databases::character::InMemoryCharacterDB;
+
+const ACCOUNT_ID: u32 = 2_000_042;
+
+async fn character_db() -> Arc<InMemoryCharacterDB> {
+    Arc::new(InMemoryCharacterDB::new(false).await.unwrap())
+}
+
+fn character(name: &str, slot: u8) -> Character {
+    let mut character = Character::new(0, ACCOUNT_ID);
+    character.name = name.to_string().into();
+    character.slot = slot as u16;
+    character
+}
+
+async fn create_with_moves(db: &InMemoryCharacterDB, name: &str, slot: u8, moves: u16) {
+    let mut character = character(name, slot);
+    character.settings.slot_moves = moves;
+    db.create(character).await.unwrap();
+}
+
+#[test]
+fn concurrent_creations_cannot_share_a_slot() {
+    task::block_on(async {
+        let db = character_db().await;
+
+        let creations = (0..8).map(|i| {
+            let db = db.clone();
+            task::spawn(async move { db.create(character(&format!("Racer{}", i), 3)).await })
+        });
+        let results = join_all(creations).await;
+
+        assert_eq!(results.iter().filter(|result| result.is_ok()).count(), 1);
+        assert!(results
+            .iter()
+            .filter_map(|result| result.as_ref().err())
+            .all(|err| matches!(err, DBError::SlotOccupied(3))));
+        let characters = db.get_by_account_id(ACCOUNT_ID).await.unwrap();
+        assert_eq!(characters.len(), 1);
+        assert_eq!(characters[0].slot, 3);
+    })
+}
+
+#[test]
+fn concurrent_moves_cannot_spend_the_same_move() {
+    task::block_on(async {
+        let db = character_db().await;
+        create_with_moves(&db, "Mover", 0, 1).await;
+
+        // Every move starts from slot 0, so only the first one finds the character there
+        let moves = (1..=8).map(|to| {
+            let db = db.clone();
+            task::spawn(async move { db.move_slot(ACCOUNT_ID, 0, to).await })
+        });
+        let results = join_all(moves).await;
+
+        assert_eq!(
+            results
+                .iter()
+                .filter(|result| matches!(result, Ok(0)))
+                .count(),
+            1
+        );
+        let characters = db.get_by_account_id(ACCOUNT_ID).await.unwrap();
+        assert_eq!(characters[0].settings.slot_moves, 0);
+        assert_ne!(characters[0].slot, 0);
+    })
+}
+
+#[test]
+fn moves_are_spent_once_per_move() {
+    task::block_on(async {
+        let db = character_db().await;
+        create_with_moves(&db, "Mover", 0, 1).await;
+        create_with_moves(&db, "Other", 1, 1).await;
+
+        // Swapping the two only spends a move of the moved character
+        assert_eq!(db.move_slot(ACCOUNT_ID, 0, 1).await.unwrap(), 0);
+        let moved = db.get_by_slot(ACCOUNT_ID, 1).await.unwrap();
+        let swapped = db.get_by_slot(ACCOUNT_ID, 0).await.unwrap();
+        assert_eq!(moved.name.to_string(), "Mover");
+        assert_eq!(moved.settings.slot_moves, 0);
+        assert_eq!(swapped.name.to_string(), "Other");
+        assert_eq!(swapped.settings.slot_moves, 1);
+
+        assert!(matches!(
+            db.move_slot(ACCOUNT_ID, 1, 2).await,
+            Err(DBError::NoMovesLeft(1))
+        ));
+        assert!(matches!(
+            db.move_slot(ACCOUNT_ID, 5, 2).await,
+            Err(DBError::NoSuchSlot(5))
+        ));
+    })
+}
+
+#[test]
+fn moving_onto_the_same_slot_spends_nothing() {
+    task::block_on(async {
+        let db = character_db().await;
+        create_with_moves(&db, "Stayer", 2, 1).await;
+
+        assert_eq!(db.move_slot(ACCOUNT_ID, 2, 2).await.unwrap(), 1);
+        let character = db.get_by_slot(ACCOUNT_ID, 2).await.unwrap();
+        assert_eq!(character.settings.slot_moves, 1);
+    })
+}
@@ -0,0 +1,507 @@
+//! Checks the codecs against known-good packets under `fixtures/`, laid out as
+//! `<packet version>/<login|char>/<requests|responses>/<name>.hex`.
+//!
+//! A fixture holds the expected outcome of decoding it & the packet's bytes in hex, with
+//! whitespace & `#` comments ignored so `xxd -p` output can be pasted as is:
+//!
+//! ```text
+//! # 0x0187 PING
+//! packet: KeepAlive
+//! field: account_id = 2000001
+//! 87 01        # PacketType
+//! 81 84 1e 00  # AID
+//! ```
+//!
+//! `packet:` names the decoded variant & each `field:` line a value the packet carries, as a path
+//! into its debug output, with indices for tuple & list elements, e.g. `characters.0.name`.
+//! Fields the packet doesn't carry are left out. The decoded packet is then encoded again & has
+//! to reproduce the fixture's bytes, unless a `decode_only: <reason>` line says why it can't, e.g.
+//! as the encoder sends another variant. Packets which are known but not parsed yet have an
+//! `error:` line instead.
+//!
+//! The bytes come from the client's packet layouts, as rAthena reads & writes them, not from our
+//! encoders. Adding a client version takes nothing more than a directory of its packets.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::convert::TryFrom;
+use std::fmt::Debug;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use async_codec::{Decode, DecodeResult, Encode, EncodeResult};
+
+use api::{
+    account::mmo_account::Sex,
+    character::{
+        AccountInfo, CharacterClientCodec, CharacterCodec, CharacterCommand,
+        Request as CharacterRequest,
+    },
+    login::{LoginClientCodec, LoginCodec, LoginCommand},
+    map::Maps,
+    packet_table::PacketTable,
+    packet_version::PacketVersion,
+};
+
+const FIXTURES: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures");
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Server {
+    Login,
+    Character,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Direction {
+    Requests,
+    Responses,
+}
+
+enum Expected {
+    Decoded {
+        packet: String,
+        fields: Vec<(String, String)>,
+    },
+    Error(String),
+}
+
+struct Fixture {
+    path: PathBuf,
+    version: PacketVersion,
+    server: Server,
+    direction: Direction,
+    expected: Expected,
+    decode_only: bool,
+    bytes: Vec<u8>,
+}
+
+impl Fixture {
+    fn load(
+        path: &Path,
+        version: PacketVersion,
+        server: Server,
+        direction: Direction,
+    ) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|err| err.to_string())?;
+        let mut packet = None;
+        let mut fields = vec![];
+        let mut error = None;
+        let mut decode_only = false;
+        let mut hex = String::new();
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            } else if let Some(name) = line.strip_prefix("packet:") {
+                packet = Some(name.trim().to_string());
+            } else if let Some(field) = line.strip_prefix("field:") {
+                let (path, value) = field
+                    .split_once('=')
+                    .ok_or_else(|| format!("Field without a value: {}", field))?;
+                fields.push((path.trim().to_string(), value.trim().to_string()));
+            } else if let Some(message) = line.strip_prefix("error:") {
+                error = Some(message.trim().to_string());
+            } else if line.starts_with("decode_only:") {
+                decode_only = true;
+            } else {
+                hex.extend(line.chars().filter(|c| !c.is_whitespace()));
+            }
+        }
+        let expected = match (packet, error) {
+            (Some(packet), None) => Expected::Decoded { packet, fields },
+            (None, Some(error)) if fields.is_empty() => Expected::Error(error),
+            _ => return Err("Needs either a `packet:` or an `error:` line".to_string()),
+        };
+        if !hex.len().is_multiple_of(2) {
+            return Err("Odd number of hex digits".to_string());
+        }
+        let bytes = (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| format!("Invalid hex: {}", err))?;
+        Ok(Fixture {
+            path: path.to_path_buf(),
+            version,
+            server,
+            direction,
+            expected,
+            decode_only,
+            bytes,
+        })
+    }
+
+    /// The packet's opcode, if it has one
+    fn opcode(&self) -> Option<u16> {
+        match &self.expected {
+            Expected::Decoded { packet, .. } if packet == "AccountConnected" => None,
+            _ => Some(u16::from_le_bytes([
+                *self.bytes.first()?,
+                *self.bytes.get(1)?,
+            ])),
+        }
+    }
+}
+
+fn load_fixtures() -> Vec<Result<Fixture, String>> {
+    let mut fixtures = vec![];
+    for (version, version_dir) in subdirectories(Path::new(FIXTURES)) {
+        let version = match version.parse() {
+            Ok(version) => PacketVersion::new(version),
+            Err(_) => {
+                fixtures.push(Err(format!(
+                    "{}: Not a packet version",
+                    version_dir.display()
+                )));
+                continue;
+            }
+        };
+        for (server, server_dir) in subdirectories(&version_dir) {
+            let server = match server.as_str() {
+                "login" => Server::Login,
+                "char" => Server::Character,
+                _ => {
+                    fixtures.push(Err(format!("{}: Not a server", server_dir.display())));
+                    continue;
+                }
+            };
+            for (direction, direction_dir) in subdirectories(&server_dir) {
+                let direction = match direction.as_str() {
+                    "requests" => Direction::Requests,
+                    "responses" => Direction::Responses,
+                    _ => {
+                        fixtures.push(Err(format!("{}: Not a direction", direction_dir.display())));
+                        continue;
+                    }
+                };
+                for (_, path) in entries(&direction_dir) {
+                    if path.extension().is_some_and(|extension| extension == "hex") {
+                        fixtures.push(
+                            Fixture::load(&path, version, server, direction)
+                                .map_err(|err| format!("{}: {}", path.display(), err)),
+                        );
+                    }
+                }
+            }
+        }
+    }
+    fixtures
+}
+
+fn entries(dir: &Path) -> Vec<(String, PathBuf)> {
+    let mut entries: Vec<_> = std::fs::read_dir(dir)
+        .unwrap_or_else(|err| panic!("Could not read {}: {}", dir.display(), err))
+        .map(|entry| {
+            let path = entry.unwrap().path();
+            (
+                path.file_name().unwrap().to_string_lossy().into_owned(),
+                path,
+            )
+        })
+        .collect();
+    entries.sort();
+    entries
+}
+
+fn subdirectories(dir: &Path) -> Vec<(String, PathBuf)> {
+    entries(dir)
+        .into_iter()
+        .filter(|(_, path)| path.is_dir())
+        .collect()
+}
+
+/// A value in a packet's debug output, e.g. `Name { field: value }`, `Name(value)` or `[value]`
+struct DebugValue<'a> {
+    /// The value's debug output
+    text: &'a str,
+    /// The struct, variant or value, empty for lists
+    name: &'a str,
+    /// Struct fields by name, tuple & list elements without one
+    fields: Vec<(Option<&'a str>, DebugValue<'a>)>,
+}
+
+impl<'a> DebugValue<'a> {
+    fn parse(text: &'a str) -> Result<Self, String> {
+        let mut rest = text;
+        let value = Self::parse_next(text, &mut rest)?;
+        if rest.trim().is_empty() {
+            Ok(value)
+        } else {
+            Err(format!("Trailing debug output {:?}", rest))
+        }
+    }
+
+    /// Parses the value at the start of `rest`, leaving `rest` just after it
+    fn parse_next(text: &'a str, rest: &mut &'a str) -> Result<Self, String> {
+        *rest = rest.trim_start();
+        let start = text.len() - rest.len();
+        let mut fields = vec![];
+        let name = if let Some(quoted) = rest.strip_prefix('"') {
+            let mut escaped = false;
+            let end = quoted
+                .find(|c| {
+                    let quote = c == '"' && !escaped;
+                    escaped = c == '\\' && !escaped;
+                    quote
+                })
+                .ok_or("Unterminated string")?;
+            *rest = &quoted[end + 1..];
+            &text[start..text.len() - rest.len()]
+        } else {
+            let end = rest
+                .find(|c: char| c.is_whitespace() || "()[]{},:".contains(c))
+                .unwrap_or(rest.len());
+            let name = &rest[..end];
+            *rest = rest[end..].trim_start();
+            let delimiters = [('(', ')'), ('[', ']'), ('{', '}')];
+            if let Some(&(open, close)) =
+                delimiters.iter().find(|(open, _)| rest.starts_with(*open))
+            {
+                *rest = rest[1..].trim_start();
+                while !rest.starts_with(close) {
+                    let field_name = if open == '{' {
+                        let colon = rest.find(':').ok_or("Field without a value")?;
+                        let field_name = rest[..colon].trim();
+                        *rest = &rest[colon + 1..];
+                        Some(field_name)
+                    } else {
+                        None
+                    };
+                    fields.push((field_name, Self::parse_next(text, rest)?));
+                    *rest = rest.trim_start();
+                    if let Some(after) = rest.strip_prefix(',') {
+                        *rest = after.trim_start();
+                    } else if !rest.starts_with(close) {
+                        return Err(format!("Expected {:?} at {:?}", close, rest));
+                    }
+                }
+                *rest = &rest[1..];
+            }
+            name
+        };
+        Ok(Self {
+            text: text[start..text.len() - rest.len()].trim_end(),
+            name,
+            fields,
+        })
+    }
+
+    /// The value at `path`, e.g. `characters.0.name`
+    fn field(&self, path: &str) -> Option<&Self> {
+        path.split('.')
+            .try_fold(self, |value, segment| match segment.parse::<usize>() {
+                Ok(index) => value
+                    .fields
+                    .iter()
+                    .filter(|(name, _)| name.is_none())
+                    .nth(index)
+                    .map(|(_, field)| field),
+                Err(_) => value
+                    .fields
+                    .iter()
+                    .find(|(name, _)| *name == Some(segment))
+                    .map(|(_, field)| field),
+            })
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Decodes the fixture, then encodes the decoded packet again with the other side's codec
+fn check<D, E>(fixture: &Fixture, decoder: &mut D, encoder: &mut E) -> Result<(), String>
+where
+    D: Decode,
+    D::Item: Debug,
+    D::Error: Debug,
+    E: Encode<Item = D::Item>,
+    E::Error: Debug,
+{
+    let (consumed, result) = decoder.decode(&mut fixture.bytes.clone());
+    let item = match (result, &fixture.expected) {
+        (DecodeResult::Ok(item), Expected::Decoded { packet, fields }) => {
+            let decoded = format!("{:?}", item);
+            let value = DebugValue::parse(&decoded)?;
+            if value.name != packet {
+                return Err(format!("Decoded {}\n    expected {}", value.name, packet));
+            }
+            for (path, expected) in fields {
+                let field = value
+                    .field(path)
+                    .ok_or_else(|| format!("No field {} in {}", path, decoded))?;
+                if field.text != expected {
+                    return Err(format!(
+                        "Decoded {} = {}\n    expected {}",
+                        path, field.text, expected
+                    ));
+                }
+            }
+            item
+        }
+        (DecodeResult::Err(err), Expected::Error(expected)) => {
+            let error = format!("{:?}", err);
+            return if &error == expected {
+                Ok(())
+            } else {
+                Err(format!("Failed with {}\n    expected {}", error, expected))
+            };
+        }
+        (DecodeResult::Ok(item), Expected::Error(expected)) => {
+            return Err(format!("Decoded {:?}\n    expected {}", item, expected))
+        }
+        (DecodeResult::Err(err), Expected::Decoded { .. }) => {
+            return Err(format!("Could not decode: {:?}", err))
+        }
+        (DecodeResult::UnexpectedEnd, _) => {
+            return Err(format!(
+                "Decoding needs more than {} bytes",
+                fixture.bytes.len()
+            ))
+        }
+    };
+    if consumed != fixture.bytes.len() {
+        return Err(format!(
+            "Decoded {} of {} bytes",
+            consumed,
+            fixture.bytes.len()
+        ));
+    }
+    if fixture.decode_only {
+        return Ok(());
+    }
+
+    let mut buffer = vec![0u8; 4096];
+    let length = match encoder.encode(&item, &mut buffer) {
+        EncodeResult::Ok(length) => length,
+        EncodeResult::Overflow(length) => return Err(format!("Encoding needs {} bytes", length)),
+        EncodeResult::Err(err) => return Err(format!("Could not encode: {:?}", err)),
+    };
+    if buffer[..length] != fixture.bytes[..] {
+        return Err(format!(
+            "Encoded {}\n    expected {}",
+            hex(&buffer[..length]),
+            hex(&fixture.bytes)
+        ));
+    }
+    Ok(())
+}
+
+fn run(fixture: &Fixture, maps: &Arc<Maps>) -> Result<(), String> {
+    let version = fixture.version;
+    match (fixture.server, fixture.direction) {
+        (Server::Login, Direction::Requests) => check(
+            fixture,
+            &mut LoginCodec::new(version),
+            &mut LoginClientCodec::new(version),
+        ),
+        (Server::Login, Direction::Responses) => check(
+            fixture,
+            &mut LoginClientCodec::new(version),
+            &mut LoginCodec::new(version),
+        ),
+        (Server::Character, Direction::Requests) => check(
+            fixture,
+            &mut CharacterCodec::new(maps.clone(), version),
+            &mut CharacterClientCodec::new(maps.clone(), version),
+        ),
+        (Server::Character, Direction::Responses) => {
+            let mut client = CharacterClientCodec::new(maps.clone(), version);
+            // The account ID is only sent bare in reply to `ConnectClient`
+            if fixture.opcode().is_none() {
+                let info = AccountInfo {
+                    account_id: 0,
+                    authentication_code: 0,
+                    user_level: 0,
+                    sex: Sex::Female,
+                };
+                let mut buffer = vec![0u8; 64];
+                client.encode(&CharacterRequest::ConnectClient(info), &mut buffer);
+            }
+            check(
+                fixture,
+                &mut client,
+                &mut CharacterCodec::new(maps.clone(), version),
+            )
+        }
+    }
+}
+
+/// Every opcode the codecs handle, which each need a fixture
+fn handled_opcodes() -> BTreeMap<(Server, Direction), BTreeSet<u16>> {
+    let opcodes =
+        |handled: &dyn Fn(u16) -> bool| (0..=u16::MAX).filter(|opcode| handled(*opcode)).collect();
+    let login_responses = PacketTable::login_responses(PacketVersion::default());
+    let character_responses = PacketTable::character_responses(PacketVersion::default());
+    let mut handled = BTreeMap::new();
+    handled.insert(
+        (Server::Login, Direction::Requests),
+        opcodes(&|opcode| LoginCommand::try_from(opcode).is_ok()),
+    );
+    handled.insert(
+        (Server::Login, Direction::Responses),
+        opcodes(&|opcode| login_responses.length(opcode).is_some()),
+    );
+    handled.insert(
+        (Server::Character, Direction::Requests),
+        opcodes(&|opcode| CharacterCommand::try_from(opcode).is_ok()),
+    );
+    handled.insert(
+        (Server::Character, Direction::Responses),
+        opcodes(&|opcode| character_responses.length(opcode).is_some()),
+    );
+    handled
+}
+
+#[test]
+fn fixtures() {
+    let maps = Arc::new(
+        Maps::from_file(concat!(
+            env!("CARGO_MANIFEST_DIR"),
+            "/../resources/maps.yaml"
+        ))
+        .unwrap(),
+    );
+    let mut missing = handled_opcodes();
+    let mut failures = vec![];
+    let mut count = 0;
+    for fixture in load_fixtures() {
+        count += 1;
+        let fixture = match fixture {
+            Ok(fixture) => fixture,
+            Err(err) => {
+                failures.push(err);
+                continue;
+            }
+        };
+        match run(&fixture, &maps) {
+            Ok(()) => {
+                if let Some(opcode) = fixture.opcode() {
+                    let opcodes = missing
+                        .get_mut(&(fixture.server, fixture.direction))
+                        .unwrap();
+                    opcodes.remove(&opcode);
+                }
+            }
+            Err(err) => failures.push(format!("{}: {}", fixture.path.display(), err)),
+        }
+    }
+    for ((server, direction), opcodes) in missing {
+        for opcode in opcodes {
+            failures.push(format!(
+                "No {:?} {:?} fixture for {:#06x}",
+                server, direction, opcode
+            ));
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "{} failure(s) across {} fixture(s):\n{}",
+        failures.len(),
+        count,
+        failures.join("\n")
+    );
+}
@@ -0,0 +1,516 @@
+use std::time::{Duration, SystemTime};
+
+use async_codec::Framed;
+use async_std::{net::TcpStream, task};
+use futures_util::SinkExt;
+
+use api::{
+    account::{db::AccountDB, mmo_account::AccountState},
+    character::{
+        attributes::{Appearance, Class},
+        AccountInfo, CharacterClientCodec, NewCharacter, Request as CharacterRequest,
+        Response as CharacterResponse, SlotAllowance,
+    },
+    login::{
+        CharacterSelectionInfo, LoginAborted, LoginCredentials, LoginFailed,
+        Request as LoginRequest, Response as LoginResponse,
+    },
+    pincode::{self, Pincode, PincodeHash, PincodeInfo, PincodeStatus},
+};
+use test_support::{character_config, connect, receive, TestAccount, TestServers, CAPTCHA_ANSWER};
+
+const USERNAME: &str = "integration";
+const PASSWORD: &str = "hunter22";
+
+fn credentials(username: &str, password: &str) -> LoginRequest {
+    LoginRequest::ClientLogin(LoginCredentials::ClearText {
+        client_type: 0,
+        username: username.to_string(),
+        password: password.to_string(),
+    })
+}
+
+async fn login(servers: &TestServers, username: &str, password: &str) -> LoginResponse {
+    let mut stream = servers.connect_login().unwrap();
+    stream.send(credentials(username, password)).await.unwrap();
+    receive(&mut stream).await.unwrap()
+}
+
+fn new_character(name: &str, slot: u8) -> NewCharacter {
+    NewCharacter {
+        name: name.to_string().into(),
+        slot,
+        stats: None,
+        appearance: Appearance {
+            hair: 1,
+            ..Default::default()
+        },
+        class: Class::Novice,
+        sex: None,
+    }
+}
+
+#[test]
+fn login_create_and_list_characters() {
+    task::block_on(async {
+        let servers = TestServers::start(&[TestAccount::new(USERNAME, PASSWORD)])
+            .await
+            .unwrap();
+
+        let info: Box<CharacterSelectionInfo> = match login(&servers, USERNAME, PASSWORD).await {
+            LoginResponse::LoginSuccess(info) => info,
+            response => panic!("Login failed: {:?}", response),
+        };
+        assert_eq!(info.char_servers.len(), 1);
+        let char_server = info.char_servers.iter().next().unwrap();
+        assert_eq!(
+            std::net::SocketAddr::from(char_server.address()),
+            servers.character.addr()
+        );
+
+        let mut stream = servers.connect_character().unwrap();
+        stream
+            .send(CharacterRequest::ConnectClient(AccountInfo {
+                account_id: info.account_id,
+                authentication_code: info.authentication_code,
+                user_level: info.user_level,
+                sex: info.sex,
+            }))
+            .await
+            .unwrap();
+        match receive(&mut stream).await.unwrap() {
+            CharacterResponse::AccountConnected(account_id) => {
+                assert_eq!(account_id, info.account_id)
+            }
+            response => panic!("Unexpected response {:?}", response),
+        }
+        // The account's state ends with the pincode status
+        loop {
+            match receive(&mut stream).await.unwrap() {
+                CharacterResponse::CharacterInfo { characters, .. } => {
+                    assert!(characters.is_empty())
+                }
+                CharacterResponse::PincodeInfo(_) => break,
+                _ => {}
+            }
+        }
+
+        stream
+            .send(CharacterRequest::CreateCharacter(new_character(
+                "Integrator",
+                0,
+            )))
+            .await
+            .unwrap();
+        let created = match receive(&mut stream).await.unwrap() {
+            CharacterResponse::NewCharacterInfo(character) => character,
+            response => panic!("Creation failed: {:?}", response),
+        };
+        assert_eq!(created.name.to_string(), "Integrator");
+        assert_eq!(created.slot, 0);
+        // The novice's knife is shown as a dagger
+        assert_eq!(created.equipment.weapon, 1);
+
+        // Without a map server to enter, the selection is refused & the session carries on
+        stream
+            .send(CharacterRequest::SelectCharacter { slot: 0 })
+            .await
+            .unwrap();
+        match receive(&mut stream).await.unwrap() {
+            CharacterResponse::Rejected => {}
+            response => panic!("Unexpected response {:?}", response),
+        }
+
+        stream.send(CharacterRequest::ListCharacters).await.unwrap();
+        match receive(&mut stream).await.unwrap() {
+            CharacterResponse::Characters(characters) => {
+                assert_eq!(characters.len(), 1);
+                assert_eq!(characters[0].id, created.id);
+                assert_eq!(characters[0].name.to_string(), "Integrator");
+            }
+            response => panic!("Unexpected response {:?}", response),
+        }
+
+        servers.shutdown().await.unwrap();
+    })
+}
+
+#[test]
+fn login_with_wrong_password_fails() {
+    task::block_on(async {
+        let servers = TestServers::start(&[TestAccount::new(USERNAME, PASSWORD)])
+            .await
+            .unwrap();
+
+        match login(&servers, USERNAME, "wrong-password").await {
+            LoginResponse::LoginFailed(LoginFailed::IncorrectPassword) => {}
+            response => panic!("Unexpected response {:?}", response),
+        }
+
+        servers.shutdown().await.unwrap();
+    })
+}
+
+#[test]
+fn login_with_unknown_user_fails() {
+    task::block_on(async {
+        let servers = TestServers::start(&[]).await.unwrap();
+
+        match login(&servers, USERNAME, PASSWORD).await {
+            // The username isn't sent back to the client
+            LoginResponse::LoginFailed(LoginFailed::UnregisteredId(_)) => {}
+            response => panic!("Unexpected response {:?}", response),
+        }
+
+        servers.shutdown().await.unwrap();
+    })
+}
+
+#[test]
+fn unauthenticated_char_connection_is_rejected() {
+    task::block_on(async {
+        let servers = TestServers::start(&[TestAccount::new(USERNAME, PASSWORD)])
+            .await
+            .unwrap();
+
+        // Skipping ConnectClient leaves the session without an account
+        let mut stream = servers.connect_character().unwrap();
+        stream.send(CharacterRequest::ListCharacters).await.unwrap();
+        match receive(&mut stream).await.unwrap() {
+            CharacterResponse::Rejected => {}
+            response => panic!("Unexpected response {:?}", response),
+        }
+
+        stream
+            .send(CharacterRequest::CreateCharacter(new_character(
+                "Intruder", 0,
+            )))
+            .await
+            .unwrap();
+        match receive(&mut stream).await.unwrap() {
+            CharacterResponse::Rejected => {}
+            response => panic!("Unexpected response {:?}", response),
+        }
+
+        servers.shutdown().await.unwrap();
+    })
+}
+
+async fn connect_client(servers: &TestServers, account_info: AccountInfo) -> CharacterResponse {
+    let mut stream = servers.connect_character().unwrap();
+    stream
+        .send(CharacterRequest::ConnectClient(account_info))
+        .await
+        .unwrap();
+    match receive(&mut stream).await.unwrap() {
+        CharacterResponse::AccountConnected(account_id) => {
+            assert_eq!(account_id, account_info.account_id)
+        }
+        response => panic!("Unexpected response {:?}", response),
+    }
+    receive(&mut stream).await.unwrap()
+}
+
+#[test]
+fn forged_char_connection_is_rejected() {
+    task::block_on(async {
+        let servers = TestServers::start(&[TestAccount::new(USERNAME, PASSWORD)])
+            .await
+            .unwrap();
+        let info = match login(&servers, USERNAME, PASSWORD).await {
+            LoginResponse::LoginSuccess(info) => info,
+            response => panic!("Login failed: {:?}", response),
+        };
+        let account_info = AccountInfo {
+            account_id: info.account_id,
+            authentication_code: info.authentication_code,
+            user_level: info.user_level,
+            sex: info.sex,
+        };
+
+        let forgeries = [
+            AccountInfo {
+                authentication_code: info.authentication_code.wrapping_add(1),
+                ..account_info
+            },
+            AccountInfo {
+                account_id: info.account_id.wrapping_add(1),
+                ..account_info
+            },
+        ];
+        for forged in forgeries.iter() {
+            match connect_client(&servers, *forged).await {
+                CharacterResponse::Rejected => {}
+                response => panic!("Unexpected response {:?} to {:?}", response, forged),
+            }
+        }
+
+        // The forgeries didn't use up the actual login, which can only be used once
+        if let CharacterResponse::Rejected = connect_client(&servers, account_info).await {
+            panic!("Login was rejected");
+        }
+        match connect_client(&servers, account_info).await {
+            CharacterResponse::Rejected => {}
+            response => panic!("Unexpected response {:?}", response),
+        }
+
+        servers.shutdown().await.unwrap();
+    })
+}
+
+#[test]
+fn premium_slots_are_handed_over() {
+    task::block_on(async {
+        let servers = TestServers::start(&[TestAccount::new(USERNAME, PASSWORD)])
+            .await
+            .unwrap();
+        let mut account = servers
+            .account_db
+            .get_account_by_user(&USERNAME.to_string())
+            .await
+            .unwrap();
+        account.char_slots = Some(11);
+        servers.account_db.save_account(&account).await.unwrap();
+
+        let info = match login(&servers, USERNAME, PASSWORD).await {
+            LoginResponse::LoginSuccess(info) => info,
+            response => panic!("Login failed: {:?}", response),
+        };
+        let account_info = AccountInfo {
+            account_id: info.account_id,
+            authentication_code: info.authentication_code,
+            user_level: info.user_level,
+            sex: info.sex,
+        };
+        let normal = character_config().unwrap().character_slots.normal;
+        let mut stream = servers.connect_character().unwrap();
+        stream
+            .send(CharacterRequest::ConnectClient(account_info))
+            .await
+            .unwrap();
+        let slots = loop {
+            match receive(&mut stream).await.unwrap() {
+                CharacterResponse::CharacterInfo { slots, .. } => break slots,
+                CharacterResponse::Rejected => panic!("Login was rejected"),
+                _ => {}
+            }
+        };
+        assert_eq!(slots, SlotAllowance::new(normal, 11));
+
+        servers.shutdown().await.unwrap();
+    })
+}
+
+/// Logs in & connects to the character server, returning the account's pincode prompt
+async fn enter_char_server(
+    servers: &TestServers,
+) -> (Framed<TcpStream, CharacterClientCodec>, PincodeInfo) {
+    let info = match login(servers, USERNAME, PASSWORD).await {
+        LoginResponse::LoginSuccess(info) => info,
+        response => panic!("Login failed: {:?}", response),
+    };
+    let mut stream = servers.connect_character().unwrap();
+    stream
+        .send(CharacterRequest::ConnectClient(AccountInfo {
+            account_id: info.account_id,
+            authentication_code: info.authentication_code,
+            user_level: info.user_level,
+            sex: info.sex,
+        }))
+        .await
+        .unwrap();
+    loop {
+        match receive(&mut stream).await.unwrap() {
+            CharacterResponse::PincodeInfo(pincode_info) => return (stream, pincode_info),
+            CharacterResponse::Rejected => panic!("Login was rejected"),
+            _ => {}
+        }
+    }
+}
+
+async fn check_pincode(
+    stream: &mut Framed<TcpStream, CharacterClientCodec>,
+    prompt: &PincodeInfo,
+    pincode: &Pincode,
+) -> CharacterResponse {
+    stream
+        .send(CharacterRequest::CheckPincode {
+            account_id: prompt.account_id,
+            pincode: pincode::encrypt(prompt.seed, pincode).unwrap(),
+        })
+        .await
+        .unwrap();
+    receive(stream).await.unwrap()
+}
+
+#[test]
+fn banned_account_is_rejected_by_char_server() {
+    task::block_on(async {
+        let servers = TestServers::start(&[TestAccount::new(USERNAME, PASSWORD)])
+            .await
+            .unwrap();
+        let info = match login(&servers, USERNAME, PASSWORD).await {
+            LoginResponse::LoginSuccess(info) => info,
+            response => panic!("Login failed: {:?}", response),
+        };
+
+        // Banned after logging in, but before connecting to the character server
+        let mut account = servers
+            .account_db
+            .get_account_by_id(info.account_id)
+            .await
+            .unwrap();
+        account.state = AccountState::Banned(SystemTime::now() + Duration::from_secs(600));
+        servers.account_db.save_account(&account).await.unwrap();
+
+        let account_info = AccountInfo {
+            account_id: info.account_id,
+            authentication_code: info.authentication_code,
+            user_level: info.user_level,
+            sex: info.sex,
+        };
+        match connect_client(&servers, account_info).await {
+            CharacterResponse::Rejected => {}
+            response => panic!("Unexpected response {:?}", response),
+        }
+
+        servers.shutdown().await.unwrap();
+    })
+}
+
+#[test]
+fn pincode_failures_lock_the_account_across_sessions() {
+    task::block_on(async {
+        let servers = TestServers::start_with(&[TestAccount::new(USERNAME, PASSWORD)], |config| {
+            config.pincode.enabled = true;
+            config.pincode.max_failures = 3;
+        })
+        .await
+        .unwrap();
+        let mut account = servers
+            .account_db
+            .get_account_by_user(&USERNAME.to_string())
+            .await
+            .unwrap();
+        account.pincode = Some(PincodeHash::new(account.account_id, b"2580"));
+        servers.account_db.save_account(&account).await.unwrap();
+
+        let (mut stream, prompt) = enter_char_server(&servers).await;
+        assert!(matches!(prompt.status, PincodeStatus::AskForPin));
+        for _ in 0..2 {
+            match check_pincode(&mut stream, &prompt, b"1397").await {
+                CharacterResponse::PincodeInfo(info) => {
+                    assert!(matches!(info.status, PincodeStatus::Incorrect))
+                }
+                response => panic!("Unexpected response {:?}", response),
+            }
+        }
+
+        // Reconnecting doesn't grant more attempts
+        let (mut stream, prompt) = enter_char_server(&servers).await;
+        match check_pincode(&mut stream, &prompt, b"1397").await {
+            CharacterResponse::Rejected => {}
+            response => panic!("Unexpected response {:?}", response),
+        }
+        match login(&servers, USERNAME, PASSWORD).await {
+            LoginResponse::LoginFailed(LoginFailed::BannedUntil(_)) => {}
+            response => panic!("Unexpected response {:?}", response),
+        }
+
+        servers.shutdown().await.unwrap();
+    })
+}
+
+async fn create_character(
+    stream: &mut Framed<TcpStream, CharacterClientCodec>,
+    name: &str,
+    slot: u8,
+) -> CharacterResponse {
+    stream
+        .send(CharacterRequest::CreateCharacter(new_character(name, slot)))
+        .await
+        .unwrap();
+    receive(stream).await.unwrap()
+}
+
+#[test]
+fn each_solved_captcha_lets_one_creation_through() {
+    task::block_on(async {
+        let servers = TestServers::start_with(&[TestAccount::new(USERNAME, PASSWORD)], |config| {
+            config.captcha.enabled = true;
+            config.captcha.max_creations_per_ip = 1;
+        })
+        .await
+        .unwrap();
+        let (mut stream, prompt) = enter_char_server(&servers).await;
+
+        match create_character(&mut stream, "First", 0).await {
+            CharacterResponse::NewCharacterInfo(_) => {}
+            response => panic!("Unexpected response {:?}", response),
+        }
+        // The address is suspicious from now on
+        match create_character(&mut stream, "Second", 1).await {
+            CharacterResponse::CaptchaImage(_) => {}
+            response => panic!("Unexpected response {:?}", response),
+        }
+        stream
+            .send(CharacterRequest::CheckCaptcha {
+                account_id: prompt.account_id,
+                answer: CAPTCHA_ANSWER.to_string(),
+            })
+            .await
+            .unwrap();
+        match receive(&mut stream).await.unwrap() {
+            CharacterResponse::CaptchaResult { solved: true } => {}
+            response => panic!("Unexpected response {:?}", response),
+        }
+        match create_character(&mut stream, "Second", 1).await {
+            CharacterResponse::NewCharacterInfo(_) => {}
+            response => panic!("Unexpected response {:?}", response),
+        }
+        // The solved captcha was spent on the previous creation
+        match create_character(&mut stream, "Third", 2).await {
+            CharacterResponse::CaptchaImage(_) => {}
+            response => panic!("Unexpected response {:?}", response),
+        }
+
+        servers.shutdown().await.unwrap();
+    })
+}
+
+#[test]
+fn shutdown_notifies_connected_clients() {
+    task::block_on(async {
+        let servers = TestServers::start(&[TestAccount::new(USERNAME, PASSWORD)])
+            .await
+            .unwrap();
+
+        // A response makes sure both sessions are running before the shutdown
+        let mut login_stream = servers.connect_login().unwrap();
+        login_stream
+            .send(credentials(USERNAME, "wrong-password"))
+            .await
+            .unwrap();
+        receive(&mut login_stream).await.unwrap();
+        let mut char_stream = servers.connect_character().unwrap();
+        char_stream
+            .send(CharacterRequest::ListCharacters)
+            .await
+            .unwrap();
+        receive(&mut char_stream).await.unwrap();
+
+        let login_addr = servers.login.addr();
+        servers.shutdown().await.unwrap();
+
+        match receive(&mut login_stream).await.unwrap() {
+            LoginResponse::LoginAborted(LoginAborted::ServerClosed) => {}
+            response => panic!("Unexpected response {:?}", response),
+        }
+        match receive(&mut char_stream).await.unwrap() {
+            CharacterResponse::Aborted(LoginAborted::ServerClosed) => {}
+            response => panic!("Unexpected response {:?}", response),
+        }
+        assert!(receive(&mut char_stream).await.is_err());
+        assert!(connect(login_addr).is_err());
+    })
+}
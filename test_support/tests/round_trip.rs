@@ -0,0 +1,544 @@
+//! Encodes generated packets with one side's codec & decodes them with the other's,
+//! so layout regressions show up as a value that doesn't survive the round trip
+
+use std::convert::TryFrom;
+use std::fmt::Debug;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use async_codec::{Decode, DecodeResult, Encode, EncodeResult};
+use proptest::prelude::*;
+
+use api::{
+    account::mmo_account::Sex,
+    character::{
+        attributes::{Appearance, Class},
+        AccountInfo, Character, CharacterClientCodec, CharacterCodec, CharacterFrame,
+        CharacterServer, NewCharacter, Request as CharacterRequest, Response as CharacterResponse,
+        StatAllocation, TcpServer, CHARACTERS_PER_PAGE, MAX_CHARACTERS_PER_ACCOUNT,
+    },
+    config::ServerConfig,
+    login::{
+        CharacterSelectionInfo, LoginClientCodec, LoginCodec, LoginCredentials, LoginFailed,
+        Request as LoginRequest, Response as LoginResponse,
+    },
+    map::Maps,
+    packet_table::{PacketLength, PacketTable},
+    packet_version::PacketVersion,
+    pincode::{PincodeInfo, PincodeStatus},
+};
+
+fn maps() -> Arc<Maps> {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/../resources/maps.yaml");
+    Arc::new(Maps::from_file(path).unwrap())
+}
+
+fn map_count(maps: &Maps) -> u16 {
+    (1..).find(|id| maps.name(*id).is_none()).unwrap() - 1
+}
+
+/// One client version per era of packet layouts, with the length of a character in its
+/// character list, as sent by rAthena's `char_mmo_char_tobuf`
+const CHARACTER_LENGTHS: [(u32, usize); 9] = [
+    (20090000, 112),
+    (20100413, 112),
+    // Last map & delete date
+    (20100803, 132),
+    // Robe, slot change & rename count
+    (20111025, 144),
+    (20130522, 144),
+    // Sex & body style
+    (20141022, 147),
+    (20151001, 147),
+    // 64-bit experience
+    (20170830, 155),
+    (20180620, 155),
+];
+
+fn version() -> impl Strategy<Value = PacketVersion> {
+    let versions = CHARACTER_LENGTHS
+        .iter()
+        .map(|(version, _)| PacketVersion::new(*version))
+        .collect::<Vec<_>>();
+    prop::sample::select(versions)
+}
+
+fn character_length(version: PacketVersion) -> usize {
+    CHARACTER_LENGTHS
+        .iter()
+        .find(|(date, _)| PacketVersion::new(*date) == version)
+        .map(|(_, length)| *length)
+        .unwrap()
+}
+
+fn sex() -> impl Strategy<Value = Sex> {
+    prop_oneof![Just(Sex::Female), Just(Sex::Male), Just(Sex::Server)]
+}
+
+fn class() -> impl Strategy<Value = Class> {
+    prop_oneof![0u16..=30, 4001u16..=4244]
+        .prop_filter_map("Not a class", |class| Class::try_from(class).ok())
+}
+
+fn name() -> impl Strategy<Value = String> {
+    "[A-Za-z0-9 ]{1,23}"
+}
+
+/// Characters the frame of `version` can carry, i.e. with every other field at its default
+fn character(version: PacketVersion, map_count: u16) -> impl Strategy<Value = Character> {
+    // Older clients only receive 32-bit experience, capped at `i32::MAX`
+    let max_exp = if version.has_64bit_exp() {
+        u64::MAX
+    } else {
+        i32::MAX as u64
+    };
+    let identity = (
+        any::<u32>(),
+        0..MAX_CHARACTERS_PER_ACCOUNT as u16,
+        name(),
+        class(),
+    );
+    let experience = (any::<[u16; 4]>(), 0..=max_exp, 0..=max_exp, any::<u32>());
+    let stats = (any::<[u8; 6]>(), any::<[u32; 2]>(), any::<[u16; 2]>());
+    let appearance = any::<[u16; 4]>();
+    let equipment = (any::<[u16; 5]>(), any::<u32>(), any::<u16>());
+    // Only options which neither get masked nor hide the weapon
+    let status = (0u32..0x20, any::<[u32; 2]>(), any::<u32>(), 1..=map_count);
+    let extra = (sex(), any::<bool>());
+    (
+        identity, experience, stats, appearance, equipment, status, extra,
+    )
+        .prop_map(
+            move |(
+                (id, slot, name, class),
+                ([base_level, job_level, status_points, skill_points], base_exp, job_exp, zeny),
+                ([str, agi, vit, int, dex, luk], [hp, max_hp], [sp, max_sp]),
+                [hair, hair_color, clothes_color, body],
+                ([weapon, shield, head_top, head_mid, head_bottom], robe, slot_moves),
+                (option, [karma, manner], delete_date, map_id),
+                (sex, renamed),
+            )| {
+                let mut character = Character::new(id, 0);
+                character.slot = slot;
+                character.name = name.into();
+                character.class = class;
+                character.experience.base_level = base_level;
+                character.experience.job_level = job_level;
+                character.experience.base_exp = base_exp;
+                character.experience.job_exp = job_exp;
+                character.experience.status_points = status_points;
+                character.experience.skill_points = skill_points;
+                character.currency.zeny = zeny;
+                character.stats.str = str;
+                character.stats.agi = agi;
+                character.stats.vit = vit;
+                character.stats.int = int;
+                character.stats.dex = dex;
+                character.stats.luk = luk;
+                character.stats.hp = hp;
+                character.stats.max_hp = max_hp;
+                character.stats.sp = sp;
+                character.stats.max_sp = max_sp;
+                character.appearance.hair = hair;
+                character.appearance.hair_color = hair_color;
+                character.appearance.clothes_color = clothes_color;
+                character.equipment.weapon = weapon;
+                character.equipment.shield = shield;
+                character.equipment.head_top = head_top;
+                character.equipment.head_mid = head_mid;
+                character.equipment.head_bottom = head_bottom;
+                character.status.option = option;
+                character.status.karma = Some(karma);
+                character.status.manner = Some(manner);
+                character.location.last_location.map_id = map_id;
+                character.settings.rename = renamed as u16;
+                if version.has_delete_date() && delete_date > 0 {
+                    let delete_date = Duration::from_secs(delete_date as u64);
+                    character.status.delete_date = Some(SystemTime::UNIX_EPOCH + delete_date);
+                }
+                if version.has_robe() {
+                    character.equipment.robe = robe;
+                }
+                if version.has_slot_change() {
+                    character.settings.slot_moves = slot_moves;
+                }
+                if version.has_character_sex() {
+                    character.sex = sex;
+                }
+                if version.has_body_style() {
+                    character.appearance.body = body;
+                }
+                character
+            },
+        )
+}
+
+fn account_info() -> impl Strategy<Value = AccountInfo> {
+    (any::<[u32; 3]>(), sex()).prop_map(|([account_id, authentication_code, user_level], sex)| {
+        AccountInfo {
+            account_id,
+            authentication_code,
+            user_level,
+            sex,
+        }
+    })
+}
+
+/// New characters as sent by a client of `version`
+fn new_character(version: PacketVersion) -> impl Strategy<Value = NewCharacter> {
+    let stats = any::<[u8; 6]>().prop_map(move |[str, agi, vit, int, dex, luk]| {
+        if version.has_creation_without_stats() {
+            None
+        } else {
+            Some(StatAllocation {
+                str,
+                agi,
+                vit,
+                int,
+                dex,
+                luk,
+            })
+        }
+    });
+    let class_and_sex = (class(), sex()).prop_map(move |(class, sex)| {
+        if version.has_creation_with_class() {
+            (class, Some(sex))
+        } else {
+            (Class::Novice, None)
+        }
+    });
+    (
+        name(),
+        0..MAX_CHARACTERS_PER_ACCOUNT as u8,
+        stats,
+        any::<[u16; 2]>(),
+        class_and_sex,
+    )
+        .prop_map(
+            |(name, slot, stats, [hair, hair_color], (class, sex))| NewCharacter {
+                name: name.into(),
+                slot,
+                stats,
+                appearance: Appearance {
+                    hair,
+                    hair_color,
+                    ..Default::default()
+                },
+                class,
+                sex,
+            },
+        )
+}
+
+fn character_selection_info(
+    version: PacketVersion,
+) -> impl Strategy<Value = CharacterSelectionInfo> {
+    let server = ("[a-z]{1,19}", any::<u16>()).prop_map(|(name, port)| {
+        TcpServer::new(&ServerConfig {
+            name,
+            address: "127.0.0.1".to_string(),
+            port,
+            packet_version: PacketVersion::default(),
+        })
+        .info()
+    });
+    (
+        any::<[u32; 3]>(),
+        sex(),
+        any::<[u8; 16]>(),
+        prop::collection::vec(server, 0..=5),
+    )
+        .prop_map(
+            move |([account_id, authentication_code, user_level], sex, web_auth_token, servers)| {
+                let mut info = CharacterSelectionInfo {
+                    account_id,
+                    authentication_code,
+                    user_level,
+                    sex,
+                    // Only sent to clients with the extended server list
+                    web_auth_token: [0; 16],
+                    char_servers: Default::default(),
+                };
+                if version.has_extended_server_list() {
+                    info.web_auth_token = web_auth_token;
+                }
+                for server in servers {
+                    info.char_servers.try_push(server).unwrap();
+                }
+                info
+            },
+        )
+}
+
+/// Failures with the details the packet doesn't carry left at their defaults
+fn login_failed() -> impl Strategy<Value = LoginFailed> {
+    prop_oneof![0u32..=15, 99u32..=104].prop_map(|code| LoginFailed::from_error_code(code).unwrap())
+}
+
+fn pincode_info() -> impl Strategy<Value = PincodeInfo> {
+    (any::<u32>(), 0u16..=8, any::<u32>()).prop_map(|(seed, status, account_id)| PincodeInfo {
+        seed,
+        status: PincodeStatus::try_from(status).unwrap(),
+        account_id,
+    })
+}
+
+/// Encodes `item`, then decodes it again, checking the decoder consumes exactly the encoded frame
+fn round_trip<E, D>(encoder: &mut E, decoder: &mut D, item: &E::Item) -> (Vec<u8>, D::Item)
+where
+    E: Encode,
+    E::Error: Debug,
+    D: Decode,
+    D::Error: Debug,
+{
+    let mut buffer = vec![0u8; 4096];
+    let length = match encoder.encode(item, &mut buffer) {
+        EncodeResult::Ok(length) => length,
+        EncodeResult::Overflow(length) => panic!("Encoding needs {} bytes", length),
+        EncodeResult::Err(err) => panic!("Could not encode: {:?}", err),
+    };
+    buffer.truncate(length);
+    let (consumed, decoded) = decoder.decode(&mut buffer.clone());
+    let decoded = match decoded {
+        DecodeResult::Ok(decoded) => decoded,
+        DecodeResult::UnexpectedEnd => panic!("Decoding needs more than {} bytes", length),
+        DecodeResult::Err(err) => panic!("Could not decode: {:?}", err),
+    };
+    assert_eq!(consumed, length, "Decoded frame length");
+    (buffer, decoded)
+}
+
+fn fixed_length(table: &PacketTable, opcode: u16) -> usize {
+    match table.length(opcode) {
+        Some(PacketLength::Fixed(length)) => length,
+        length => panic!("Packet {:#06x} has length {:?}", opcode, length),
+    }
+}
+
+fn declared_length(frame: &[u8]) -> usize {
+    u16::from_le_bytes([frame[2], frame[3]]) as usize
+}
+
+/// A client codec past the bare account ID the server answers `ConnectClient` with
+fn connected_client(maps: &Arc<Maps>, version: PacketVersion) -> CharacterClientCodec {
+    let mut client = CharacterClientCodec::new(maps.clone(), version);
+    let mut server = CharacterCodec::new(maps.clone(), version);
+    let info = AccountInfo {
+        account_id: 2000000,
+        authentication_code: 0,
+        user_level: 0,
+        sex: Sex::Female,
+    };
+    round_trip(
+        &mut client,
+        &mut server,
+        &CharacterRequest::ConnectClient(info),
+    );
+    round_trip(
+        &mut server,
+        &mut client,
+        &CharacterResponse::AccountConnected(info.account_id),
+    );
+    client
+}
+
+fn character_strategy() -> impl Strategy<Value = (PacketVersion, Character)> {
+    let map_count = map_count(&maps());
+    // Older frames don't carry the last map, so it can't come back
+    version()
+        .prop_filter("Without last map", PacketVersion::has_last_map)
+        .prop_flat_map(move |version| (Just(version), character(version, map_count)))
+}
+
+fn characters_strategy() -> impl Strategy<Value = (PacketVersion, Vec<Character>)> {
+    let map_count = map_count(&maps());
+    version()
+        .prop_filter("Without pages", PacketVersion::has_character_pages)
+        .prop_flat_map(move |version| {
+            let characters =
+                prop::collection::vec(character(version, map_count), 0..=CHARACTERS_PER_PAGE);
+            (Just(version), characters)
+        })
+}
+
+proptest! {
+    #[test]
+    fn new_character_info_round_trips((version, character) in character_strategy()) {
+        let maps = maps();
+        let mut server = CharacterCodec::new(maps.clone(), version);
+        let mut client = connected_client(&maps, version);
+        let response = CharacterResponse::NewCharacterInfo(character);
+        let (frame, decoded) = round_trip(&mut server, &mut client, &response);
+
+        prop_assert_eq!(frame.len(), 2 + character_length(version));
+        prop_assert_eq!(&decoded, &response);
+    }
+
+    #[test]
+    fn character_pages_round_trip((version, characters) in characters_strategy()) {
+        let maps = maps();
+        let mut server = CharacterCodec::new(maps.clone(), version);
+        let mut client = connected_client(&maps, version);
+        let count = characters.len();
+        let response = CharacterResponse::Characters(characters);
+        let (frame, decoded) = round_trip(&mut server, &mut client, &response);
+
+        prop_assert_eq!(frame.len(), 4 + count * character_length(version));
+        prop_assert_eq!(declared_length(&frame), frame.len());
+        prop_assert_eq!(&decoded, &response);
+    }
+
+    #[test]
+    fn account_info_round_trips(version in version(), info in account_info()) {
+        let maps = maps();
+        let mut client = CharacterClientCodec::new(maps.clone(), version);
+        let mut server = CharacterCodec::new(maps, version);
+        let request = CharacterRequest::ConnectClient(info);
+        let (frame, decoded) = round_trip(&mut client, &mut server, &request);
+
+        prop_assert_eq!(frame.len(), 2 + AccountInfo::size(version));
+        prop_assert_eq!(frame.len(), fixed_length(&PacketTable::character(version), AccountInfo::OPCODE));
+        prop_assert_eq!(&decoded, &request);
+    }
+
+    #[test]
+    fn new_character_round_trips(
+        (version, character) in version().prop_flat_map(|version| (Just(version), new_character(version)))
+    ) {
+        let maps = maps();
+        let mut client = CharacterClientCodec::new(maps.clone(), version);
+        let mut server = CharacterCodec::new(maps, version);
+        let request = CharacterRequest::CreateCharacter(character);
+        let (frame, decoded) = round_trip(&mut client, &mut server, &request);
+
+        let opcode = request.command_code(version);
+        prop_assert_eq!(frame.len(), fixed_length(&PacketTable::character(version), opcode));
+        prop_assert_eq!(&decoded, &request);
+    }
+
+    #[test]
+    fn character_selection_info_round_trips(
+        (version, info) in version().prop_flat_map(|version| (Just(version), character_selection_info(version)))
+    ) {
+        let mut server = LoginCodec::new(version);
+        let mut client = LoginClientCodec::new(version);
+        let server_count = info.char_servers.len();
+        let response = LoginResponse::LoginSuccess(Box::new(info));
+        let (frame, decoded) = round_trip(&mut server, &mut client, &response);
+
+        let (header_length, server_length) = if version.has_extended_server_list() {
+            (64, 160)
+        } else {
+            (47, 32)
+        };
+        prop_assert_eq!(frame.len(), header_length + server_count * server_length);
+        prop_assert_eq!(declared_length(&frame), frame.len());
+        // A buffer too short for the servers reports the size the whole frame needs
+        let overflow = server.encode(&response, &mut vec![0u8; frame.len() - 1]);
+        prop_assert!(matches!(overflow, EncodeResult::Overflow(length) if length == frame.len()));
+        prop_assert_eq!(&decoded, &response);
+    }
+
+    #[test]
+    fn login_failed_round_trips(version in version(), failure in login_failed()) {
+        let mut server = LoginCodec::new(version);
+        let mut client = LoginClientCodec::new(version);
+        let response = LoginResponse::LoginFailed(failure);
+        let (frame, decoded) = round_trip(&mut server, &mut client, &response);
+
+        let opcode = response.command_code(version);
+        prop_assert_eq!(frame.len(), fixed_length(&PacketTable::login_responses(version), opcode));
+        prop_assert_eq!(&decoded, &response);
+    }
+
+    #[test]
+    fn pincode_info_round_trips(
+        version in version().prop_filter("Without pincodes", |version| {
+            PacketTable::character_responses(*version).length(0x8b9).is_some()
+        }),
+        info in pincode_info(),
+    ) {
+        let maps = maps();
+        let mut server = CharacterCodec::new(maps.clone(), version);
+        let mut client = connected_client(&maps, version);
+        let response = CharacterResponse::PincodeInfo(info);
+        let (frame, decoded) = round_trip(&mut server, &mut client, &response);
+
+        let opcode = response.command_code().unwrap();
+        prop_assert_eq!(frame.len(), fixed_length(&PacketTable::character_responses(version), opcode));
+        prop_assert_eq!(&decoded, &response);
+    }
+}
+
+#[test]
+fn character_frames_match_the_client_length() {
+    for (date, length) in CHARACTER_LENGTHS.iter() {
+        let version = PacketVersion::new(*date);
+        assert_eq!(CharacterFrame::size(version), *length, "{:?}", version);
+    }
+}
+
+#[test]
+fn oversized_variable_length_responses_never_fit() {
+    let mut server = CharacterCodec::new(maps(), PacketVersion::new(20180620));
+    let response = CharacterResponse::CaptchaImage(vec![0; usize::from(u16::MAX)]);
+    let mut buffer = vec![0u8; 2 * usize::from(u16::MAX)];
+    // The 2-byte length can't hold the frame, even though the buffer could
+    let overflow = server.encode(&response, &mut buffer);
+    assert!(matches!(
+        overflow,
+        EncodeResult::Overflow(length) if length > usize::from(u16::MAX)
+    ));
+}
+
+/// Encodes `item` after a packet missing from the decoder's table, which the decoder drops without
+/// giving up on the connection
+fn decode_after_unknown_packet<E, D>(encoder: &mut E, decoder: &mut D, item: &E::Item) -> D::Item
+where
+    E: Encode,
+    D: Decode,
+    D::Error: Debug,
+{
+    let mut unknown = vec![0xff, 0xff, 1, 2, 3];
+    let (consumed, result) = decoder.decode(&mut unknown);
+    assert_eq!(consumed, unknown.len(), "Dropped bytes");
+    assert!(matches!(result, DecodeResult::UnexpectedEnd));
+
+    let mut buffer = vec![0u8; 4096];
+    let length = match encoder.encode(item, &mut buffer) {
+        EncodeResult::Ok(length) => length,
+        _ => panic!("Could not encode"),
+    };
+    match decoder.decode(&mut buffer[..length]) {
+        (consumed, DecodeResult::Ok(decoded)) if consumed == length => decoded,
+        (_, DecodeResult::Err(err)) => panic!("Could not decode: {:?}", err),
+        _ => panic!("Could not decode the whole frame"),
+    }
+}
+
+#[test]
+fn unknown_packets_are_dropped() {
+    let version = PacketVersion::new(20180620);
+    let login = LoginRequest::ClientLogin(LoginCredentials::ClearText {
+        client_type: 0,
+        username: "user".to_string(),
+        password: "password".to_string(),
+    });
+    let decoded = decode_after_unknown_packet(
+        &mut LoginClientCodec::new(version),
+        &mut LoginCodec::new(version),
+        &login,
+    );
+    assert!(matches!(
+        decoded,
+        LoginRequest::ClientLogin(LoginCredentials::ClearText { username, .. }) if username == "user"
+    ));
+
+    let maps = maps();
+    let decoded = decode_after_unknown_packet(
+        &mut CharacterClientCodec::new(maps.clone(), version),
+        &mut CharacterCodec::new(maps, version),
+        &CharacterRequest::ListCharacters,
+    );
+    assert_eq!(decoded, CharacterRequest::ListCharacters);
+}